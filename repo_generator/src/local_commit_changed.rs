@@ -1,4 +1,4 @@
-use test_repo::{RemoteRepo, TestRepoWithRemote};
+use test_repo::TestRepoWithRemote;
 
 use indoc::indoc;
 use ubr::commands::create;
@@ -42,8 +42,7 @@ pub fn init_repo(local_repo: TestRepoWithRemote) {
     let git_repo = ubr::git::GitRepo::open(local_repo.path()).unwrap();
     create::execute(
         create::Options {
-            force: false,
-            commit_ref: None,
+            ..Default::default()
         },
         git_repo,
     )
@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use clap::{command, Parser, Subcommand};
+use clap::{Parser, Subcommand};
 
 mod local_commit_changed;
 mod rebase_with_conflict;
@@ -42,14 +42,13 @@ pub fn init_repo(remote_repo: &RemoteRepo, local_repo: TestRepoWithRemote) {
     let git_repo = GitRepo::open(local_repo.path()).unwrap();
     create::execute(
         create::Options {
-            force: false,
-            commit_ref: None,
+            ..Default::default()
         },
         git_repo,
     )
     .unwrap();
 
-    let local_repo = local_repo
+    let _local_repo = local_repo
         .create_file(
             "File1",
             indoc! {"
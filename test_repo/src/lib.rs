@@ -27,12 +27,16 @@ impl RemoteRepo {
         RemoteRepo { dir: Box::new(dir) }
     }
 
-    pub fn clone_repo(&self) -> TestRepoWithRemote {
+    pub fn path(&self) -> &Path {
+        (*self.dir).as_ref()
+    }
+
+    pub fn clone_repo(&self) -> TestRepoWithRemote<'_> {
         let local_repo_dir = tempdir().unwrap();
         self.clone_repo_into(local_repo_dir)
     }
 
-    pub fn clone_repo_into<P>(&self, dir: P) -> TestRepoWithRemote
+    pub fn clone_repo_into<P>(&self, dir: P) -> TestRepoWithRemote<'_>
     where
         P: AsRef<Path> + 'static,
     {
@@ -111,6 +115,23 @@ impl<'a> TestRepoWithRemote<'a> {
         self
     }
 
+    #[allow(dead_code)]
+    pub fn checkout_new_branch(self, branch: &str) -> Self {
+        let current_dir = (*self.local_repo_dir).as_ref();
+        assert!(Command::new("git")
+            .current_dir(current_dir)
+            .arg("checkout")
+            .arg("-b")
+            .arg(branch)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap()
+            .success());
+
+        self
+    }
+
     #[allow(dead_code)]
     pub fn run_command(&self) -> Command {
         let current_dir = (*self.local_repo_dir).as_ref();
@@ -253,7 +274,7 @@ impl<'a> TestRepoWithRemote<'a> {
             .current_dir(current_dir)
             .arg("commit")
             .arg("-a")
-            .arg(&format!("--fixup={}", fixup_commit))
+            .arg(format!("--fixup={}", fixup_commit))
             .stdout(Stdio::null())
             .stderr(Stdio::null())
             .status()
@@ -274,6 +295,24 @@ impl<'a> TestRepoWithRemote<'a> {
         self
     }
 
+    /// Rebase the current branch onto `upstream`, squashing the `fixup!` commits.
+    pub fn rebase_autosquash(self, upstream: &str) -> Self {
+        assert!(self
+            .run_command()
+            .arg("-c")
+            .arg("sequence.editor=:")
+            .arg("rebase")
+            .arg("-i")
+            .arg("--autosquash")
+            .arg(upstream)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap()
+            .success());
+        self
+    }
+
     pub fn push(self) -> Self {
         let current_dir = (*self.local_repo_dir).as_ref();
 
@@ -288,6 +327,22 @@ impl<'a> TestRepoWithRemote<'a> {
         self
     }
 
+    #[allow(dead_code)]
+    pub fn force_push(self) -> Self {
+        let current_dir = (*self.local_repo_dir).as_ref();
+
+        assert!(Command::new("git")
+            .current_dir(current_dir)
+            .arg("push")
+            .arg("--force")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .unwrap()
+            .success());
+        self
+    }
+
     #[allow(dead_code)]
     pub fn pull_rebase(self) -> Self {
         let current_dir = (*self.local_repo_dir).as_ref();
@@ -405,7 +460,23 @@ impl<'a> TestRepoWithRemote<'a> {
         String::from_utf8(out.stdout).expect("Output is not valid UTF-8")
     }
 
-    pub fn find_commit(&self, ancestors: u32) -> Commit {
+    #[allow(dead_code)]
+    pub fn find_note_in_ref(&self, notes_ref: &str, rev: &str) -> String {
+        let current_dir = (*self.local_repo_dir).as_ref();
+
+        let out = Command::new("git")
+            .current_dir(current_dir)
+            .arg("notes")
+            .arg("--ref")
+            .arg(notes_ref)
+            .arg("show")
+            .arg(rev)
+            .output()
+            .unwrap();
+        String::from_utf8(out.stdout).expect("Output is not valid UTF-8")
+    }
+
+    pub fn find_commit(&self, ancestors: u32) -> Commit<'_> {
         let head = self.local_repo.head().unwrap();
 
         let mut commit = head.peel_to_commit().unwrap();
@@ -418,7 +489,7 @@ impl<'a> TestRepoWithRemote<'a> {
     }
 
     #[allow(dead_code)]
-    pub fn find_commit_by_reference(&self, reference: &str) -> Commit {
+    pub fn find_commit_by_reference(&self, reference: &str) -> Commit<'_> {
         self.local_repo
             .find_reference(reference)
             .unwrap()
@@ -0,0 +1,131 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::git::{lock, GitRepo};
+
+#[derive(clap::Parser, Default)]
+pub struct Options {
+    /// Also remove the state of a sync or rebase that is still in progress, and the lock
+    /// of a process that is still running
+    #[arg(short, long)]
+    pub force: bool,
+}
+
+/// What `clean` removed, or would remove in a dry run.
+#[derive(Debug, Default)]
+pub struct CleanResult {
+    pub removed: Vec<PathBuf>,
+    /// Files that are still in use and were kept because `--force` wasn't given
+    pub kept: Vec<PathBuf>,
+    pub dry_run: bool,
+}
+
+impl Display for CleanResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let verb = if self.dry_run {
+            "would remove"
+        } else {
+            "removed"
+        };
+        for path in &self.removed {
+            writeln!(f, "{} {}", verb, path.display())?;
+        }
+        for path in &self.kept {
+            writeln!(
+                f,
+                "kept {}, it is still in use, run with '--force' to remove it anyway",
+                path.display()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Remove the files that a `ubr` process that was killed can leave behind in `.ubr/`: the
+/// state of a stopped sync or rebase, the lock and partially written state files. The
+/// state of a sync or rebase is only stale when git has no merge in progress any more, and
+/// the lock when its process is no longer running. `clean` holds the lock itself while it
+/// removes the files.
+pub fn execute(options: Options, repo: GitRepo) -> anyhow::Result<()> {
+    print!("{}", plan(options, &repo)?);
+    Ok(())
+}
+
+/// Clean `.ubr/`, returning what was removed instead of printing it.
+pub fn plan(options: Options, repo: &GitRepo) -> anyhow::Result<CleanResult> {
+    let mut result = CleanResult {
+        dry_run: repo.is_dry_run(),
+        ..Default::default()
+    };
+    let state_dir = repo.path().join(".ubr");
+    let lock_dir = repo.main_worktree_path().join(".ubr");
+    let lock_file = repo.main_worktree_path().join(lock::LOCK_FILE);
+    if lock_file.exists() {
+        if !lock::is_stale(&lock_file) && !options.force {
+            anyhow::bail!(
+                "another ubr process is running, run with '--force' to remove its lock anyway"
+            );
+        }
+        if !result.dry_run {
+            std::fs::remove_file(&lock_file)
+                .with_context(|| format!("Removing {}", lock_file.display()))?;
+        }
+        result.removed.push(lock_file);
+    }
+    let _lock = if result.dry_run {
+        None
+    } else {
+        Some(repo.lock()?)
+    };
+
+    let mut files = Vec::new();
+    for name in ["SYNC_MERGE_HEAD", "REBASE"] {
+        files.push((state_dir.join(name), repo.operation_in_progress()));
+    }
+    files.extend(tmp_files(&state_dir)?.into_iter().map(|path| (path, false)));
+    if lock_dir != state_dir {
+        files.extend(tmp_files(&lock_dir)?.into_iter().map(|path| (path, false)));
+    }
+
+    for (path, active) in files {
+        if !path.exists() {
+            continue;
+        }
+        if active && !options.force {
+            result.kept.push(path);
+            continue;
+        }
+        if !result.dry_run {
+            std::fs::remove_file(&path).with_context(|| format!("Removing {}", path.display()))?;
+        }
+        result.removed.push(path);
+    }
+    if !result.dry_run {
+        // Only succeeds if there is nothing else (like a config file) left in the directory
+        let _ = std::fs::remove_dir(&state_dir);
+        let _ = std::fs::remove_dir(&lock_dir);
+    }
+    Ok(result)
+}
+
+/// The `*.tmp` files in `dir`, left by a state that was never completely written.
+fn tmp_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("Reading {}", dir.display())),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.extension().is_some_and(|ext| ext == "tmp") && path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
@@ -0,0 +1,96 @@
+//! Colored terminal output for the commands. [`GitRepo`](crate::git::GitRepo) never
+//! colors anything, so output produced by the library stays plain.
+use std::{
+    io::IsTerminal,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ColorChoice {
+    /// Color when stdout is a terminal and `NO_COLOR` is not set to a non-empty value
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+    Bold,
+}
+
+impl Color {
+    fn code(&self) -> &'static str {
+        match self {
+            Color::Red => "31",
+            Color::Green => "32",
+            Color::Yellow => "33",
+            Color::Bold => "1",
+        }
+    }
+}
+
+static CHOICE: AtomicU8 = AtomicU8::new(ColorChoice::Never as u8);
+
+/// Set how the commands color their output. Defaults to [`ColorChoice::Never`] so that
+/// library users get plain output unless they opt in.
+pub fn set_choice(choice: ColorChoice) {
+    CHOICE.store(choice as u8, Ordering::Relaxed);
+}
+
+fn enabled() -> bool {
+    match CHOICE.load(Ordering::Relaxed) {
+        c if c == ColorChoice::Always as u8 => true,
+        c if c == ColorChoice::Auto as u8 => {
+            colors_allowed(std::env::var_os("NO_COLOR")) && std::io::stdout().is_terminal()
+        }
+        _ => false,
+    }
+}
+
+/// Only a non-empty `NO_COLOR` turns colors off, see <https://no-color.org>.
+fn colors_allowed(value: Option<std::ffi::OsString>) -> bool {
+    value.is_none_or(|v| v.is_empty())
+}
+
+/// `text` wrapped in the escape codes for `color`, if colors are enabled.
+pub fn paint(color: Color, text: &str) -> String {
+    paint_if(enabled(), color, text)
+}
+
+fn paint_if(enabled: bool, color: Color, text: &str) -> String {
+    if enabled {
+        format!("\x1b[{}m{}\x1b[0m", color.code(), text)
+    } else {
+        text.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{colors_allowed, paint_if, Color};
+
+    #[test]
+    fn paint_when_enabled() {
+        assert_eq!(
+            paint_if(true, Color::Green, "+added"),
+            "\x1b[32m+added\x1b[0m"
+        );
+    }
+
+    #[test]
+    fn plain_when_disabled() {
+        assert_eq!(paint_if(false, Color::Red, "-removed"), "-removed");
+    }
+
+    #[test]
+    fn empty_no_color_allows_colors() {
+        assert!(colors_allowed(None));
+        assert!(colors_allowed(Some("".into())));
+        assert!(!colors_allowed(Some("1".into())));
+    }
+}
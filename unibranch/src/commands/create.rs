@@ -1,29 +1,579 @@
-use crate::git::{local_commit::MainCommit, GitRepo};
+use std::{fmt::Display, io::BufRead};
 
-#[derive(clap::Parser, Default)]
+use anyhow::Context;
+
+use crate::{
+    commands::{
+        color::{paint, Color},
+        sync, verbosity,
+    },
+    forge,
+    git::{
+        hooks::Hook,
+        local_commit::{CommitMetadata, MainCommit},
+        trailers, GitRepo,
+    },
+    progress::{NoProgress, ProgressReporter},
+};
+
+#[derive(clap::Parser, Default, Clone)]
 pub struct Options {
     #[arg(short, long)]
     pub force: bool,
-    pub commit_ref: Option<String>,
+    /// Commits to create PRs for, in the given order. Defaults to HEAD. A range like
+    /// `base..HEAD` creates a PR for every commit in it, each stacked on the one before.
+    pub commit_refs: Vec<String>,
+    /// Number of branches to push in parallel
+    #[arg(short, long, default_value_t = 1)]
+    pub jobs: usize,
+    /// Stack the PR on the PR of this commit instead of the base branch
+    #[arg(long, value_name = "REF")]
+    pub base_of: Option<String>,
+    /// Edit the PR title and body in the editor instead of using the commit message
+    #[arg(short, long)]
+    pub edit: bool,
+    /// Message of the PR's commit, instead of the message of the local commit
+    #[arg(short, long)]
+    pub message: Option<String>,
+    /// Create PRs for commits without any changes
+    #[arg(long)]
+    pub allow_empty: bool,
+    /// Don't run the hooks in .ubr/hooks/
+    #[arg(long)]
+    pub no_verify: bool,
+    /// Trailer to add to the message of the PR's commit, can be given multiple times
+    #[arg(long = "trailer", value_name = "KEY=VALUE", value_parser = parse_trailer)]
+    pub trailers: Vec<(String, String)>,
+    /// Author of the PR's commit, instead of the author of the local commit
+    #[arg(long, value_name = "NAME <EMAIL>", value_parser = parse_author)]
+    pub author: Option<(String, String)>,
+    /// Read the commits to create PRs for from stdin, one per line, e.g. from
+    /// 'git log --format=%H'. Every commit gets a PR of its own, also when creating the PR
+    /// for another one fails
+    #[arg(long, conflicts_with = "commit_refs")]
+    pub stdin: bool,
+}
+
+/// A `--trailer` like `Fixes=#12`. The key is put in the message as it is, so it can't
+/// contain whitespace or a ':'.
+fn parse_trailer(value: &str) -> anyhow::Result<(String, String)> {
+    let (key, value) = value.split_once('=').context("expected KEY=VALUE")?;
+    if key.is_empty() || key.contains(|c: char| c.is_whitespace() || c == ':') {
+        anyhow::bail!("invalid trailer key '{}'", key);
+    }
+    if value.trim().is_empty() || value.contains('\n') {
+        anyhow::bail!("the value of '{}' must be a single non-empty line", key);
+    }
+    Ok((key.to_string(), value.trim().to_string()))
+}
+
+/// An `--author` like `Some One <one@example.com>`, as the name and the email.
+fn parse_author(value: &str) -> anyhow::Result<(String, String)> {
+    let (name, email) = value
+        .trim()
+        .strip_suffix('>')
+        .and_then(|value| value.split_once('<'))
+        .context("expected 'NAME <EMAIL>'")?;
+    let (name, email) = (name.trim(), email.trim());
+    if name.is_empty() || email.is_empty() || email.contains(['<', '>']) {
+        anyhow::bail!("expected 'NAME <EMAIL>'");
+    }
+    // Reject what git can't put in a signature, e.g. newlines
+    git2::Signature::now(name, email)?;
+    Ok((name.to_string(), email.to_string()))
+}
+
+/// What `create` did, for library users that want to present it themselves.
+#[derive(Debug, Default)]
+pub struct CreateResult {
+    pub branches: Vec<CreatedBranch>,
+    /// Number of commits in the stack, if several PRs were created and it is deeper than
+    /// `max-stack-depth`
+    pub deep_stack: Option<usize>,
+}
+
+#[derive(Debug)]
+pub struct CreatedBranch {
+    /// The local commit the PR was created for
+    pub commit: git2::Oid,
+    pub meta_data: CommitMetadata<'static>,
+    /// Link to the pushed branch, if the remote is hosted on a known forge
+    pub url: Option<String>,
+    /// The commit was already tracked and its branch in sync, so nothing was pushed
+    pub up_to_date: bool,
+    /// The metadata of the commit before `--force`, kept until the new one is saved
+    replaced: Option<CommitMetadata<'static>>,
+    /// The branch wasn't on the remote before, so it can be deleted if creating fails
+    new_branch: bool,
+}
+
+impl CreatedBranch {
+    fn finish(&self, git_repo: &GitRepo) -> anyhow::Result<()> {
+        git_repo.finish_create(
+            self.commit,
+            &self.meta_data,
+            self.replaced.as_ref(),
+            self.new_branch,
+        )
+    }
+}
+
+impl Display for CreateResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for branch in &self.branches {
+            if branch.up_to_date {
+                writeln!(f, "{}: up to date", branch.meta_data.remote_branch_name)?;
+                continue;
+            }
+            match &branch.url {
+                Some(url) => writeln!(f, "{}: {}", branch.meta_data.remote_branch_name, url)?,
+                None => writeln!(
+                    f,
+                    "{}: {}",
+                    branch.meta_data.remote_branch_name, branch.meta_data.remote_commit
+                )?,
+            }
+        }
+        Ok(())
+    }
 }
 
 pub fn execute(config: Options, git_repo: GitRepo) -> anyhow::Result<()> {
-    let rev = config.commit_ref.unwrap_or_else(|| "HEAD".to_string());
-    let commit = git_repo.find_unpushed_commit(&rev)?;
+    execute_with_progress(config, git_repo, &NoProgress)
+}
 
-    let untracked_commit = match commit {
-        MainCommit::UnTracked(commit) => commit,
-        MainCommit::Tracked(tracked) => {
-            if !config.force {
-                anyhow::bail!("Commit is already tracked");
+pub fn execute_with_progress(
+    config: Options,
+    git_repo: GitRepo,
+    progress: &dyn ProgressReporter,
+) -> anyhow::Result<()> {
+    if config.stdin {
+        return execute_from_reader(config, std::io::stdin().lock(), &git_repo, progress);
+    }
+    let result = plan(config, &git_repo, progress)?;
+    if !git_repo.is_dry_run() && !verbosity::is_quiet() {
+        print!("{}", result);
+    }
+    Ok(())
+}
+
+/// Create a PR for every commit read from `reader`, see [`read_commit_refs`]. A failure is
+/// reported and the next commit tried, and the result is an error if any of them failed.
+pub fn execute_from_reader(
+    config: Options,
+    reader: impl BufRead,
+    git_repo: &GitRepo,
+    progress: &dyn ProgressReporter,
+) -> anyhow::Result<()> {
+    let commit_refs = read_commit_refs(reader)?;
+    let total = commit_refs.len();
+    let mut failed = 0;
+    for commit_ref in commit_refs {
+        let options = Options {
+            commit_refs: vec![commit_ref.clone()],
+            stdin: false,
+            ..config.clone()
+        };
+        match plan(options, git_repo, progress) {
+            Ok(result) => {
+                if !git_repo.is_dry_run() && !verbosity::is_quiet() {
+                    print!("{}", result);
+                }
+            }
+            Err(err) => {
+                failed += 1;
+                eprintln!("{}: {:#}", commit_ref, err);
+            }
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("Creating PRs failed for {} of {} commits", failed, total);
+    }
+    Ok(())
+}
+
+/// The commit refs in `reader`, one per line. Blank lines and lines starting with `#` are
+/// skipped.
+pub fn read_commit_refs(reader: impl BufRead) -> anyhow::Result<Vec<String>> {
+    let mut commit_refs = Vec::new();
+    for line in reader.lines() {
+        let line = line.context("Reading the commits")?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        commit_refs.push(line.to_string());
+    }
+    Ok(commit_refs)
+}
+
+/// Create and push the PR branches, returning what was created instead of printing it.
+pub fn plan(
+    config: Options,
+    git_repo: &GitRepo,
+    progress: &dyn ProgressReporter,
+) -> anyhow::Result<CreateResult> {
+    let _lock = git_repo.lock()?;
+    // The commits to create PRs for, and whether to stack them on the one before
+    let mut revs = Vec::new();
+    for rev in &config.commit_refs {
+        if !rev.contains("..") {
+            revs.push((rev.clone(), false));
+            continue;
+        }
+        let commits = git_repo.commits_in_range(rev)?;
+        if commits.is_empty() {
+            anyhow::bail!("There are no commits in '{}'", rev);
+        }
+        revs.extend(
+            commits
+                .iter()
+                .enumerate()
+                .map(|(i, id)| (id.to_string(), i > 0)),
+        );
+    }
+    if let Some(message) = &config.message {
+        if message.trim().is_empty() {
+            anyhow::bail!("The message given with '--message' is empty");
+        }
+        if revs.len() > 1 {
+            anyhow::bail!("'--message' can only be used when creating a single PR");
+        }
+    }
+    let base_of = match &config.base_of {
+        Some(base_ref) => match git_repo.find_unpushed_commit(base_ref)? {
+            MainCommit::Tracked(tracked) => Some(tracked.meta_data().clone().into_owned()),
+            MainCommit::UnTracked(_) => {
+                anyhow::bail!("'{}' is not tracked, create a PR for it first", base_ref)
             }
+        },
+        None => None,
+    };
+    if revs.len() <= 1 {
+        let rev = revs.first().map_or("HEAD", |(r, _)| r.as_str());
+        let created = create_single(git_repo, rev, base_of.as_ref(), &config, progress)?;
+        if created.up_to_date {
+            return Ok(CreateResult {
+                branches: vec![created],
+                deep_stack: None,
+            });
+        }
+        if !config.no_verify {
+            git_repo.run_hook(Hook::PrePush, &created.meta_data)?;
+        }
+        progress.on_push(&created.meta_data.remote_branch_name);
+        git_repo.remote().push(&created.meta_data)?;
+        created.finish(git_repo)?;
+        git_repo.set_upstream(&created.meta_data.remote_branch_name)?;
+        if !config.no_verify {
+            git_repo.run_hook(Hook::PostCreate, &created.meta_data)?;
+        }
+        let mut branches = vec![created];
+        open_prs(git_repo, &mut branches)?;
+        return Ok(CreateResult {
+            branches,
+            deep_stack: None,
+        });
+    }
 
-            tracked.untrack()?
+    let deep_stack = sync::warn_on_deep_stack(git_repo)?;
+    // Track all the commits before pushing, so that the pushes can run in parallel.
+    // Commits tracked before a failure are still pushed, and every branch is saved as soon
+    // as it has been pushed, so that a failing push doesn't leave the others untracked.
+    let mut created = Vec::new();
+    let result = revs.iter().try_for_each(|(rev, stacked)| {
+        let base = if *stacked {
+            created.last().map(|c: &CreatedBranch| c.meta_data.clone())
+        } else {
+            base_of.clone()
+        };
+        created.push(
+            create_single(git_repo, rev, base.as_ref(), &config, progress)
+                .with_context(|| format!("Creating PR for '{}'", rev))?,
+        );
+        Ok::<_, anyhow::Error>(())
+    });
+    let pushes = created
+        .iter()
+        .filter(|c| !c.up_to_date)
+        .map(|c| c.meta_data.clone())
+        .collect::<Vec<_>>();
+    if !config.no_verify {
+        for meta_data in &pushes {
+            git_repo.run_hook(Hook::PrePush, meta_data)?;
+        }
+    }
+    for meta_data in &pushes {
+        progress.on_push(&meta_data.remote_branch_name);
+    }
+    let mut saved = Vec::new();
+    let pushed = git_repo
+        .remote()
+        .push_all(&pushes, config.jobs, |meta_data| {
+            let branch = created
+                .iter()
+                .find(|c| c.meta_data.remote_branch_name == meta_data.remote_branch_name)
+                .expect("Pushed branches are created");
+            branch.finish(git_repo)?;
+            saved.push(meta_data.clone().into_owned());
+            Ok(())
+        });
+    for meta_data in &saved {
+        git_repo.set_upstream(&meta_data.remote_branch_name)?;
+        if !config.no_verify {
+            git_repo.run_hook(Hook::PostCreate, meta_data)?;
         }
+    }
+    pushed?;
+    result?;
+    open_prs(git_repo, &mut created)?;
+    Ok(CreateResult {
+        branches: created,
+        deep_stack,
+    })
+}
+
+/// Open PRs for the pushed branches if the forge is supported, see [`forge::client`].
+fn open_prs(git_repo: &GitRepo, branches: &mut [CreatedBranch]) -> anyhow::Result<()> {
+    let Some(client) = forge::client(git_repo) else {
+        return Ok(());
     };
+    if git_repo.is_dry_run() {
+        return Ok(());
+    }
+    for branch in branches.iter_mut().filter(|b| !b.up_to_date) {
+        let MainCommit::Tracked(tracked) =
+            git_repo.find_unpushed_commit(&branch.commit.to_string())?
+        else {
+            continue;
+        };
+        let commit = tracked.as_commit();
+        let message = commit
+            .message()
+            .context("Commit message is not valid UTF-8")?;
+        let (title, body) = message.split_once('\n').unwrap_or((message, ""));
+        let title = branch.meta_data.pr_title.as_deref().unwrap_or(title);
+        let body = match &branch.meta_data.pr_body {
+            Some(body) => body.clone(),
+            None => forge::pr_body(git_repo, body),
+        };
+        let base = branch
+            .meta_data
+            .base_branch_name
+            .as_deref()
+            .unwrap_or(git_repo.base_branch_name());
+        let number = client
+            .create_pr(
+                &branch.meta_data.remote_branch_name,
+                base,
+                title,
+                body.trim(),
+            )
+            .with_context(|| format!("Opening PR for {}", branch.meta_data.remote_branch_name))?;
+        branch.meta_data.pr_number = Some(number);
+        git_repo.save_meta_data(commit, &branch.meta_data)?;
+    }
+    Ok(())
+}
 
-    let tracked_commit = untracked_commit.track()?;
-    git_repo.remote().push(tracked_commit.meta_data())?;
+fn create_single(
+    git_repo: &GitRepo,
+    rev: &str,
+    base: Option<&CommitMetadata>,
+    options: &Options,
+    progress: &dyn ProgressReporter,
+) -> anyhow::Result<CreatedBranch> {
+    let commit = git_repo.find_unpushed_commit(rev)?;
+    if commit.is_ignored() {
+        anyhow::bail!(
+            "{} is ignored, run 'ubr unignore' to create a PR for it",
+            commit.id()
+        );
+    }
+    if !options.allow_empty && commit.is_empty()? {
+        anyhow::bail!(
+            "{} is empty, there is nothing to create a PR for. Use '--allow-empty' to create it anyway",
+            commit.id()
+        );
+    }
+    if let (MainCommit::Tracked(tracked), false) = (&commit, options.force) {
+        // Creating the same PR again is fine, as long as there is nothing new to push
+        if tracked.is_in_sync()? {
+            return Ok(CreatedBranch {
+                commit: tracked.as_commit().id(),
+                meta_data: tracked.meta_data().clone().into_owned(),
+                url: tracked.remote_url(),
+                up_to_date: true,
+                replaced: None,
+                new_branch: false,
+            });
+        }
+        anyhow::bail!("Commit is already tracked and has changed, run 'ubr sync' or use '--force'");
+    }
+    let pr_message = if options.edit {
+        Some(edit_pr_message(git_repo, commit.as_commit())?)
+    } else {
+        None
+    };
 
+    // Like 'git commit -m', the message ends with a newline
+    let mut message = options
+        .message
+        .as_ref()
+        .map(|message| format!("{}\n", message.trim_end()));
+    if !options.trailers.is_empty() {
+        let commit_message = match &message {
+            Some(message) => message.as_str(),
+            None => commit
+                .as_commit()
+                .message()
+                .context("Commit message is not valid UTF-8")?,
+        };
+        message = Some(trailers::add(
+            commit_message,
+            options
+                .trailers
+                .iter()
+                .map(|(k, v)| (k.as_str(), v.as_str())),
+        ));
+    }
+    // The note of a tracked commit is only replaced once the new branch has been pushed
+    let (untracked_commit, replaced) = match commit {
+        MainCommit::UnTracked(commit) => (commit, None),
+        MainCommit::Tracked(tracked) => {
+            let replaced = tracked.meta_data().clone().into_owned();
+            (tracked.into_untracked(), Some(replaced))
+        }
+    };
+    progress.on_commit_start(untracked_commit.as_commit());
+
+    let author = match &options.author {
+        Some((name, email)) => Some(git2::Signature::new(
+            name,
+            email,
+            &untracked_commit.as_commit().author().when(),
+        )?),
+        None => None,
+    };
+    let tracked_commit = untracked_commit.track(base, message.as_deref(), author.as_ref())?;
+    let mut meta_data = tracked_commit.meta_data().clone().into_owned();
+    if let Some((title, body)) = pr_message {
+        meta_data.pr_title = Some(title);
+        meta_data.pr_body = Some(body);
+    }
+    if git_repo.is_dry_run() {
+        print_diff(&git_repo.diff_from_base(meta_data.remote_commit)?)?;
+    }
+    let new_branch = git_repo
+        .find_head_of_remote_branch(&meta_data.remote_branch_name)
+        .is_none();
+    Ok(CreatedBranch {
+        commit: tracked_commit.as_commit().id(),
+        url: tracked_commit.remote_url(),
+        meta_data,
+        up_to_date: false,
+        replaced,
+        new_branch,
+    })
+}
+
+/// Let the user edit the PR title (first line) and body in their editor, starting from
+/// the commit message.
+fn edit_pr_message(git_repo: &GitRepo, commit: &git2::Commit) -> anyhow::Result<(String, String)> {
+    let message = commit
+        .message()
+        .context("Commit message is not valid UTF-8")?;
+    let path = git_repo.path().join(".ubr/PR_EDITMSG");
+    std::fs::create_dir_all(path.parent().expect("Has a parent"))?;
+    std::fs::write(
+        &path,
+        format!(
+            "{}\n# Edit the title (first line) and body of the PR for {}.\n\
+             # Lines starting with '#' are ignored, an empty title aborts.\n",
+            message.trim_end(),
+            commit.id()
+        ),
+    )?;
+
+    let editor = git_repo.editor();
+    let status = std::process::Command::new("sh")
+        .current_dir(git_repo.path())
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg(&editor)
+        .arg(&path)
+        .status()
+        .with_context(|| format!("Running editor '{}'", editor))?;
+    let edited = std::fs::read_to_string(&path);
+    let _ = std::fs::remove_file(&path);
+    if !status.success() {
+        anyhow::bail!("Editor '{}' failed ({})", editor, status);
+    }
+
+    let edited = edited?
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let edited = edited.trim();
+    let (title, body) = edited.split_once('\n').unwrap_or((edited, ""));
+    if title.trim().is_empty() {
+        anyhow::bail!("Aborting due to empty PR title");
+    }
+    Ok((title.trim().to_string(), body.trim().to_string()))
+}
+
+fn print_diff(diff: &git2::Diff) -> anyhow::Result<()> {
+    diff.print(git2::DiffFormat::Patch, |_, _, line| {
+        let content = String::from_utf8_lossy(line.content());
+        match line.origin() {
+            '+' => print!("{}", paint(Color::Green, &format!("+{}", content))),
+            '-' => print!("{}", paint(Color::Red, &format!("-{}", content))),
+            ' ' => print!(" {}", content),
+            'F' => print!("{}", paint(Color::Bold, &content)),
+            _ => print!("{}", content),
+        }
+        true
+    })?;
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::{parse_author, parse_trailer, read_commit_refs};
+
+    #[test]
+    fn test_parse_trailer() {
+        assert_eq!(
+            parse_trailer("Reviewed-by=Some One <one@example.com>").unwrap(),
+            (
+                "Reviewed-by".to_string(),
+                "Some One <one@example.com>".to_string()
+            )
+        );
+        assert!(parse_trailer("Reviewed by=Some One").is_err());
+        assert!(parse_trailer("Fixes").is_err());
+        assert!(parse_trailer("Fixes=").is_err());
+    }
+
+    #[test]
+    fn test_read_commit_refs() {
+        let input = Cursor::new("# From git log\nHEAD\n\n  HEAD~2 \r\n#HEAD~1\n");
+        assert_eq!(read_commit_refs(input).unwrap(), vec!["HEAD", "HEAD~2"]);
+    }
+
+    #[test]
+    fn test_parse_author() {
+        assert_eq!(
+            parse_author(" Some One <one@example.com> ").unwrap(),
+            ("Some One".to_string(), "one@example.com".to_string())
+        );
+        assert!(parse_author("Some One").is_err());
+        assert!(parse_author("<one@example.com>").is_err());
+        assert!(parse_author("Some One <>").is_err());
+        assert!(parse_author("Some One <one@example.com").is_err());
+        assert!(parse_author("Some One <one<@example.com>").is_err());
+    }
+}
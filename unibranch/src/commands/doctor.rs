@@ -0,0 +1,151 @@
+use std::path::Path;
+
+use crate::{
+    commands::{
+        color::{paint, Color},
+        verbosity,
+    },
+    config::Config,
+    git::GitRepo,
+};
+
+/// The outcome of one of the checks made by `ubr doctor`.
+#[derive(Debug)]
+pub struct Check {
+    pub name: String,
+    pub passed: bool,
+    /// Whether the other commands will fail as long as the check does
+    pub critical: bool,
+    /// How to fix the problem when the check fails
+    pub hint: String,
+}
+
+impl Check {
+    fn new(name: impl Into<String>, passed: bool, critical: bool, hint: impl Into<String>) -> Self {
+        Check {
+            name: name.into(),
+            passed,
+            critical,
+            hint: hint.into(),
+        }
+    }
+}
+
+/// Check the repository at `path` for the problems that commonly make the other commands
+/// fail. Nothing is changed, not even the git config that the other commands set up. The
+/// only file written is the probe for whether `.ubr/` is writable, and it is removed again.
+pub fn checks<P>(path: P, config: &Config) -> anyhow::Result<Vec<Check>>
+where
+    P: AsRef<Path>,
+{
+    let repo = GitRepo::open_repository(path.as_ref())?;
+    let path = repo.workdir().unwrap_or(path.as_ref());
+    let mut checks = Vec::new();
+
+    let branch = repo
+        .head()
+        .ok()
+        .filter(|head| head.is_branch())
+        .and_then(|head| head.shorthand().map(str::to_string));
+    checks.push(Check::new(
+        "HEAD is a branch",
+        branch.is_some(),
+        true,
+        "check out the branch with the stack, e.g. 'git switch master'",
+    ));
+
+    // Without a branch there is no base to look for, the check above already failed
+    if let Some(base) = config.base.clone().or(branch) {
+        let exists = |branch: &str| {
+            repo.refname_to_id(&format!("refs/remotes/{}/{}", config.remote, branch))
+                .is_ok()
+        };
+        checks.push(Check::new(
+            format!("base branch '{}/{}' exists", config.remote, base),
+            exists(&base) || exists("HEAD"),
+            true,
+            format!(
+                "fetch '{}', push the branch or set 'base' in .ubr/config.toml",
+                config.remote
+            ),
+        ));
+    }
+
+    let git_config = repo.config()?;
+    // Without them the notes and commits get the committer of the local commit
+    for key in ["user.name", "user.email"] {
+        checks.push(Check::new(
+            format!("'{}' is set", key),
+            git_config.get_string(key).is_ok(),
+            false,
+            format!("run 'git config --global {} <value>'", key),
+        ));
+    }
+    checks.push(Check::new(
+        "'notes.rewriteRef' is set",
+        git_config.get_string("notes.rewriteRef").is_ok(),
+        false,
+        "it is set by the first 'ubr create' or 'ubr sync', or run \
+         'git config notes.rewriteRef \"refs/notes/*\"'",
+    ));
+
+    let ubr_dir = path.join(".ubr");
+    let writable_dir = if ubr_dir.exists() { &ubr_dir } else { path };
+    checks.push(Check::new(
+        ".ubr/ is writable",
+        is_writable(writable_dir),
+        true,
+        format!("make {} writable", writable_dir.display()),
+    ));
+    checks.push(Check::new(
+        "no sync in progress",
+        !ubr_dir.join("SYNC_MERGE_HEAD").exists(),
+        false,
+        "run 'ubr sync --continue' or 'ubr sync --abort'",
+    ));
+
+    Ok(checks)
+}
+
+/// Whether a file can be created in `dir`. The permission bits don't tell, e.g. for root
+/// or on a read-only file system.
+fn is_writable(dir: &Path) -> bool {
+    let probe = dir.join(format!(".ubr-doctor-{}", std::process::id()));
+    let created = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .is_ok();
+    if created {
+        let _ = std::fs::remove_file(&probe);
+    }
+    created
+}
+
+/// Print the result of every check, failing if any critical check failed.
+pub fn execute<P>(path: P, config: &Config) -> anyhow::Result<()>
+where
+    P: AsRef<Path>,
+{
+    let checks = checks(path, config)?;
+    for check in &checks {
+        let status = match (check.passed, check.critical) {
+            (true, _) if verbosity::is_quiet() => continue,
+            (true, _) => paint(Color::Green, "ok  "),
+            (false, true) => paint(Color::Red, "FAIL"),
+            (false, false) => paint(Color::Yellow, "warn"),
+        };
+        println!("{} {}", status, check.name);
+        if !check.passed {
+            println!("     {}", check.hint);
+        }
+    }
+    let failed = checks
+        .iter()
+        .filter(|check| check.critical && !check.passed)
+        .count();
+    if failed > 0 {
+        anyhow::bail!("{} critical check(s) failed", failed);
+    }
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use std::fmt::Display;
+
+use crate::{
+    forge,
+    git::{
+        local_commit::{CommitMetadata, MainCommit},
+        GitRepo,
+    },
+};
+
+#[derive(clap::Parser)]
+pub struct Options {
+    /// The tracked commit to stop tracking
+    #[arg(default_value = "HEAD")]
+    pub commit_ref: String,
+}
+
+/// What `drop` did, or would do in a dry run.
+#[derive(Debug)]
+pub struct DropResult {
+    pub commit: git2::Oid,
+    /// The metadata the commit was tracked with
+    pub meta_data: CommitMetadata<'static>,
+    /// The name of the remote the branch is deleted from
+    pub remote_name: String,
+}
+
+impl Display for DropResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(number) = self.meta_data.pr_number {
+            writeln!(f, "would close PR #{}", number)?;
+        }
+        writeln!(
+            f,
+            "would delete remote branch '{}/{}'",
+            self.remote_name, self.meta_data.remote_branch_name
+        )?;
+        writeln!(f, "would remove note on commit {}", self.commit)
+    }
+}
+
+/// Stop tracking a commit: its PR is closed, its remote branch deleted and its note
+/// removed. The local commit is kept. With `--dry-run` only what would be done is printed.
+pub fn execute(options: Options, repo: GitRepo) -> anyhow::Result<()> {
+    let result = plan(options, &repo)?;
+    if repo.is_dry_run() {
+        print!("{}", result);
+    }
+    Ok(())
+}
+
+/// Drop the commit, returning its metadata instead of printing anything.
+pub fn plan(options: Options, repo: &GitRepo) -> anyhow::Result<DropResult> {
+    let _lock = repo.lock()?;
+    let tracked = match repo.find_unpushed_commit(&options.commit_ref)? {
+        MainCommit::Tracked(tracked) => tracked,
+        MainCommit::UnTracked(_) => anyhow::bail!("'{}' is not tracked", options.commit_ref),
+    };
+    let result = DropResult {
+        commit: tracked.as_commit().id(),
+        meta_data: tracked.meta_data().clone().into_owned(),
+        remote_name: repo.remote_name().to_string(),
+    };
+    if repo.is_dry_run() {
+        return Ok(result);
+    }
+    if let (Some(number), Some(client)) = (result.meta_data.pr_number, forge::client(repo)) {
+        client.close_pr(number)?;
+    }
+    repo.remote()
+        .delete_branch(&result.meta_data.remote_branch_name)?;
+    repo.remove_meta_data(tracked.as_commit())?;
+    Ok(result)
+}
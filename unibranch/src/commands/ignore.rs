@@ -0,0 +1,39 @@
+use crate::git::{local_commit::MainCommit, GitRepo};
+
+#[derive(clap::Parser)]
+pub struct Options {
+    /// The untracked commit to mark
+    #[arg(default_value = "HEAD")]
+    pub commit_ref: String,
+}
+
+/// Mark an untracked commit, e.g. a local-only tweak, so that `publish`, `create` and
+/// `status` leave it out. It is still moved along with the rest of the stack.
+pub fn execute(options: Options, repo: GitRepo) -> anyhow::Result<()> {
+    set_ignored(&options.commit_ref, true, &repo)
+}
+
+/// Remove the mark added by [`execute`].
+pub fn execute_unignore(options: Options, repo: GitRepo) -> anyhow::Result<()> {
+    set_ignored(&options.commit_ref, false, &repo)
+}
+
+pub fn set_ignored(commit_ref: &str, ignored: bool, repo: &GitRepo) -> anyhow::Result<()> {
+    let _lock = repo.lock()?;
+    let commit = match repo.find_unpushed_commit(commit_ref)? {
+        MainCommit::Tracked(tracked) => anyhow::bail!(
+            "'{}' is tracked by '{}'",
+            commit_ref,
+            tracked.meta_data().remote_branch_name
+        ),
+        MainCommit::UnTracked(untracked) => untracked,
+    };
+    repo.set_ignored(commit.as_commit(), ignored)?;
+    if !ignored && !repo.is_dry_run() && commit.is_ignored() {
+        anyhow::bail!(
+            "'{}' has an 'Ubr-Ignore' trailer, remove it from the commit message",
+            commit_ref
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use anyhow::Context;
+
+use crate::git::{local_commit::MainCommit, GitRepo};
+
+#[derive(clap::Parser)]
+pub struct Options {
+    /// The tracked commit whose remote branch to show
+    #[arg(default_value = "HEAD")]
+    pub commit_ref: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    pub id: git2::Oid,
+    pub summary: String,
+    pub author: String,
+}
+
+pub fn execute(options: Options, repo: GitRepo) -> anyhow::Result<()> {
+    for entry in remote_log(&options.commit_ref, &repo)? {
+        let id = entry.id.to_string();
+        println!("{} {} ({})", &id[..7], entry.summary, entry.author);
+    }
+    Ok(())
+}
+
+/// The commits on the remote branch of the tracked commit at `commit_ref`, newest first,
+/// down to the commit the PR is based on.
+pub fn remote_log(commit_ref: &str, repo: &GitRepo) -> anyhow::Result<Vec<LogEntry>> {
+    let tracked_commit = match repo.find_unpushed_commit(commit_ref)? {
+        MainCommit::Tracked(tracked) => tracked,
+        MainCommit::UnTracked(_) => anyhow::bail!("'{}' is not tracked", commit_ref),
+    };
+    let branch_name = &tracked_commit.meta_data().remote_branch_name;
+    let head = repo
+        .find_head_of_remote_branch(branch_name)
+        .with_context(|| {
+            format!(
+                "Remote branch '{}/{}' not found",
+                repo.remote_name(),
+                branch_name
+            )
+        })?;
+    let base = tracked_commit.pr_base_commit()?;
+
+    let mut entries = Vec::new();
+    let mut commit = head;
+    while commit.id() != base.id() && !repo.is_ancestor(commit.id(), base.id())? {
+        entries.push(LogEntry {
+            id: commit.id(),
+            summary: commit.summary().unwrap_or("").to_string(),
+            author: commit.author().name().unwrap_or("").to_string(),
+        });
+        let Ok(parent) = commit.parent(0) else {
+            break;
+        };
+        commit = parent;
+    }
+    Ok(entries)
+}
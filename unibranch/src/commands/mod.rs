@@ -1,3 +1,47 @@
+use crate::git::local_commit::{MergeConflict, RebaseConflict};
+
+pub mod clean;
+pub mod color;
 pub mod create;
+pub mod doctor;
+pub mod drop;
+pub mod ignore;
+pub mod log;
+pub mod publish;
 pub mod push;
+pub mod rebase;
+pub mod resync_notes;
+pub mod set_base;
+pub mod squash;
+pub mod status;
 pub mod sync;
+pub mod verbosity;
+pub mod version;
+
+/// Exit status of the `ubr` binary, so that scripts can tell different failures apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExitStatus {
+    Success = 0,
+    /// Any failure that doesn't have a more specific status
+    Error = 1,
+    /// The command stopped on merge conflicts, resolve them and run with '--continue'
+    Conflict = 2,
+}
+
+impl From<&anyhow::Error> for ExitStatus {
+    fn from(err: &anyhow::Error) -> Self {
+        if err.downcast_ref::<MergeConflict>().is_some()
+            || err.downcast_ref::<RebaseConflict>().is_some()
+        {
+            ExitStatus::Conflict
+        } else {
+            ExitStatus::Error
+        }
+    }
+}
+
+impl From<ExitStatus> for std::process::ExitCode {
+    fn from(status: ExitStatus) -> Self {
+        std::process::ExitCode::from(status as u8)
+    }
+}
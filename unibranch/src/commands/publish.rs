@@ -0,0 +1,119 @@
+use std::fmt::Display;
+
+use crate::{
+    commands::{create, sync, verbosity},
+    git::{local_commit::MainCommit, GitRepo},
+    progress::{NoProgress, ProgressReporter},
+};
+
+#[derive(clap::Parser, Default)]
+pub struct Options {
+    /// Number of branches to push in parallel
+    #[arg(short, long, default_value_t = 1)]
+    pub jobs: usize,
+    /// Use the remote branches as they were last fetched
+    #[arg(long)]
+    pub no_fetch: bool,
+    /// Publish even if the working tree has uncommitted changes
+    #[arg(short, long)]
+    pub force: bool,
+    /// Create PRs for, and push, commits without any changes
+    #[arg(long)]
+    pub allow_empty: bool,
+    /// Don't run the hooks in .ubr/hooks/
+    #[arg(long)]
+    pub no_verify: bool,
+}
+
+/// What `publish` did, for library users that want to present it themselves.
+#[derive(Debug, Default)]
+pub struct PublishResult {
+    pub created: create::CreateResult,
+    /// The branches that already existed, updated by the sync
+    pub synced: sync::SyncResult,
+}
+
+impl Display for PublishResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.created, self.synced)
+    }
+}
+
+pub fn execute(options: Options, repo: GitRepo) -> anyhow::Result<()> {
+    execute_with_progress(options, repo, &NoProgress)
+}
+
+pub fn execute_with_progress(
+    options: Options,
+    repo: GitRepo,
+    progress: &dyn ProgressReporter,
+) -> anyhow::Result<()> {
+    let dry_run = repo.is_dry_run();
+    let result = plan(options, repo, progress)?;
+    if !dry_run && !verbosity::is_quiet() {
+        print!("{}", result);
+    }
+    Ok(())
+}
+
+/// Create and sync the PR branches, returning what was pushed instead of printing it.
+pub fn plan(
+    options: Options,
+    repo: GitRepo,
+    progress: &dyn ProgressReporter,
+) -> anyhow::Result<PublishResult> {
+    if repo.sync_state().is_some() {
+        anyhow::bail!("A sync is in progress, run 'ubr sync --continue' or 'ubr sync --abort'");
+    }
+    // Check before anything is pushed, instead of leaving it to the sync
+    if !options.force && repo.has_uncommitted_changes()? {
+        anyhow::bail!("working tree has uncommitted changes, commit them or run with '--force'");
+    }
+    // Fetch once, so that the new PRs are based on the same base branch as the sync uses
+    if !options.no_fetch {
+        repo.fetch()?;
+    }
+
+    let untracked = repo
+        .unpushed_commits()?
+        .into_iter()
+        .filter(|commit| matches!(commit, MainCommit::UnTracked(_)) && !commit.is_ignored())
+        .map(|commit| commit.id().to_string())
+        .collect::<Vec<_>>();
+    let created = if untracked.is_empty() {
+        create::CreateResult::default()
+    } else {
+        create::plan(
+            create::Options {
+                commit_refs: untracked,
+                jobs: options.jobs,
+                allow_empty: options.allow_empty,
+                no_verify: options.no_verify,
+                ..Default::default()
+            },
+            &repo,
+            progress,
+        )?
+    };
+
+    let mut synced = sync::plan(
+        sync::Options {
+            jobs: options.jobs,
+            no_fetch: true,
+            force: options.force,
+            allow_empty: options.allow_empty,
+            no_verify: options.no_verify,
+            ..Default::default()
+        },
+        repo,
+        progress,
+    )?;
+    // The new branches are pushed again by the sync, without any changes
+    synced.pushed.retain(|meta_data| {
+        !created
+            .branches
+            .iter()
+            .any(|c| c.meta_data.remote_branch_name == meta_data.remote_branch_name)
+    });
+    Ok(PublishResult { created, synced })
+}
@@ -1,29 +1,12 @@
-use crate::git::GitRepo;
+use crate::git::{local_commit::CommitMetadata, GitRepo};
 use anyhow::Context;
-use std::path::Path;
-use std::process::Command;
-
-pub fn execute<P>(repo_dir: P) -> anyhow::Result<()>
-where
-    P: AsRef<Path>,
-{
-    let git_repo = GitRepo::open(repo_dir.as_ref()).context("Opening git repository")?;
 
+/// Push the head of the current branch to `$USER/<branch>` on the remote.
+pub fn execute(git_repo: GitRepo) -> anyhow::Result<()> {
     let user = std::env::var("USER").context("No $USER env variable")?;
     let branch_name = format!("{}/{}", user, git_repo.current_branch_name);
 
-    Command::new("git")
-        .current_dir(repo_dir.as_ref())
-        .arg("push")
-        .arg("--no-verify")
-        .arg("--force-with-lease")
-        .arg("--")
-        .arg("origin")
-        .arg(format!(
-            "{}:refs/heads/{}",
-            git_repo.head()?.id(),
-            &branch_name
-        ))
-        .status()?;
-    Ok(())
+    git_repo
+        .remote()
+        .push(&CommitMetadata::new(branch_name, git_repo.head()?.id()))
 }
@@ -0,0 +1,150 @@
+use std::fmt::Display;
+
+use git2::Commit;
+use tracing::info;
+
+use crate::{
+    commands::verbosity,
+    git::{local_commit::RebaseConflict, GitRepo},
+};
+
+#[derive(clap::Parser, Default)]
+pub struct Options {
+    /// Continue a rebase after the conflicts have been resolved
+    #[arg(short, long = "continue")]
+    pub cont: bool,
+    /// Abort a rebase that stopped on conflicts
+    #[arg(long)]
+    pub abort: bool,
+    /// Rebase onto the base branch as it was last fetched
+    #[arg(long)]
+    pub no_fetch: bool,
+    /// Rebase even if the working tree has uncommitted changes
+    #[arg(short, long)]
+    pub force: bool,
+}
+
+/// What `rebase` did, for library users that want to present it themselves.
+#[derive(Debug, Default)]
+pub struct RebaseResult {
+    /// The new head of the current branch, `None` if the rebase was aborted
+    pub head: Option<git2::Oid>,
+    /// Tracked commits whose remote branch doesn't have the new base yet
+    pub needs_sync: Vec<NeedsSync>,
+}
+
+#[derive(Debug)]
+pub struct NeedsSync {
+    pub commit: git2::Oid,
+    pub summary: String,
+    pub remote_branch_name: String,
+}
+
+impl Display for RebaseResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.needs_sync.is_empty() {
+            return Ok(());
+        }
+        writeln!(f, "Run 'ubr sync' to update the PRs of:")?;
+        for commit in &self.needs_sync {
+            writeln!(
+                f,
+                "    {} {} ({})",
+                commit.commit, commit.summary, commit.remote_branch_name
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub fn execute(options: Options, repo: GitRepo) -> anyhow::Result<()> {
+    let dry_run = repo.is_dry_run();
+    let result = plan(options, repo)?;
+    if !dry_run && !verbosity::is_quiet() {
+        print!("{}", result);
+    }
+    Ok(())
+}
+
+/// Move the unpushed commits onto the head of the base branch, keeping their metadata.
+pub fn plan(options: Options, repo: GitRepo) -> anyhow::Result<RebaseResult> {
+    let _lock = repo.lock()?;
+    if options.cont && options.abort {
+        anyhow::bail!("'--continue' and '--abort' cannot be used together");
+    }
+    if repo.sync_state().is_some() {
+        anyhow::bail!("A sync is in progress, run 'ubr sync --continue' or 'ubr sync --abort'");
+    }
+    if (options.cont || options.abort) && repo.rebase_state().is_none() {
+        anyhow::bail!("No rebase in progress");
+    }
+    if options.abort {
+        repo.abort_rebase()?;
+        return Ok(RebaseResult::default());
+    }
+    if !options.cont && repo.rebase_state().is_some() {
+        anyhow::bail!(
+            "A rebase is in progress, run 'ubr rebase --continue' or 'ubr rebase --abort'"
+        );
+    }
+
+    let (parent_commit, commits) = if options.cont {
+        repo.finish_rebase()?
+    } else {
+        if !options.force && repo.has_uncommitted_changes()? {
+            anyhow::bail!(
+                "working tree has uncommitted changes, commit them or run with '--force'"
+            );
+        }
+        if !options.no_fetch {
+            repo.fetch()?;
+        }
+        let commits = repo.unpushed_commits()?.iter().map(|c| c.id()).collect();
+        (repo.base_commit()?, commits)
+    };
+    info!("Rebasing onto {}", parent_commit.id());
+
+    let parent_commit = rebase_commits(&repo, commits, parent_commit)?;
+    repo.update_current_branch(&parent_commit)?;
+
+    let needs_sync = repo
+        .tracked_commits()?
+        .into_iter()
+        .filter(|tracked| !matches!(tracked.is_in_sync(), Ok(true)))
+        .map(|tracked| NeedsSync {
+            commit: tracked.as_commit().id(),
+            summary: tracked.as_commit().summary().unwrap_or("").to_string(),
+            remote_branch_name: tracked.meta_data().remote_branch_name.to_string(),
+        })
+        .collect();
+    Ok(RebaseResult {
+        head: Some(parent_commit.id()),
+        needs_sync,
+    })
+}
+
+/// Move `commits` onto `parent_commit` one at a time, stopping at the first conflict.
+pub(crate) fn rebase_commits<'repo>(
+    repo: &'repo GitRepo,
+    commits: Vec<git2::Oid>,
+    mut parent_commit: Commit<'repo>,
+) -> anyhow::Result<Commit<'repo>> {
+    for (i, id) in commits.iter().enumerate() {
+        let commit = repo.find_unpushed_commit(&id.to_string())?;
+        if repo.has_conflicts(commit.as_commit(), &parent_commit)? {
+            repo.stop_rebase(
+                commit.as_commit(),
+                &parent_commit,
+                commits[i + 1..].to_vec(),
+            )?;
+            return Err(RebaseConflict {
+                commit: *id,
+                onto: parent_commit.id(),
+            }
+            .into());
+        }
+        // Tracked commits keep their metadata
+        parent_commit = commit.rebase(&parent_commit)?.commit();
+    }
+    Ok(parent_commit)
+}
@@ -0,0 +1,86 @@
+use crate::{
+    commands::{
+        color::{paint, Color},
+        verbosity,
+    },
+    git::{local_commit::MainCommit, GitRepo},
+};
+
+#[derive(clap::Parser, Default)]
+pub struct Options {
+    /// Remove the metadata of commits whose remote branch no longer exists
+    #[arg(long)]
+    pub prune: bool,
+}
+
+/// Verify that the remote branch of every tracked commit still exists, reporting
+/// (and with `--prune` removing) the metadata that points to vanished branches.
+///
+/// With `trailers` enabled, the metadata of untracked commits that a remote branch was
+/// last pushed from is restored from the branch's [`trailers`](crate::git::trailers).
+pub fn execute(options: Options, repo: GitRepo) -> anyhow::Result<()> {
+    let _lock = repo.lock()?;
+    for tracked_commit in repo.tracked_commits()? {
+        let branch_name = &tracked_commit.meta_data().remote_branch_name;
+        if repo.remote_branch_exists(branch_name) {
+            continue;
+        }
+
+        let commit = tracked_commit.as_commit();
+        let quiet = verbosity::is_quiet();
+        if !quiet {
+            println!(
+                "{} {}: {}",
+                commit.id(),
+                commit.summary().unwrap_or(""),
+                paint(
+                    Color::Yellow,
+                    &format!(
+                        "remote branch '{}/{}' does not exist",
+                        repo.remote_name(),
+                        branch_name
+                    )
+                )
+            );
+        }
+        if options.prune {
+            repo.remove_meta_data(commit)?;
+            if !quiet {
+                println!("    {}", paint(Color::Red, "removed tracking metadata"));
+            }
+        }
+    }
+    if repo.config().trailers {
+        restore_from_trailers(&repo)?;
+    }
+    Ok(())
+}
+
+fn restore_from_trailers(repo: &GitRepo) -> anyhow::Result<()> {
+    for commit in repo.unpushed_commits()? {
+        let MainCommit::UnTracked(untracked) = commit else {
+            continue;
+        };
+        let commit = untracked.as_commit();
+        let Some(meta_data) = repo.meta_data_from_trailers(commit.id())? else {
+            continue;
+        };
+        if !verbosity::is_quiet() {
+            println!(
+                "{} {}: {}",
+                commit.id(),
+                commit.summary().unwrap_or(""),
+                paint(
+                    Color::Green,
+                    &format!(
+                        "restored tracking metadata of '{}/{}'",
+                        repo.remote_name(),
+                        meta_data.remote_branch_name
+                    )
+                )
+            );
+        }
+        repo.save_meta_data(commit, &meta_data)?;
+    }
+    Ok(())
+}
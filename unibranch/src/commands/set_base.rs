@@ -0,0 +1,72 @@
+use crate::{
+    forge,
+    git::{
+        local_commit::{CommitMetadata, MainCommit},
+        GitRepo,
+    },
+};
+
+#[derive(clap::Parser)]
+pub struct Options {
+    /// The tracked commit whose PR to move
+    pub commit_ref: String,
+    /// A tracked commit further down the stack to stack the PR on, or the base branch to
+    /// base it on the base branch again
+    pub new_base_ref: String,
+}
+
+/// Stack the PR of a commit on the PR of another commit, or move it back onto the base
+/// branch, without dropping it. The remote branch is replaced by the commit on its new
+/// base and the PR's base is changed on the forge.
+pub fn execute(options: Options, repo: GitRepo) -> anyhow::Result<()> {
+    plan(options, &repo)?;
+    Ok(())
+}
+
+/// Move the PR, returning the new metadata of the commit instead of nothing.
+pub fn plan(options: Options, repo: &GitRepo) -> anyhow::Result<CommitMetadata<'static>> {
+    let _lock = repo.lock()?;
+    let tracked = match repo.find_unpushed_commit(&options.commit_ref)? {
+        MainCommit::Tracked(tracked) => tracked,
+        MainCommit::UnTracked(_) => anyhow::bail!("'{}' is not tracked", options.commit_ref),
+    };
+    let base = if is_base_branch(&options.new_base_ref, repo) {
+        None
+    } else {
+        match repo.find_unpushed_commit(&options.new_base_ref)? {
+            MainCommit::Tracked(base)
+                if base.as_commit().id() != tracked.as_commit().id()
+                    && repo.is_ancestor(base.as_commit().id(), tracked.as_commit().id())? =>
+            {
+                Some(base.meta_data().clone().into_owned())
+            }
+            _ => anyhow::bail!(
+                "'{}' is neither a tracked commit below '{}' nor the base branch '{}'",
+                options.new_base_ref,
+                options.commit_ref,
+                repo.base_branch_name()
+            ),
+        }
+    };
+
+    let tracked = tracked.set_base(base.as_ref())?;
+    let meta_data = tracked.meta_data().clone().into_owned();
+    repo.remote().push(&meta_data)?;
+    repo.save_meta_data(tracked.as_commit(), &meta_data)?;
+    if let (Some(number), Some(client)) = (meta_data.pr_number, forge::client(repo)) {
+        if !repo.is_dry_run() {
+            let base_branch = meta_data
+                .base_branch_name
+                .as_deref()
+                .unwrap_or(repo.base_branch_name());
+            client.set_pr_base(number, base_branch)?;
+        }
+    }
+    Ok(meta_data)
+}
+
+/// Whether `base_ref` names the base branch, e.g. `master` or `origin/master`.
+fn is_base_branch(base_ref: &str, repo: &GitRepo) -> bool {
+    let base = repo.base_branch_name();
+    base_ref == base || base_ref == format!("{}/{}", repo.remote_name(), base)
+}
@@ -0,0 +1,50 @@
+use crate::{
+    commands::rebase,
+    forge,
+    git::{local_commit::CommitMetadata, GitRepo},
+};
+
+#[derive(clap::Parser, Default)]
+pub struct Options {
+    /// The commit to squash into its parent
+    pub commit_ref: String,
+}
+
+/// What `squash` did, for library users that want to present it themselves.
+#[derive(Debug)]
+pub struct SquashResult {
+    /// The new head of the current branch
+    pub head: git2::Oid,
+    /// The metadata of the squashed commit, whose remote branch was deleted, if it was
+    /// tracked
+    pub deleted: Option<CommitMetadata<'static>>,
+}
+
+/// Squash a commit into its parent so that both end up in the parent's PR. The remote
+/// branch of the squashed commit is deleted and its PR closed, the parent's branch gets
+/// the combined diff on the next sync.
+pub fn execute(options: Options, repo: GitRepo) -> anyhow::Result<()> {
+    plan(options, &repo)?;
+    Ok(())
+}
+
+/// Squash the commit, returning the new head instead of nothing.
+pub fn plan(options: Options, repo: &GitRepo) -> anyhow::Result<SquashResult> {
+    let _lock = repo.lock()?;
+    let (squashed, commits, deleted) = repo.squash(&options.commit_ref)?;
+    // Stops like `rebase` if a commit above no longer applies, `rebase --continue` finishes
+    let head = rebase::rebase_commits(repo, commits, squashed)?;
+    repo.update_current_branch(&head)?;
+    if let Some(meta_data) = &deleted {
+        if let (Some(number), Some(client)) = (meta_data.pr_number, forge::client(repo)) {
+            if !repo.is_dry_run() {
+                client.close_pr(number)?;
+            }
+        }
+        repo.remote().delete_branch(&meta_data.remote_branch_name)?;
+    }
+    Ok(SquashResult {
+        head: head.id(),
+        deleted,
+    })
+}
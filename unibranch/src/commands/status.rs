@@ -0,0 +1,306 @@
+use std::fmt::Display;
+
+use crate::{
+    commands::color::{paint, Color},
+    git::{
+        commit_subject,
+        local_commit::{MainCommit, PrState},
+        GitRepo,
+    },
+};
+
+#[derive(clap::Parser, Default)]
+pub struct Options {
+    /// Also show the commits marked with 'ubr ignore'
+    #[arg(short, long)]
+    pub all: bool,
+    /// Print every commit with this format instead, like 'git log --format'. The
+    /// placeholders are '%H' (commit id), '%h' (abbreviated commit id), '%s' (subject),
+    /// '%b' (remote branch), '%p' (PR number), '%t' (PR state), '%u' (PR link), '%n'
+    /// (newline) and '%%' (a '%')
+    #[arg(long, conflicts_with = "count")]
+    pub format: Option<String>,
+    /// Only print how many commits there are, how many are tracked and how many of those
+    /// are in sync with their remote branch
+    #[arg(long)]
+    pub count: bool,
+    /// Print the counts of '--count' as a JSON object
+    #[arg(long, requires = "count")]
+    pub json: bool,
+}
+
+/// A commit of the stack and, if it is tracked, its PR.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitStatus {
+    pub id: git2::Oid,
+    pub summary: String,
+    /// `None` if the commit is untracked
+    pub remote_branch_name: Option<String>,
+    pub pr_number: Option<u64>,
+    pub pr_state: PrState,
+    /// Link to the PR, or to the remote branch until a PR has been opened, if the remote
+    /// is on a known forge
+    pub url: Option<String>,
+    /// The commit is untracked and marked to never become a PR, see `ubr ignore`
+    pub ignored: bool,
+    /// How many commits the last commit made for the PR is ahead and behind the remote
+    /// branch, `None` if the commit is untracked or its branch hasn't been pushed
+    pub ahead_behind: Option<(usize, usize)>,
+    /// The remote branch has all the changes of the commit, see
+    /// [`TrackedCommit::is_in_sync`]. `false` if the commit is untracked or its branch
+    /// hasn't been pushed
+    ///
+    /// [`TrackedCommit::is_in_sync`]: crate::git::local_commit::TrackedCommit::is_in_sync
+    pub in_sync: bool,
+}
+
+/// The numbers printed by `status --count`. Ignored commits are only counted with `--all`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct StatusCounts {
+    pub total: usize,
+    pub tracked: usize,
+    pub untracked: usize,
+    /// Tracked commits whose changes are all on their remote branch
+    pub in_sync: usize,
+    /// Tracked commits with changes that `sync` would push
+    pub needs_sync: usize,
+}
+
+impl Display for StatusCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "total {}, tracked {}, untracked {}, in sync {}, needs sync {}",
+            self.total, self.tracked, self.untracked, self.in_sync, self.needs_sync
+        )
+    }
+}
+
+pub fn execute(options: Options, repo: GitRepo) -> anyhow::Result<()> {
+    if options.count {
+        let counts = counts(&repo, options.all)?;
+        if options.json {
+            println!("{}", serde_json::to_string(&counts)?);
+        } else {
+            print!("{}", counts);
+        }
+        return Ok(());
+    }
+    for commit in status(&repo)? {
+        let id = commit.id.to_string();
+        if commit.ignored && !options.all {
+            continue;
+        }
+        if let Some(format) = &options.format {
+            println!("{}", format_status(format, &commit)?);
+            continue;
+        }
+        if commit.ignored {
+            println!("{} {} (ignored)", &id[..7], commit.summary);
+            continue;
+        }
+        let Some(branch_name) = &commit.remote_branch_name else {
+            println!(
+                "{} {} ({})",
+                &id[..7],
+                commit.summary,
+                paint(Color::Red, "untracked")
+            );
+            continue;
+        };
+        // Green when the remote branch is up to date, yellow when it needs a sync
+        let branch_name = paint(
+            if commit.in_sync {
+                Color::Green
+            } else {
+                Color::Yellow
+            },
+            branch_name,
+        );
+        let pr = commit
+            .pr_number
+            .map(|number| format!(" #{}", number))
+            .unwrap_or_default();
+        let state = match commit.pr_state {
+            PrState::Merged => paint(Color::Green, "merged"),
+            PrState::Closed => paint(Color::Red, "closed"),
+            state => state.to_string(),
+        };
+        let remote = match commit.ahead_behind {
+            None => ", not pushed".to_string(),
+            Some((0, 0)) => String::new(),
+            Some((0, behind)) => format!(", behind {}", behind),
+            Some((ahead, 0)) => format!(", ahead {}", ahead),
+            Some((ahead, behind)) => format!(", ahead {}, behind {}", ahead, behind),
+        };
+        println!(
+            "{} {} ({}{}, {}{})",
+            &id[..7],
+            commit.summary,
+            branch_name,
+            pr,
+            state,
+            remote
+        );
+    }
+    Ok(())
+}
+
+/// The commits of the stack, oldest first, with the PR state last seen by `sync`. The
+/// ignored commits are included, see [`CommitStatus::ignored`].
+pub fn status(repo: &GitRepo) -> anyhow::Result<Vec<CommitStatus>> {
+    repo.unpushed_commits()?
+        .into_iter()
+        .map(|commit| {
+            let id = commit.as_commit().id();
+            let summary = commit_subject(commit.as_commit());
+            Ok(match commit {
+                MainCommit::Tracked(tracked) => {
+                    let meta_data = tracked.meta_data();
+                    let ahead_behind = tracked.ahead_behind()?;
+                    CommitStatus {
+                        id,
+                        summary,
+                        remote_branch_name: Some(meta_data.remote_branch_name.to_string()),
+                        pr_number: meta_data.pr_number,
+                        pr_state: meta_data.pr_state,
+                        url: tracked.pr_url().or_else(|| tracked.remote_url()),
+                        ignored: false,
+                        in_sync: ahead_behind.is_some() && tracked.is_in_sync()?,
+                        ahead_behind,
+                    }
+                }
+                MainCommit::UnTracked(untracked) => CommitStatus {
+                    id,
+                    summary,
+                    remote_branch_name: None,
+                    pr_number: None,
+                    pr_state: PrState::Unknown,
+                    url: None,
+                    ignored: untracked.is_ignored(),
+                    ahead_behind: None,
+                    in_sync: false,
+                },
+            })
+        })
+        .collect()
+}
+
+/// Count the commits of the stack, see [`StatusCounts`]. A tracked commit whose branch
+/// isn't on the remote needs a sync.
+pub fn counts(repo: &GitRepo, all: bool) -> anyhow::Result<StatusCounts> {
+    let mut counts = StatusCounts::default();
+    for commit in repo.unpushed_commits()? {
+        match commit {
+            MainCommit::Tracked(tracked) => {
+                counts.tracked += 1;
+                if tracked.ahead_behind()?.is_some() && tracked.is_in_sync()? {
+                    counts.in_sync += 1;
+                } else {
+                    counts.needs_sync += 1;
+                }
+            }
+            MainCommit::UnTracked(untracked) => {
+                if untracked.is_ignored() && !all {
+                    continue;
+                }
+                counts.untracked += 1;
+            }
+        }
+        counts.total += 1;
+    }
+    Ok(counts)
+}
+
+/// Expand the placeholders of a `--format` in `format` for `commit`. Values that the
+/// commit doesn't have, like the branch of an untracked commit, expand to nothing.
+pub fn format_status(format: &str, commit: &CommitStatus) -> anyhow::Result<String> {
+    let mut result = String::with_capacity(format.len());
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => result.push('%'),
+            Some('n') => result.push('\n'),
+            Some('H') => result.push_str(&commit.id.to_string()),
+            Some('h') => result.push_str(&commit.id.to_string()[..7]),
+            Some('s') => result.push_str(&commit.summary),
+            Some('b') => result.push_str(commit.remote_branch_name.as_deref().unwrap_or("")),
+            Some('p') => {
+                if let Some(number) = commit.pr_number {
+                    result.push_str(&number.to_string());
+                }
+            }
+            Some('t') => result.push_str(&commit.pr_state.to_string()),
+            Some('u') => result.push_str(commit.url.as_deref().unwrap_or("")),
+            Some(other) => anyhow::bail!("Unknown placeholder '%{}' in the format", other),
+            None => anyhow::bail!("The format ends with a lone '%', write '%%' for a '%'"),
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use git2::Oid;
+
+    use super::{format_status, CommitStatus};
+    use crate::git::local_commit::PrState;
+
+    fn commit_status() -> CommitStatus {
+        CommitStatus {
+            id: Oid::from_str("6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84").unwrap(),
+            summary: "Add a feature".to_string(),
+            remote_branch_name: Some("add-a-feature".to_string()),
+            pr_number: Some(12),
+            pr_state: PrState::Open,
+            url: Some("https://github.com/org/repo/pull/12".to_string()),
+            ignored: false,
+            ahead_behind: Some((0, 0)),
+            in_sync: true,
+        }
+    }
+
+    #[test]
+    fn test_format_status() {
+        let commit = commit_status();
+        assert_eq!(
+            format_status("%h %s [%b] #%p %t", &commit).unwrap(),
+            "6ec67b3 Add a feature [add-a-feature] #12 open"
+        );
+        assert_eq!(
+            format_status("%H%n%u", &commit).unwrap(),
+            "6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84\nhttps://github.com/org/repo/pull/12"
+        );
+        assert_eq!(
+            format_status("100%% %s, 50%%%%", &commit).unwrap(),
+            "100% Add a feature, 50%%"
+        );
+
+        let untracked = CommitStatus {
+            remote_branch_name: None,
+            pr_number: None,
+            pr_state: PrState::Unknown,
+            url: None,
+            ..commit
+        };
+        assert_eq!(
+            format_status("%h:%b:%p:%u", &untracked).unwrap(),
+            "6ec67b3:::"
+        );
+    }
+
+    #[test]
+    fn test_format_status_errors() {
+        let commit = commit_status();
+        assert_eq!(
+            format_status("%h %x", &commit).unwrap_err().to_string(),
+            "Unknown placeholder '%x' in the format"
+        );
+        assert!(format_status("%s %", &commit).is_err());
+    }
+}
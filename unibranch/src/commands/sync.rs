@@ -1,12 +1,72 @@
+use std::{fmt::Display, path::PathBuf};
+
 use anyhow::Context;
-use tracing::{debug, info, span, Level};
 
-use crate::git::{local_commit::MainCommit, GitRepo};
+use git2::Commit;
+use tracing::{debug, info, span, warn, Level};
+
+use crate::{
+    commands::verbosity,
+    forge,
+    git::{
+        hooks::Hook,
+        local_commit::{
+            is_empty, CommitMetadata, MainCommit, MergeConflict, PrState, TrackedCommit,
+        },
+        GitRepo,
+    },
+    progress::{NoProgress, ProgressReporter},
+};
 
 #[derive(clap::Parser, Default)]
 pub struct Options {
-    #[arg(short, long)]
+    /// Continue a sync after the merge conflicts have been resolved
+    #[arg(short, long = "continue")]
     pub cont: bool,
+    /// Abort a sync that stopped on merge conflicts
+    #[arg(long)]
+    pub abort: bool,
+    /// Number of branches to push in parallel
+    #[arg(short, long, default_value_t = 1)]
+    pub jobs: usize,
+    /// Use the remote branches as they were last fetched
+    #[arg(long)]
+    pub no_fetch: bool,
+    /// Only sync the commits after this commit
+    #[arg(long, value_name = "REF")]
+    pub since: Option<String>,
+    /// Sync even if the working tree has uncommitted changes
+    #[arg(short, long)]
+    pub force: bool,
+    /// Skip commits that conflict with their remote branch and sync the rest of the stack
+    #[arg(long)]
+    pub keep_going: bool,
+    /// Run 'git mergetool' with the configured 'merge.tool' on the conflicting files when
+    /// the sync stops on merge conflicts
+    #[arg(long, conflicts_with = "keep_going")]
+    pub mergetool: bool,
+    /// Push the branches of tracked commits without any changes
+    #[arg(long)]
+    pub allow_empty: bool,
+    /// Don't run the hooks in .ubr/hooks/
+    #[arg(long)]
+    pub no_verify: bool,
+    /// Write the changes of every tracked commit to a `<remote-branch>.patch` file in this
+    /// directory, to be applied with 'git am'
+    #[arg(long, value_name = "DIR")]
+    pub output: Option<PathBuf>,
+    /// Only propagate changed commit messages to the PRs whose changes are already in sync,
+    /// without merging or rebasing anything
+    #[arg(long, conflicts_with_all = ["cont", "keep_going"])]
+    pub only_message_changes: bool,
+    /// Only fetch and sync the tracked commit with this remote branch, the rest of the
+    /// stack is rebased on top of it
+    #[arg(long, value_name = "NAME")]
+    pub branch: Option<String>,
+    /// Only push the changes to files matching this pathspec, the other changes stay local
+    /// until the next sync. Can be given more than once
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["cont", "only_message_changes"])]
+    pub pathspec: Vec<String>,
 }
 
 ///```text
@@ -23,10 +83,187 @@ pub struct Options {
 /// (old_origin) *
 ///```
 pub fn execute(options: Options, repo: GitRepo) -> anyhow::Result<()> {
+    execute_with_progress(options, repo, &NoProgress)
+}
+
+/// What `sync` did, for library users that want to present it themselves.
+#[derive(Debug, Default)]
+pub struct SyncResult {
+    /// The branches that were pushed
+    pub pushed: Vec<CommitMetadata<'static>>,
+    /// The new head of the current branch, `None` if the sync was aborted
+    pub head: Option<git2::Oid>,
+    /// Commits that were left unsynced because of conflicts, only with `--keep-going`
+    pub skipped: Vec<SkippedCommit>,
+    /// Tracked commits that were left unsynced because they are empty, e.g. because their
+    /// changes have been merged into the base branch
+    pub empty: Vec<SkippedCommit>,
+    /// Tracked commits that were left unsynced because their PR has been merged
+    pub merged: Vec<SkippedCommit>,
+    /// Number of commits in the stack, if it is deeper than `max-stack-depth`
+    pub deep_stack: Option<usize>,
+}
+
+/// A tracked commit that was rebased without the remote changes and not pushed, see
+/// [`SyncResult`].
+#[derive(Debug)]
+pub struct SkippedCommit {
+    pub commit: git2::Oid,
+    pub summary: String,
+    pub remote_branch_name: String,
+}
+
+impl Display for SyncResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for meta_data in &self.pushed {
+            writeln!(
+                f,
+                "{}: {}",
+                meta_data.remote_branch_name, meta_data.remote_commit
+            )?;
+        }
+        if !self.skipped.is_empty() {
+            writeln!(
+                f,
+                "Skipped commits with conflicts, run 'ubr sync' without '--keep-going' to resolve them:"
+            )?;
+            for skipped in &self.skipped {
+                writeln!(
+                    f,
+                    "    {} {} ({})",
+                    skipped.commit, skipped.summary, skipped.remote_branch_name
+                )?;
+            }
+        }
+        if !self.empty.is_empty() {
+            writeln!(
+                f,
+                "Skipped empty commits, drop them with 'git rebase -i' or run with '--allow-empty' to push them:"
+            )?;
+            for empty in &self.empty {
+                writeln!(
+                    f,
+                    "    {} {} ({})",
+                    empty.commit, empty.summary, empty.remote_branch_name
+                )?;
+            }
+        }
+        if !self.merged.is_empty() {
+            writeln!(
+                f,
+                "Skipped commits whose PR has been merged, drop them with 'git rebase -i':"
+            )?;
+            for merged in &self.merged {
+                writeln!(
+                    f,
+                    "    {} {} ({})",
+                    merged.commit, merged.summary, merged.remote_branch_name
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn execute_with_progress(
+    options: Options,
+    repo: GitRepo,
+    progress: &dyn ProgressReporter,
+) -> anyhow::Result<()> {
+    let dry_run = repo.is_dry_run();
+    let result = plan(options, repo, progress)?;
+    if !dry_run && !verbosity::is_quiet() {
+        print!("{}", result);
+    }
+    Ok(())
+}
+
+/// Sync the stack with the remote, returning what was pushed instead of printing it.
+pub fn plan(
+    options: Options,
+    mut repo: GitRepo,
+    progress: &dyn ProgressReporter,
+) -> anyhow::Result<SyncResult> {
+    let _lock = repo.lock()?;
+    if options.cont && options.abort {
+        anyhow::bail!("'--continue' and '--abort' cannot be used together");
+    }
+    if repo.rebase_state().is_some() {
+        anyhow::bail!(
+            "A rebase is in progress, run 'ubr rebase --continue' or 'ubr rebase --abort'"
+        );
+    }
+    if (options.cont || options.abort) && repo.sync_state().is_none() {
+        anyhow::bail!("No sync in progress");
+    }
+    if options.abort {
+        repo.abort_sync()?;
+        return Ok(SyncResult::default());
+    }
+
+    if !options.cont && !options.force && repo.has_uncommitted_changes()? {
+        anyhow::bail!("working tree has uncommitted changes, commit them or run with '--force'");
+    }
+
+    // Checking out the synced stack must not overwrite the changes that '--force' allowed
+    let stashed = !options.cont && repo.stash_changes()?;
+    let result = sync_stack(options, &mut repo, progress);
+    if stashed {
+        if repo.operation_in_progress() {
+            warn!("The uncommitted changes are stashed, run 'git stash pop' once the sync is done");
+        } else {
+            let popped = repo.pop_stash();
+            let result = result?;
+            popped?;
+            return Ok(result);
+        }
+    }
+    result
+}
+
+/// The part of [`plan`] that runs with the uncommitted changes stashed.
+fn sync_stack(
+    options: Options,
+    repo: &mut GitRepo,
+    progress: &dyn ProgressReporter,
+) -> anyhow::Result<SyncResult> {
     debug!("Syncing local changes with remote");
 
+    if let Some(branch) = &options.branch {
+        if !repo
+            .tracked_commits()?
+            .iter()
+            .any(|c| c.meta_data().remote_branch_name == branch.as_str())
+        {
+            anyhow::bail!("No tracked commit has the remote branch '{}'", branch);
+        }
+    }
+
+    if !options.no_fetch {
+        match &options.branch {
+            Some(branch) => repo.fetch_branch(branch)?,
+            None => repo.fetch()?,
+        }
+    }
+    if let Some(since) = &options.since {
+        repo.set_since(since)?;
+    }
+    if let Some(output) = &options.output {
+        write_patches(repo, output)?;
+    }
+    if !options.cont {
+        update_pr_states(repo)?;
+    }
+    let deep_stack = warn_on_deep_stack(repo)?;
     let unpushed_commits = repo.unpushed_commits()?;
-    let mut parent_commit = if options.cont {
+    if options.only_message_changes {
+        let result = sync_messages(repo, unpushed_commits, &options, progress)?;
+        return Ok(SyncResult {
+            deep_stack,
+            ..result
+        });
+    }
+    let parent_commit = if options.cont {
         //Read the current state
         //First finish the ongoing merge
         let tracked_commit = repo.finish_merge()?;
@@ -34,14 +271,192 @@ pub fn execute(options: Options, repo: GitRepo) -> anyhow::Result<()> {
         //
         tracked_commit.commit()
     } else {
-        repo.base_commit()?
+        repo.stack_base()?
     };
     info!(
         "Base commit {} {}",
         parent_commit.id(),
         parent_commit.message().unwrap_or("")
     );
+
+    // Build all the commits first and push the branches once they are all known, so that
+    // the pushes can run in parallel. Branches that were synced before a conflict are
+    // still pushed.
+    let mut sync_result = SyncResult {
+        deep_stack,
+        ..Default::default()
+    };
+    let result = sync_commits(
+        repo,
+        unpushed_commits,
+        parent_commit,
+        &options,
+        &mut sync_result,
+        progress,
+    );
+    if !options.no_verify {
+        for meta_data in &sync_result.pushed {
+            repo.run_hook(Hook::PrePush, meta_data)?;
+        }
+    }
+    for meta_data in &sync_result.pushed {
+        progress.on_push(&meta_data.remote_branch_name);
+    }
+    repo.remote()
+        .push_all(&sync_result.pushed, options.jobs, |_| Ok(()))?;
+    let parent_commit = result?;
+
+    repo.update_current_branch(&parent_commit)?;
+
+    sync_result.head = Some(parent_commit.id());
+    Ok(sync_result)
+}
+
+/// Write a patch for every tracked commit to `dir`, see [`TrackedCommit::format_patch`].
+///
+/// [`TrackedCommit::format_patch`]: crate::git::local_commit::TrackedCommit::format_patch
+fn write_patches(repo: &GitRepo, dir: &std::path::Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir).with_context(|| format!("Creating {}", dir.display()))?;
+    for tracked_commit in repo.tracked_commits()? {
+        let branch_name = &tracked_commit.meta_data().remote_branch_name;
+        let path = dir.join(format!("{}.patch", branch_name.replace('/', "-")));
+        let patch = tracked_commit
+            .format_patch()
+            .with_context(|| format!("Creating patch for {}", branch_name))?;
+        std::fs::write(&path, patch).with_context(|| format!("Writing {}", path.display()))?;
+    }
+    Ok(())
+}
+
+/// Look up the state of the PRs of the tracked commits on the forge and save it in their
+/// notes. Nothing is looked up if no forge is configured.
+fn update_pr_states(repo: &GitRepo) -> anyhow::Result<()> {
+    let Some(client) = forge::client(repo) else {
+        return Ok(());
+    };
+    for tracked_commit in repo.tracked_commits()? {
+        let meta_data = tracked_commit.meta_data();
+        let Some(number) = meta_data.pr_number else {
+            continue;
+        };
+        // Syncing the stack shouldn't depend on the forge being reachable
+        let pr_state = match client.pr_state(number) {
+            Ok(pr_state) => pr_state,
+            Err(err) => {
+                warn!("Unable to get the state of PR #{}: {:?}", number, err);
+                continue;
+            }
+        };
+        if pr_state != meta_data.pr_state {
+            let mut meta_data = meta_data.clone().into_owned();
+            meta_data.pr_state = pr_state;
+            repo.save_meta_data(tracked_commit.as_commit(), &meta_data)?;
+        }
+    }
+    Ok(())
+}
+
+/// Warn if the stack has more commits than `max-stack-depth`, returning the number of
+/// commits if it does.
+pub(crate) fn warn_on_deep_stack(repo: &GitRepo) -> anyhow::Result<Option<usize>> {
+    let max_depth = repo.config().max_stack_depth;
+    let depth = repo.current_stack_depth()?;
+    if max_depth == 0 || depth <= max_depth {
+        return Ok(None);
+    }
+    warn!(
+        "The stack has {} commits, more than 'max-stack-depth' ({}). Consider merging the \
+         PRs at the bottom of the stack, or squashing some of the commits with 'ubr squash'",
+        depth, max_depth
+    );
+    Ok(Some(depth))
+}
+
+/// Reword the PRs of the tracked commits whose messages have changed, leaving the local
+/// commits as they are. Commits with changes that aren't on their remote branch yet, or
+/// whose remote branch has new commits, are left for a full sync.
+fn sync_messages<'repo>(
+    repo: &'repo GitRepo,
+    unpushed_commits: Vec<MainCommit<'repo>>,
+    options: &Options,
+    progress: &dyn ProgressReporter,
+) -> anyhow::Result<SyncResult> {
+    let mut result = SyncResult::default();
+    for commit in unpushed_commits {
+        let MainCommit::Tracked(tracked_commit) = commit else {
+            continue;
+        };
+        if let Some(branch) = &options.branch {
+            if *branch != tracked_commit.meta_data().remote_branch_name {
+                continue;
+            }
+        }
+        progress.on_commit_start(tracked_commit.as_commit());
+        let remote_commit = tracked_commit.meta_data().remote_commit;
+        let remote_head = tracked_commit.remote_branch()?.get().peel_to_commit()?.id();
+        if remote_head != remote_commit || !tracked_commit.is_in_sync()? {
+            info!(
+                "Not rewording {}, its changes are not in sync",
+                tracked_commit.as_commit().id()
+            );
+            continue;
+        }
+        let tracked_commit = tracked_commit.update_remote_message()?;
+        if tracked_commit.meta_data().remote_commit != remote_commit {
+            result
+                .pushed
+                .push(tracked_commit.meta_data().clone().into_owned());
+        }
+    }
+    if !options.no_verify {
+        for meta_data in &result.pushed {
+            repo.run_hook(Hook::PrePush, meta_data)?;
+        }
+    }
+    for meta_data in &result.pushed {
+        progress.on_push(&meta_data.remote_branch_name);
+    }
+    repo.remote()
+        .push_all(&result.pushed, options.jobs, |_| Ok(()))?;
+    result.head = Some(repo.head()?.id());
+    Ok(result)
+}
+
+/// Push the changes of `tracked_commit` to the files matching `paths` and move it onto
+/// `parent_commit`. Changes on the remote branch can't be merged without losing the local
+/// changes that aren't pushed, so the remote branch must not have any.
+fn sync_paths<'repo>(
+    tracked_commit: TrackedCommit<'repo>,
+    parent_commit: &Commit,
+    paths: &[String],
+) -> anyhow::Result<TrackedCommit<'repo>> {
+    let remote_head = tracked_commit.remote_branch()?.get().peel_to_commit()?.id();
+    if remote_head != tracked_commit.meta_data().remote_commit {
+        anyhow::bail!(
+            "'{}' has changes that aren't in the local commit, run 'ubr sync' without \
+             '--pathspec' first",
+            tracked_commit.meta_data().remote_branch_name
+        );
+    }
+    let tracked_commit = tracked_commit.update_local_branch_head_in(paths)?;
+    if tracked_commit.as_commit().parent_id(0)? == parent_commit.id() {
+        return Ok(tracked_commit);
+    }
+    tracked_commit.rebase(parent_commit)
+}
+
+/// Sync the commits on top of `parent_commit`, collecting what is to be pushed and what
+/// was skipped in `result`.
+fn sync_commits<'repo>(
+    repo: &'repo GitRepo,
+    unpushed_commits: Vec<MainCommit<'repo>>,
+    mut parent_commit: Commit<'repo>,
+    options: &Options,
+    result: &mut SyncResult,
+    progress: &dyn ProgressReporter,
+) -> anyhow::Result<Commit<'repo>> {
     for original_commit in unpushed_commits {
+        progress.on_commit_start(original_commit.as_commit());
         match original_commit {
             MainCommit::Tracked(tracked_commit) => {
                 let _span = span!(
@@ -51,15 +466,94 @@ pub fn execute(options: Options, repo: GitRepo) -> anyhow::Result<()> {
                     summary = tracked_commit.as_commit().summary()
                 )
                 .entered();
-                let new_parent_1 = tracked_commit
-                    .update_local_branch_head()?
-                    .merge_remote_head(Some(&parent_commit))?;
+                if options
+                    .branch
+                    .as_ref()
+                    .is_some_and(|b| *b != tracked_commit.meta_data().remote_branch_name)
+                {
+                    info!("Not syncing {}", tracked_commit.as_commit().id());
+                    parent_commit = tracked_commit.rebase(&parent_commit)?.commit();
+                    continue;
+                }
+                if tracked_commit.meta_data().pr_state == PrState::Merged {
+                    info!("Skipping merged commit {}", tracked_commit.as_commit().id());
+                    let summary = tracked_commit.as_commit().summary().map(str::to_string);
+                    let remote_branch_name =
+                        tracked_commit.meta_data().remote_branch_name.to_string();
+                    let rebased_commit = tracked_commit.rebase(&parent_commit)?.commit();
+                    result.merged.push(SkippedCommit {
+                        commit: rebased_commit.id(),
+                        summary: summary.unwrap_or_default(),
+                        remote_branch_name,
+                    });
+                    parent_commit = rebased_commit;
+                    continue;
+                }
+                tracked_commit.ensure_remote_not_rewritten()?;
+                if !options.allow_empty && is_empty(tracked_commit.as_commit())? {
+                    info!("Skipping empty commit {}", tracked_commit.as_commit().id());
+                    let summary = tracked_commit.as_commit().summary().map(str::to_string);
+                    let remote_branch_name =
+                        tracked_commit.meta_data().remote_branch_name.to_string();
+                    let rebased_commit = tracked_commit.rebase(&parent_commit)?.commit();
+                    result.empty.push(SkippedCommit {
+                        commit: rebased_commit.id(),
+                        summary: summary.unwrap_or_default(),
+                        remote_branch_name,
+                    });
+                    parent_commit = rebased_commit;
+                    continue;
+                }
+                let original_commit = tracked_commit.as_commit().id();
+                let original_summary = tracked_commit.as_commit().summary().map(str::to_string);
+                let original_meta_data = tracked_commit.meta_data().clone().into_owned();
+                let synced = if options.pathspec.is_empty() {
+                    tracked_commit
+                        .update_local_branch_head()
+                        .and_then(|c| c.merge_remote_head(Some(&parent_commit)))
+                } else {
+                    sync_paths(tracked_commit, &parent_commit, &options.pathspec)
+                };
+                let new_parent_1 = match synced.and_then(|c| c.update_remote_message()) {
+                    Ok(c) => c,
+                    Err(err) => {
+                        let conflicts = repo.conflicting_paths()?;
+                        if !conflicts.is_empty() {
+                            progress.on_conflict(&conflicts);
+                            if options.mergetool {
+                                repo.run_mergetool(&conflicts)?;
+                            }
+                        }
+                        if !options.keep_going || err.downcast_ref::<MergeConflict>().is_none() {
+                            return Err(err);
+                        }
+                        info!("Skipping {} because of conflicts", original_commit);
+                        repo.abandon_merge()?;
+                        let rebased_commit = repo
+                            .find_unpushed_commit(&original_commit.to_string())?
+                            .rebase(&parent_commit)?
+                            .commit();
+                        // Forget any remote commit that was created but won't be pushed
+                        repo.save_meta_data(&rebased_commit, &original_meta_data)?;
+                        result.skipped.push(SkippedCommit {
+                            commit: rebased_commit.id(),
+                            summary: original_summary.unwrap_or_default(),
+                            remote_branch_name: original_meta_data.remote_branch_name.into_owned(),
+                        });
+                        parent_commit = rebased_commit;
+                        continue;
+                    }
+                };
                 //.sync_with_main()?;
 
-                info!("Pushing {} to branch {}", new_parent_1.as_commit().id(), new_parent_1.meta_data().remote_branch_name);
-                repo.remote()
-                    .push(new_parent_1.meta_data())
-                    .with_context(|| format!("Pushing {}", new_parent_1.meta_data()))?;
+                info!(
+                    "Will push {} to branch {}",
+                    new_parent_1.as_commit().id(),
+                    new_parent_1.meta_data().remote_branch_name
+                );
+                result
+                    .pushed
+                    .push(new_parent_1.meta_data().clone().into_owned());
                 parent_commit = new_parent_1.commit();
             }
             MainCommit::UnTracked(local_commit) => {
@@ -73,8 +567,5 @@ pub fn execute(options: Options, repo: GitRepo) -> anyhow::Result<()> {
             }
         }
     }
-
-    repo.update_current_branch(&parent_commit)?;
-
-    Ok(())
+    Ok(parent_commit)
 }
@@ -0,0 +1,14 @@
+//! Whether the commands print anything besides errors, see `--quiet`. Errors are
+//! returned to the caller and never silenced.
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Stop the commands from printing their results.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
@@ -0,0 +1,59 @@
+/// The version of ubr and of the libgit2 it was built with, including the optional
+/// libgit2 features, for bug reports.
+pub fn version_info() -> String {
+    let version = git2::Version::get();
+    let (major, minor, patch) = version.libgit2_version();
+    let features = [
+        ("threads", version.threads()),
+        ("https", version.https()),
+        ("ssh", version.ssh()),
+        ("nsec", version.nsec()),
+    ]
+    .iter()
+    .map(|(name, enabled)| format!("{}{}", if *enabled { '+' } else { '-' }, name))
+    .collect::<Vec<_>>()
+    .join(" ");
+    format!(
+        "ubr {}\ngit2 {}\nlibgit2 {}.{}.{} ({}) {}\n",
+        env!("CARGO_PKG_VERSION"),
+        version.crate_version(),
+        major,
+        minor,
+        patch,
+        if version.vendored() {
+            "vendored"
+        } else {
+            "system"
+        },
+        features
+    )
+}
+
+/// Print the [version info](version_info).
+pub fn execute() -> anyhow::Result<()> {
+    print!("{}", version_info());
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::version_info;
+
+    #[test]
+    fn test_version_info_has_libgit2_version() {
+        let info = version_info();
+        let line = info
+            .lines()
+            .find(|line| line.starts_with("libgit2 "))
+            .expect("No libgit2 line");
+        let version = line.split(' ').nth(1).unwrap();
+        let parts = version.split('.').collect::<Vec<_>>();
+        assert_eq!(parts.len(), 3, "{}", version);
+        assert!(
+            parts.iter().all(|p| p.parse::<u32>().is_ok()),
+            "{}",
+            version
+        );
+        assert!(info.starts_with(&format!("ubr {}\n", env!("CARGO_PKG_VERSION"))));
+    }
+}
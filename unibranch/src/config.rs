@@ -0,0 +1,216 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+pub const CONFIG_FILE: &str = ".ubr/config.toml";
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BranchNaming {
+    /// Derive the remote branch name from the commit subject
+    #[default]
+    Subject,
+    /// Name the remote branch after the abbreviated commit id, e.g. `ubr/1a2b3c4`
+    CommitId,
+}
+
+/// Forge hosting the remote, used to open PRs for the pushed branches.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ForgeKind {
+    Github,
+    Gitlab,
+}
+
+/// Option for merging a tracked commit with its remote branch, like git's
+/// `--strategy-option`. libgit2 has no `histogram` diff, use `patience` instead.
+#[derive(Deserialize, clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum StrategyOption {
+    /// Resolve conflicting hunks with the local version
+    Ours,
+    /// Resolve conflicting hunks with the version on the remote branch
+    Theirs,
+    /// Resolve conflicting hunks by keeping both versions
+    Union,
+    Patience,
+    Minimal,
+    IgnoreSpaceChange,
+    IgnoreAllSpace,
+    IgnoreSpaceAtEol,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    /// Name of the remote to push branches to and read the base branch from
+    pub remote: String,
+    /// Branch on the remote that the stack is based on. Defaults to the current branch, or
+    /// to the default branch of the remote if it has no branch with that name.
+    pub base: Option<String>,
+    pub branch_naming: BranchNaming,
+    /// Prepended to the names of the remote branches of new PRs, e.g. `users/alice/`
+    pub branch_prefix: Option<String>,
+    /// Notes ref used to store the commit metadata, e.g. `refs/notes/ubr`.
+    /// Defaults to git's default notes ref.
+    pub notes_ref: Option<String>,
+    /// Detected from the host of the remote URL when not set, see `forge-hosts`
+    pub forge: Option<ForgeKind>,
+    /// Forges on other hosts than github.com and gitlab.com, e.g. a self-hosted GitLab,
+    /// by the host of the remote URL
+    pub forge_hosts: BTreeMap<String, ForgeKind>,
+    /// Number of times to retry a fetch or push that failed because of a network problem
+    pub retries: u32,
+    /// Delay before the first retry, doubled for every following retry
+    pub retry_delay_ms: u64,
+    /// Sign the created commits, like git does when `commit.gpgsign` is set
+    pub sign: bool,
+    /// Options for merging the local changes with the remote branches
+    pub strategy_options: Vec<StrategyOption>,
+    /// Keep the committer, and the committer date, of local commits that are rewritten
+    /// instead of using the author
+    pub keep_committer_date: bool,
+    /// Give every commit created by ubr the date of its author as committer date, like
+    /// `git rebase --committer-date-is-author-date`. Takes precedence over
+    /// `keep-committer-date`.
+    pub committer_date_is_author_date: bool,
+    /// Add `Ubr-Source` and `Ubr-Branch` trailers to the commits pushed to the PR branches,
+    /// see [`trailers`](crate::git::trailers)
+    pub trailers: bool,
+    /// Number of commits in the stack above which `sync` warns that it is getting too
+    /// deep, 0 to never warn
+    pub max_stack_depth: usize,
+    /// `GIT_SSH_COMMAND` for the fetches and pushes, e.g. `ssh -i ~/.ssh/deploy_key`
+    pub ssh_command: Option<String>,
+    /// Extra environment variables for the fetches and pushes
+    pub remote_env: BTreeMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            remote: "origin".to_string(),
+            base: None,
+            branch_naming: BranchNaming::default(),
+            branch_prefix: None,
+            notes_ref: None,
+            forge: None,
+            forge_hosts: BTreeMap::new(),
+            retries: 0,
+            retry_delay_ms: 1000,
+            sign: false,
+            strategy_options: Vec::new(),
+            keep_committer_date: false,
+            committer_date_is_author_date: false,
+            trailers: false,
+            max_stack_depth: 10,
+            ssh_command: None,
+            remote_env: BTreeMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Load the config from `.ubr/config.toml` under `path`, see
+    /// [`GitRepo::main_worktree`](crate::git::GitRepo::main_worktree). A missing file yields
+    /// the defaults.
+    pub fn load<P>(path: P) -> anyhow::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let file = path.as_ref().join(CONFIG_FILE);
+        match std::fs::read_to_string(&file) {
+            Ok(content) => content
+                .parse()
+                .with_context(|| format!("Parsing {}", file.display())),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(err) => Err(err).with_context(|| format!("Reading {}", file.display())),
+        }
+    }
+}
+
+impl std::str::FromStr for Config {
+    type Err = toml::de::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        toml::from_str(s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use indoc::indoc;
+    use tempfile::tempdir;
+
+    use super::{BranchNaming, Config, ForgeKind, StrategyOption};
+
+    #[test]
+    fn test_parse_empty() {
+        let config: Config = "".parse().unwrap();
+        assert_eq!(config, Config::default());
+    }
+
+    #[test]
+    fn test_parse() {
+        let config: Config = indoc! {r#"
+            remote = "upstream"
+            base = "develop"
+            branch-naming = "commit-id"
+            branch-prefix = "users/alice/"
+            notes-ref = "refs/notes/ubr"
+            forge = "gitlab"
+            retries = 3
+            retry-delay-ms = 200
+            sign = true
+            strategy-options = ["theirs", "patience"]
+            keep-committer-date = true
+            committer-date-is-author-date = true
+            trailers = true
+            max-stack-depth = 20
+            ssh-command = "ssh -p 2222"
+
+            [forge-hosts]
+            "git.example.com" = "gitlab"
+
+            [remote-env]
+            GIT_TRACE = "1"
+        "#}
+        .parse()
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                remote: "upstream".to_string(),
+                base: Some("develop".to_string()),
+                branch_naming: BranchNaming::CommitId,
+                branch_prefix: Some("users/alice/".to_string()),
+                notes_ref: Some("refs/notes/ubr".to_string()),
+                forge: Some(ForgeKind::Gitlab),
+                forge_hosts: [("git.example.com".to_string(), ForgeKind::Gitlab)].into(),
+                retries: 3,
+                retry_delay_ms: 200,
+                sign: true,
+                strategy_options: vec![StrategyOption::Theirs, StrategyOption::Patience],
+                keep_committer_date: true,
+                committer_date_is_author_date: true,
+                trailers: true,
+                max_stack_depth: 20,
+                ssh_command: Some("ssh -p 2222".to_string()),
+                remote_env: [("GIT_TRACE".to_string(), "1".to_string())].into(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_unknown_branch_naming() {
+        let config: Result<Config, _> = r#"branch-naming = "random""#.parse();
+        assert!(config.is_err());
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let dir = tempdir().unwrap();
+        assert_eq!(Config::load(dir.path()).unwrap(), Config::default());
+    }
+}
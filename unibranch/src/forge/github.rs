@@ -0,0 +1,70 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use super::{pr_number_from_url, pr_state_from_str, run_cli, ForgeClient};
+use crate::git::local_commit::PrState;
+
+pub struct GitHub {
+    path: PathBuf,
+}
+
+impl GitHub {
+    pub fn new(path: &Path) -> Self {
+        Self { path: path.into() }
+    }
+}
+
+impl ForgeClient for GitHub {
+    fn create_pr(&self, head: &str, base: &str, title: &str, body: &str) -> anyhow::Result<u64> {
+        let output = run_cli(
+            "gh",
+            &self.path,
+            &[
+                "pr", "create", "--head", head, "--base", base, "--title", title, "--body", body,
+            ],
+        )?;
+        output
+            .lines()
+            .find_map(pr_number_from_url)
+            .with_context(|| format!("No PR link in the output of gh: {}", output))
+    }
+
+    fn update_pr(&self, number: u64, title: &str, body: &str) -> anyhow::Result<()> {
+        let number = number.to_string();
+        run_cli(
+            "gh",
+            &self.path,
+            &["pr", "edit", &number, "--title", title, "--body", body],
+        )?;
+        Ok(())
+    }
+
+    fn close_pr(&self, number: u64) -> anyhow::Result<()> {
+        run_cli("gh", &self.path, &["pr", "close", &number.to_string()])?;
+        Ok(())
+    }
+
+    fn set_pr_base(&self, number: u64, base: &str) -> anyhow::Result<()> {
+        let number = number.to_string();
+        run_cli("gh", &self.path, &["pr", "edit", &number, "--base", base])?;
+        Ok(())
+    }
+
+    fn pr_state(&self, number: u64) -> anyhow::Result<PrState> {
+        let output = run_cli(
+            "gh",
+            &self.path,
+            &[
+                "pr",
+                "view",
+                &number.to_string(),
+                "--json",
+                "state",
+                "--jq",
+                ".state",
+            ],
+        )?;
+        Ok(pr_state_from_str(&output))
+    }
+}
@@ -0,0 +1,161 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::Context;
+
+use super::{pr_state_from_str, ForgeClient};
+use crate::git::local_commit::PrState;
+
+/// Environment variable with the access token for the API, the same one that `glab` uses
+const TOKEN_VARIABLE: &str = "GITLAB_TOKEN";
+
+/// Client for the merge requests API of GitLab, with `curl`. Uses the merge request iid as
+/// the PR number.
+pub struct GitLab {
+    path: PathBuf,
+    /// Like `https://gitlab.com/api/v4/projects/group%2Frepo/merge_requests`
+    merge_requests_url: String,
+}
+
+impl GitLab {
+    /// Client for the project at `project_path` on `host`, running `curl` in `path`.
+    pub fn new(path: &Path, host: &str, project_path: &str) -> Self {
+        Self {
+            path: path.into(),
+            merge_requests_url: format!(
+                "https://{}/api/v4/projects/{}/merge_requests",
+                host,
+                encode(project_path)
+            ),
+        }
+    }
+
+    /// Send a request to the merge request `iid`, or to the merge requests when `None`,
+    /// with the `params` as form data, returning the JSON response. The token is passed on
+    /// stdin, so that it doesn't show up in the process list.
+    fn request(
+        &self,
+        method: &str,
+        iid: Option<u64>,
+        params: &[(&str, &str)],
+    ) -> anyhow::Result<serde_json::Value> {
+        let token = std::env::var(TOKEN_VARIABLE).with_context(|| {
+            format!(
+                "Set {} to a GitLab access token with the 'api' scope",
+                TOKEN_VARIABLE
+            )
+        })?;
+        let url = match iid {
+            Some(iid) => format!("{}/{}", self.merge_requests_url, iid),
+            None => self.merge_requests_url.clone(),
+        };
+        let mut command = Command::new("curl");
+        command
+            .current_dir(&self.path)
+            .args(["--silent", "--show-error", "--fail-with-body"])
+            .args(["--request", method, "--header", "@-"]);
+        for (key, value) in params {
+            command
+                .arg("--data-urlencode")
+                .arg(format!("{}={}", key, value));
+        }
+        let mut child = command
+            .arg(&url)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Running curl")?;
+        child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(format!("PRIVATE-TOKEN: {}\n", token).as_bytes())?;
+        let output = child.wait_with_output().context("Running curl")?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        if !output.status.success() {
+            anyhow::bail!(
+                "{} {} failed: {}{}",
+                method,
+                url,
+                String::from_utf8_lossy(&output.stderr).trim(),
+                stdout.trim()
+            );
+        }
+        serde_json::from_str(&stdout)
+            .with_context(|| format!("Parsing the response of {} {}: {}", method, url, stdout))
+    }
+}
+
+/// Percent-encode `value` for use in a URL path, e.g. a project path as project id.
+fn encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+impl ForgeClient for GitLab {
+    fn create_pr(&self, head: &str, base: &str, title: &str, body: &str) -> anyhow::Result<u64> {
+        let response = self.request(
+            "POST",
+            None,
+            &[
+                ("source_branch", head),
+                ("target_branch", base),
+                ("title", title),
+                ("description", body),
+            ],
+        )?;
+        response["iid"]
+            .as_u64()
+            .with_context(|| format!("No merge request iid in the response: {}", response))
+    }
+
+    fn update_pr(&self, number: u64, title: &str, body: &str) -> anyhow::Result<()> {
+        self.request(
+            "PUT",
+            Some(number),
+            &[("title", title), ("description", body)],
+        )?;
+        Ok(())
+    }
+
+    fn close_pr(&self, number: u64) -> anyhow::Result<()> {
+        self.request("PUT", Some(number), &[("state_event", "close")])?;
+        Ok(())
+    }
+
+    fn set_pr_base(&self, number: u64, base: &str) -> anyhow::Result<()> {
+        self.request("PUT", Some(number), &[("target_branch", base)])?;
+        Ok(())
+    }
+
+    fn pr_state(&self, number: u64) -> anyhow::Result<PrState> {
+        let response = self.request("GET", Some(number), &[])?;
+        Ok(response["state"]
+            .as_str()
+            .map_or(PrState::Unknown, pr_state_from_str))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::encode;
+
+    #[test]
+    fn test_encode() {
+        assert_eq!(
+            encode("group/sub-group/repo.rs"),
+            "group%2Fsub-group%2Frepo.rs"
+        );
+    }
+}
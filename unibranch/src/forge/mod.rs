@@ -0,0 +1,134 @@
+//! Pull requests (merge requests on GitLab) on the forge hosting the remote.
+//!
+//! The clients shell out, the same way pushing shells out to `git`: to the `gh` CLI for
+//! GitHub, and to `curl` for the merge requests API of GitLab. They are only compiled in
+//! with the `github`/`gitlab` features.
+use crate::git::{local_commit::PrState, GitRepo};
+
+#[cfg(feature = "github")]
+mod github;
+#[cfg(feature = "gitlab")]
+mod gitlab;
+
+pub trait ForgeClient {
+    /// Open a PR from the `head` branch into `base`, returning its number.
+    fn create_pr(&self, head: &str, base: &str, title: &str, body: &str) -> anyhow::Result<u64>;
+
+    fn update_pr(&self, number: u64, title: &str, body: &str) -> anyhow::Result<()>;
+
+    fn close_pr(&self, number: u64) -> anyhow::Result<()>;
+
+    /// Change the branch the PR is merged into.
+    fn set_pr_base(&self, number: u64, base: &str) -> anyhow::Result<()>;
+
+    fn pr_state(&self, number: u64) -> anyhow::Result<PrState>;
+}
+
+/// Client for the forge hosting the remote. `None` if the forge isn't known or support
+/// for it isn't compiled in.
+pub fn client(git_repo: &GitRepo) -> Option<Box<dyn ForgeClient>> {
+    match git_repo.forge_kind()? {
+        #[cfg(feature = "github")]
+        crate::config::ForgeKind::Github => Some(Box::new(github::GitHub::new(git_repo.path()))),
+        #[cfg(feature = "gitlab")]
+        crate::config::ForgeKind::Gitlab => {
+            let (host, project_path) = git_repo.forge_repo()?;
+            Some(Box::new(gitlab::GitLab::new(
+                git_repo.path(),
+                &host,
+                &project_path,
+            )))
+        }
+        #[allow(unreachable_patterns)]
+        _ => None,
+    }
+}
+
+/// PR templates looked for in the working tree, the first one found is used
+const PR_TEMPLATES: &[&str] = &[
+    ".github/PULL_REQUEST_TEMPLATE.md",
+    ".github/pull_request_template.md",
+    ".gitlab/merge_request_templates/Default.md",
+];
+
+/// Body of a new PR: the commit body followed by the repository's PR template, if it has
+/// one, or just the commit body.
+pub fn pr_body(git_repo: &GitRepo, commit_body: &str) -> String {
+    let template = PR_TEMPLATES
+        .iter()
+        .find_map(|path| std::fs::read_to_string(git_repo.path().join(path)).ok());
+    match (commit_body.trim(), template.as_deref().map(str::trim)) {
+        (body, None | Some("")) => body.to_string(),
+        ("", Some(template)) => template.to_string(),
+        (body, Some(template)) => format!("{}\n\n{}", body, template),
+    }
+}
+
+/// The PR number from a PR link like `https://github.com/org/repo/pull/12` or
+/// `https://gitlab.com/group/repo/-/merge_requests/12`.
+pub fn pr_number_from_url(url: &str) -> Option<u64> {
+    url.trim()
+        .trim_end_matches('/')
+        .rsplit('/')
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// The PR state from the state reported by the forge, like `MERGED` on GitHub or `opened`
+/// on GitLab.
+pub fn pr_state_from_str(state: &str) -> PrState {
+    match state.trim().to_lowercase().as_str() {
+        "open" | "opened" => PrState::Open,
+        "merged" => PrState::Merged,
+        "closed" | "locked" => PrState::Closed,
+        _ => PrState::Unknown,
+    }
+}
+
+#[cfg(feature = "github")]
+fn run_cli(program: &str, path: &std::path::Path, args: &[&str]) -> anyhow::Result<String> {
+    use anyhow::Context;
+
+    let output = std::process::Command::new(program)
+        .current_dir(path)
+        .args(args)
+        .output()
+        .with_context(|| format!("Running {}", program))?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "{} {} failed: {}",
+            program,
+            args.first().unwrap_or(&""),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{pr_number_from_url, pr_state_from_str};
+    use crate::git::local_commit::PrState;
+
+    #[test]
+    fn test_pr_number_from_url() {
+        assert_eq!(
+            pr_number_from_url("https://github.com/org/repo/pull/12\n"),
+            Some(12)
+        );
+        assert_eq!(
+            pr_number_from_url("https://gitlab.com/group/repo/-/merge_requests/7/"),
+            Some(7)
+        );
+        assert_eq!(pr_number_from_url("Created"), None);
+    }
+
+    #[test]
+    fn test_pr_state_from_str() {
+        assert_eq!(pr_state_from_str("MERGED\n"), PrState::Merged);
+        assert_eq!(pr_state_from_str("opened"), PrState::Open);
+        assert_eq!(pr_state_from_str("CLOSED"), PrState::Closed);
+        assert_eq!(pr_state_from_str("draft?"), PrState::Unknown);
+    }
+}
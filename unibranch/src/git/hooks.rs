@@ -0,0 +1,73 @@
+//! User provided executables in `.ubr/hooks/` of the main working tree, run around
+//! pushing PR branches.
+//!
+//! A hook is invoked from the root of the current working tree with the remote commit and the
+//! remote branch as arguments, which are also available in the `UBR_REMOTE_COMMIT` and
+//! `UBR_REMOTE_BRANCH` environment variables:
+//!
+//! ```text
+//! .ubr/hooks/pre-push <remote-commit> <remote-branch>
+//! ```
+//!
+//! * `pre-push` runs before a branch is pushed. A non-zero exit status aborts the push.
+//! * `post-create` runs after `ubr create` has pushed a new branch. Its exit status is
+//!   reported as an error, but the branch stays pushed.
+//!
+//! Hooks that don't exist are skipped, and no hooks are run on dry runs.
+use std::{path::Path, process::Command};
+
+use anyhow::Context;
+
+use super::local_commit::CommitMetadata;
+
+pub const HOOKS_DIR: &str = ".ubr/hooks";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    PrePush,
+    PostCreate,
+}
+
+impl Hook {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Hook::PrePush => "pre-push",
+            Hook::PostCreate => "post-create",
+        }
+    }
+}
+
+pub(crate) fn run(
+    hooks_root: &Path,
+    repo_path: &Path,
+    hook: Hook,
+    meta_data: &CommitMetadata,
+) -> anyhow::Result<()> {
+    let hook_path = hooks_root.join(HOOKS_DIR).join(hook.name());
+    if !hook_path.is_file() {
+        return Ok(());
+    }
+    let remote_commit = meta_data.remote_commit.to_string();
+    tracing::debug!(
+        "Running {} hook for {}",
+        hook.name(),
+        meta_data.remote_branch_name
+    );
+    let status = Command::new(&hook_path)
+        .current_dir(repo_path)
+        .arg(&remote_commit)
+        .arg(meta_data.remote_branch_name.as_ref())
+        .env("UBR_REMOTE_COMMIT", &remote_commit)
+        .env("UBR_REMOTE_BRANCH", meta_data.remote_branch_name.as_ref())
+        .status()
+        .with_context(|| format!("Running {}", hook_path.display()))?;
+    if !status.success() {
+        anyhow::bail!(
+            "'{}' hook failed for {} ({})",
+            hook.name(),
+            meta_data.remote_branch_name,
+            status
+        );
+    }
+    Ok(())
+}
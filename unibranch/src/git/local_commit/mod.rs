@@ -1,7 +1,6 @@
 use std::{borrow::Cow, error::Error, fmt::Display, str::FromStr};
 
 use git2::{Commit, Oid, Repository};
-use itertools::Itertools;
 
 use super::GitRepo;
 
@@ -25,7 +24,7 @@ impl<'repo> MainCommit<'repo> {
         repo: &'repo Repository,
         commit: Commit<'repo>,
     ) -> Result<MainCommit<'repo>, git2::Error> {
-        let res = repo.find_note(None, commit.id());
+        let res = repo.find_note(git_repo.notes_ref(), commit.id());
         if let Err(error) = res {
             match error.code() {
                 git2::ErrorCode::NotFound => {
@@ -58,35 +57,252 @@ impl<'repo> MainCommit<'repo> {
         }
     }
 
+    pub fn as_commit(&self) -> &Commit<'_> {
+        match self {
+            MainCommit::UnTracked(c) => c.as_commit(),
+            MainCommit::Tracked(c) => c.as_commit(),
+        }
+    }
+
+    /// Move the commit onto `parent_commit`.
+    pub(crate) fn rebase(self, parent_commit: &Commit<'_>) -> anyhow::Result<Self> {
+        Ok(match self {
+            MainCommit::UnTracked(c) => MainCommit::UnTracked(c.rebase(parent_commit)?),
+            MainCommit::Tracked(c) => MainCommit::Tracked(c.rebase(parent_commit)?),
+        })
+    }
+
+    pub fn commit(self) -> Commit<'repo> {
+        match self {
+            MainCommit::UnTracked(c) => c.commit(),
+            MainCommit::Tracked(c) => c.commit(),
+        }
+    }
+
     pub fn message(&self) -> Option<&str> {
         match self {
             MainCommit::UnTracked(c) => c.as_commit().message(),
             MainCommit::Tracked(c) => c.as_commit().message(),
         }
     }
+
+    /// Whether the commit is untracked and marked to never become a PR, see
+    /// [`GitRepo::is_ignored`].
+    pub fn is_ignored(&self) -> bool {
+        match self {
+            MainCommit::UnTracked(c) => c.is_ignored(),
+            MainCommit::Tracked(_) => false,
+        }
+    }
+
+    /// Whether the commit doesn't change anything compared to its parent.
+    pub fn is_empty(&self) -> anyhow::Result<bool> {
+        is_empty(self.as_commit())
+    }
+}
+
+pub(crate) fn is_empty(commit: &Commit) -> anyhow::Result<bool> {
+    Ok(commit.tree_id() == commit.parent(0)?.tree_id())
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
 pub struct CommitMetadata<'a> {
     pub remote_branch_name: Cow<'a, str>,
     pub remote_commit: Oid,
+    /// Remote branch the PR is stacked on, when it doesn't target the base branch
+    pub base_branch_name: Option<Cow<'a, str>>,
+    /// Number of the PR opened for the branch on the forge
+    pub pr_number: Option<u64>,
+    /// State of the PR as last seen on the forge by `sync`
+    pub pr_state: PrState,
+    /// PR title and body when they differ from the commit message, see `create --edit`
+    pub pr_title: Option<String>,
+    pub pr_body: Option<String>,
+    /// Message of the PR's first commit when it differs from the local commit, see
+    /// `create --message`
+    pub remote_message: Option<String>,
+    /// `key: value` lines this version doesn't know about, e.g. written by a newer version.
+    /// They are written back as they are so that rewriting a note doesn't lose them.
+    pub other_fields: Vec<(String, String)>,
 }
 
 impl<'a> CommitMetadata<'a> {
+    /// Metadata of a PR on `remote_branch_name` at `remote_commit`, without any of the
+    /// optional fields. Set them with struct update syntax,
+    /// `CommitMetadata { pr_number: Some(1), ..CommitMetadata::new(branch, commit) }`.
+    pub fn new(remote_branch_name: impl Into<Cow<'a, str>>, remote_commit: Oid) -> Self {
+        CommitMetadata {
+            remote_branch_name: remote_branch_name.into(),
+            remote_commit,
+            base_branch_name: None,
+            pr_number: None,
+            pr_state: PrState::Unknown,
+            pr_title: None,
+            pr_body: None,
+            remote_message: None,
+            other_fields: Vec::new(),
+        }
+    }
+
     pub fn update_commit(mut self, oid: Oid) -> Self {
         self.remote_commit = oid;
         self
     }
+
+    pub fn into_owned(self) -> CommitMetadata<'static> {
+        CommitMetadata {
+            remote_branch_name: Cow::Owned(self.remote_branch_name.into_owned()),
+            remote_commit: self.remote_commit,
+            base_branch_name: self
+                .base_branch_name
+                .map(|name| Cow::Owned(name.into_owned())),
+            pr_number: self.pr_number,
+            pr_state: self.pr_state,
+            pr_title: self.pr_title,
+            pr_body: self.pr_body,
+            remote_message: self.remote_message,
+            other_fields: self.other_fields,
+        }
+    }
 }
 
 impl<'a> Display for CommitMetadata<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_fmt(format_args!("remote-branch: {}\n", self.remote_branch_name))?;
         f.write_fmt(format_args!("remote-commit: {}\n", self.remote_commit))?;
+        if let Some(base_branch_name) = &self.base_branch_name {
+            f.write_fmt(format_args!("base-branch: {}\n", base_branch_name))?;
+        }
+        if let Some(pr_number) = self.pr_number {
+            f.write_fmt(format_args!("pr-number: {}\n", pr_number))?;
+        }
+        if self.pr_state != PrState::Unknown {
+            f.write_fmt(format_args!("pr-state: {}\n", self.pr_state))?;
+        }
+        if let Some(pr_title) = &self.pr_title {
+            f.write_fmt(format_args!("pr-title: {}\n", escape(pr_title)))?;
+        }
+        if let Some(pr_body) = &self.pr_body {
+            f.write_fmt(format_args!("pr-body: {}\n", escape(pr_body)))?;
+        }
+        if let Some(remote_message) = &self.remote_message {
+            f.write_fmt(format_args!("remote-message: {}\n", escape(remote_message)))?;
+        }
+        for (key, value) in &self.other_fields {
+            f.write_fmt(format_args!("{}: {}\n", key, value))?;
+        }
         Ok(())
     }
 }
 
+/// State of a PR on the forge. [`PrState::Unknown`] when it has never been looked up, e.g.
+/// because no forge is configured.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PrState {
+    #[default]
+    Unknown,
+    Open,
+    Merged,
+    Closed,
+}
+
+impl Display for PrState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            PrState::Unknown => "unknown",
+            PrState::Open => "open",
+            PrState::Merged => "merged",
+            PrState::Closed => "closed",
+        })
+    }
+}
+
+impl FromStr for PrState {
+    type Err = MetaDataError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "unknown" => Ok(PrState::Unknown),
+            "open" => Ok(PrState::Open),
+            "merged" => Ok(PrState::Merged),
+            "closed" => Ok(PrState::Closed),
+            _ => Err(MetaDataError),
+        }
+    }
+}
+
+/// The local commit could not be merged with the remote branch without conflicts.
+/// The merge has been left in the working tree for the user to resolve.
+#[derive(Debug, PartialEq, Eq)]
+pub struct MergeConflict {
+    pub local: Oid,
+    pub remote: Oid,
+}
+
+impl Display for MergeConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Unable to merge local commit ({}) with commit from remote ({})",
+            self.local, self.remote
+        )?;
+        writeln!(
+            f,
+            "Once all the conflicts has been resolved, run 'ubr sync --continue'"
+        )
+    }
+}
+
+impl Error for MergeConflict {}
+
+/// `ubr rebase` could not move a commit onto its new parent without conflicts. The
+/// conflicts have been left in the working tree for the user to resolve.
+#[derive(Debug, PartialEq, Eq)]
+pub struct RebaseConflict {
+    pub commit: Oid,
+    pub onto: Oid,
+}
+
+impl Display for RebaseConflict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "Conflicts while moving {} onto {}",
+            self.commit, self.onto
+        )?;
+        writeln!(
+            f,
+            "Once all the conflicts has been resolved, run 'ubr rebase --continue'"
+        )
+    }
+}
+
+impl Error for RebaseConflict {}
+
+/// Values are stored one per line, so newlines (and the escape character) are escaped.
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                result.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                result.push('\\');
+                chars.next();
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
 #[derive(Debug)]
 pub struct MetaDataError;
 
@@ -104,19 +320,59 @@ impl FromStr for CommitMetadata<'static> {
     fn from_str(value: &str) -> Result<Self, Self::Err> {
         let mut remote_branch_name = None;
         let mut remote_commit_id = None;
+        let mut base_branch_name = None;
+        let mut pr_number = None;
+        let mut pr_state = PrState::Unknown;
+        let mut pr_title = None;
+        let mut pr_body = None;
+        let mut remote_message = None;
+        let mut other_fields = Vec::new();
         for line in value.lines() {
-            if let Some((key, value)) = line.splitn(2, ':').collect_tuple() {
-                if key == "remote-branch" {
-                    remote_branch_name = Some(value.trim());
-                } else if key == "remote-commit" {
-                    remote_commit_id = value.trim().parse::<Oid>().ok();
-                }
+            if line.trim().is_empty() {
+                continue;
+            }
+            // A line that isn't `key: value` means the note isn't ubr's, or is corrupt
+            let (key, value) = line.split_once(':').ok_or(MetaDataError)?;
+            let key = key.trim();
+            if key.is_empty() {
+                return Err(MetaDataError);
+            }
+            if key == "remote-branch" {
+                remote_branch_name = Some(value.trim()).filter(|name| !name.is_empty());
+            } else if key == "remote-commit" {
+                // `Oid` pads an abbreviated id with zeros instead of rejecting it
+                remote_commit_id = Some(value.trim())
+                    .filter(|id| id.len() == 40)
+                    .and_then(|id| id.parse::<Oid>().ok());
+            } else if key == "base-branch" {
+                let name = Some(value.trim()).filter(|name| !name.is_empty());
+                base_branch_name = Some(Cow::Owned(name.ok_or(MetaDataError)?.to_string()));
+            } else if key == "pr-number" {
+                // A known field that doesn't parse is as corrupt as a missing one
+                pr_number = Some(value.trim().parse().map_err(|_| MetaDataError)?);
+            } else if key == "pr-state" {
+                pr_state = value.trim().parse()?;
+            } else if key == "pr-title" {
+                pr_title = Some(unescape(value.trim()));
+            } else if key == "pr-body" {
+                pr_body = Some(unescape(value.trim()));
+            } else if key == "remote-message" {
+                remote_message = Some(unescape(value.trim()));
+            } else {
+                other_fields.push((key.to_string(), value.trim().to_string()));
             }
         }
         if let (Some(branch), Some(commit)) = (remote_branch_name, remote_commit_id) {
             Ok(CommitMetadata {
                 remote_branch_name: Cow::Owned(branch.to_string()),
                 remote_commit: commit,
+                base_branch_name,
+                pr_number,
+                pr_state,
+                pr_title,
+                pr_body,
+                remote_message,
+                other_fields,
             })
         } else {
             Err(MetaDataError)
@@ -127,29 +383,8 @@ impl FromStr for CommitMetadata<'static> {
 impl<'a> TryFrom<&'a str> for CommitMetadata<'a> {
     type Error = MetaDataError;
 
-    //Implement this using parse..
     fn try_from(value: &'a str) -> Result<Self, Self::Error> {
-        let mut remote_branch_name = None;
-        let mut remote_commit_id = None;
-        for line in value.lines() {
-            if let Some((key, value)) = line.splitn(2, ':').collect_tuple() {
-                if key == "remote-branch" {
-                    remote_branch_name = Some(value.trim());
-                } else if key == "remote-commit" {
-                    remote_commit_id = value.trim().parse::<Oid>().ok();
-                }
-            }
-        }
-        if let (Some(remote_branch_name), Some(remote_commit)) =
-            (remote_branch_name, remote_commit_id)
-        {
-            Ok(CommitMetadata {
-                remote_branch_name: Cow::Owned(remote_branch_name.to_string()),
-                remote_commit,
-            })
-        } else {
-            Err(MetaDataError)
-        }
+        value.parse()
     }
 }
 
@@ -159,7 +394,7 @@ mod test {
 
     use indoc::indoc;
 
-    use super::CommitMetadata;
+    use super::{CommitMetadata, PrState};
 
     #[test]
     fn test_parse() {
@@ -171,6 +406,38 @@ mod test {
         assert!(meta_data.is_err(),)
     }
 
+    #[test]
+    fn test_parse_crlf_and_whitespace() {
+        let msg = "remote-branch:  branch_name \r\n\r\n remote-commit :\t6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84\r\npr-number: 42";
+        let meta_data = TryInto::<CommitMetadata>::try_into(msg).unwrap();
+        assert_eq!(meta_data.remote_branch_name, "branch_name");
+        assert_eq!(
+            meta_data.remote_commit.to_string(),
+            "6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84"
+        );
+        assert_eq!(meta_data.pr_number, Some(42));
+        assert!(meta_data.other_fields.is_empty());
+    }
+
+    #[test]
+    fn test_parse_malformed() {
+        for msg in [
+            "remote-branch: branch_name\nremote-commit: 6ec67b3\n",
+            "remote-branch: branch_name\nremote-commit: 6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84\nnot a field\n",
+            "remote-branch: branch_name\nremote-commit: 6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84\n: value\n",
+            "remote-branch:\nremote-commit: 6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84\n",
+            "remote-branch: branch_name\nremote-commit: 6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84\npr-number: abc\n",
+            "remote-branch: branch_name\nremote-commit: 6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84\npr-state: reopened\n",
+            "remote-branch: branch_name\nremote-commit: 6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84\nbase-branch:\n",
+        ] {
+            assert!(
+                TryInto::<CommitMetadata>::try_into(msg).is_err(),
+                "{:?} should not parse",
+                msg
+            );
+        }
+    }
+
     #[test]
     fn test_parse_where_there_is_no_meta() {
         let commit_msg = indoc! {"
@@ -190,13 +457,91 @@ mod test {
         let meta_data = TryInto::<CommitMetadata>::try_into(msg).unwrap();
         assert_eq!(
             meta_data,
-            CommitMetadata {
-                remote_branch_name: Cow::Borrowed("branch_name"),
-                remote_commit: "6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84".parse().unwrap(),
-            }
+            CommitMetadata::new(
+                "branch_name",
+                "6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84".parse().unwrap()
+            )
         )
     }
 
+    #[test]
+    fn test_parse_with_base_branch() {
+        let msg = indoc! {"
+            remote-branch: branch_name
+            remote-commit: 6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84
+            base-branch: parent_branch
+            pr-number: 42
+        "};
+        let meta_data = TryInto::<CommitMetadata>::try_into(msg).unwrap();
+        assert_eq!(
+            meta_data.base_branch_name,
+            Some(Cow::Borrowed("parent_branch"))
+        );
+        assert_eq!(meta_data.pr_number, Some(42));
+        assert_eq!(meta_data.to_string(), msg);
+    }
+
+    #[test]
+    fn test_pr_title_and_body_roundtrip() {
+        let meta_data = CommitMetadata {
+            pr_title: Some("A title: with a colon".to_string()),
+            pr_body: Some("First line\n\nA path C:\\temp\\new".to_string()),
+            remote_message: Some("A different\nmessage\n".to_string()),
+            ..CommitMetadata::new(
+                "branch_name",
+                "6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84".parse().unwrap(),
+            )
+        };
+        let note = meta_data.to_string();
+        let parsed = TryInto::<CommitMetadata>::try_into(note.as_str()).unwrap();
+        assert_eq!(parsed, meta_data);
+    }
+
+    #[test]
+    fn test_pr_state_roundtrip() {
+        let msg = indoc! {"
+            remote-branch: branch_name
+            remote-commit: 6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84
+            pr-number: 42
+            pr-state: merged
+        "};
+        let meta_data = TryInto::<CommitMetadata>::try_into(msg).unwrap();
+        assert_eq!(meta_data.pr_state, PrState::Merged);
+        assert_eq!(meta_data.to_string(), msg);
+
+        let msg = indoc! {"
+            remote-branch: branch_name
+            remote-commit: 6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84
+        "};
+        let meta_data = TryInto::<CommitMetadata>::try_into(msg).unwrap();
+        assert_eq!(meta_data.pr_state, PrState::Unknown);
+        assert_eq!(meta_data.to_string(), msg);
+    }
+
+    #[test]
+    fn test_unknown_fields_roundtrip() {
+        let msg = indoc! {"
+            remote-branch: branch_name
+            remote-commit: 6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84
+            pr-number: 42
+            review-state: approved
+            merge-queue: https://example.com/queue/1
+        "};
+        let meta_data = TryInto::<CommitMetadata>::try_into(msg).unwrap();
+        assert_eq!(meta_data.pr_number, Some(42));
+        assert_eq!(
+            meta_data.other_fields,
+            vec![
+                ("review-state".to_string(), "approved".to_string()),
+                (
+                    "merge-queue".to_string(),
+                    "https://example.com/queue/1".to_string()
+                ),
+            ]
+        );
+        assert_eq!(meta_data.to_string(), msg);
+    }
+
     #[test]
     fn test_parse_with_invalid_remote_commit() {
         let msg = indoc! {"
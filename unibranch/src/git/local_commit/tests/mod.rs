@@ -4,6 +4,8 @@ use crate::{commands::create, git::GitRepo};
 
 use super::{MainCommit, TrackedCommit};
 
+mod tracked_commit_diff_against_remote;
+mod tracked_commit_is_in_sync;
 mod tracked_commit_merge_remote_head;
 mod tracked_commit_sync_with_main;
 mod tracked_commit_update_local_branch_head;
@@ -16,7 +18,7 @@ fn tracked(commit: MainCommit) -> TrackedCommit {
     }
 }
 
-fn setup_repo(remote: &RemoteRepo) -> TestRepoWithRemote {
+fn setup_repo(remote: &RemoteRepo) -> TestRepoWithRemote<'_> {
     let local = remote.clone_repo();
 
     let local = local
@@ -0,0 +1,41 @@
+use test_repo::RemoteRepo;
+
+use super::{setup_repo, tracked};
+use crate::git::GitRepo;
+
+#[test]
+fn newly_created_commit_has_no_diff() {
+    let remote = RemoteRepo::new();
+    let local = setup_repo(&remote);
+
+    let git_repo = GitRepo::open(local.path()).unwrap();
+    let tracked_commit = tracked(git_repo.find_unpushed_commit("HEAD").unwrap());
+
+    let stats = tracked_commit
+        .diff_against_remote()
+        .unwrap()
+        .stats()
+        .unwrap();
+    assert_eq!(stats.files_changed(), 0);
+}
+
+#[test]
+fn diff_has_the_amended_changes() {
+    let remote = RemoteRepo::new();
+    let local = setup_repo(&remote)
+        .create_file("file3", "More changes\nOn two lines")
+        .append_file("file2", "And another line")
+        .commit_all_amend();
+
+    let git_repo = GitRepo::open(local.path()).unwrap();
+    let tracked_commit = tracked(git_repo.find_unpushed_commit("HEAD").unwrap());
+
+    let stats = tracked_commit
+        .diff_against_remote()
+        .unwrap()
+        .stats()
+        .unwrap();
+    assert_eq!(stats.files_changed(), 2);
+    assert_eq!(stats.insertions(), 3);
+    assert_eq!(stats.deletions(), 0);
+}
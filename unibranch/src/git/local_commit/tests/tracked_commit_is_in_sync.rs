@@ -0,0 +1,39 @@
+use test_repo::RemoteRepo;
+
+use super::{setup_repo, tracked};
+use crate::{commands::sync, git::GitRepo};
+
+#[test]
+fn newly_created_commit_is_in_sync() {
+    let remote = RemoteRepo::new();
+    let local = setup_repo(&remote);
+
+    let git_repo = GitRepo::open(local.path()).unwrap();
+    let tracked_commit = tracked(git_repo.find_unpushed_commit("HEAD").unwrap());
+
+    assert!(tracked_commit.is_in_sync().unwrap());
+}
+
+#[test]
+fn amended_commit_is_not_in_sync_until_synced() {
+    let remote = RemoteRepo::new();
+    let local = setup_repo(&remote)
+        .create_file("file3", "More changes")
+        .commit_all_amend();
+
+    {
+        let git_repo = GitRepo::open(local.path()).unwrap();
+        let tracked_commit = tracked(git_repo.find_unpushed_commit("HEAD").unwrap());
+        assert!(!tracked_commit.is_in_sync().unwrap());
+    }
+
+    sync::execute(
+        sync::Options::default(),
+        GitRepo::open(local.path()).unwrap(),
+    )
+    .unwrap();
+
+    let git_repo = GitRepo::open(local.path()).unwrap();
+    let tracked_commit = tracked(git_repo.find_unpushed_commit("HEAD").unwrap());
+    assert!(tracked_commit.is_in_sync().unwrap());
+}
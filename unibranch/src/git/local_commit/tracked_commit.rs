@@ -1,18 +1,18 @@
+use std::borrow::Cow;
 use std::ffi::CString;
 use std::fmt::Debug;
 
 use anyhow::Context;
 use anyhow::Ok;
-use git2::DiffFormat;
 use git2::MergeOptions;
 use git2::{Branch, Commit, Oid, Repository};
-use indoc::formatdoc;
-use tracing::info;
+use tracing::{debug, info};
 
-use crate::git::SyncState;
+use crate::git::{trailers, SyncState};
 
 use super::CommitMetadata;
 use super::GitRepo;
+use super::MergeConflict;
 use super::UnTrackedCommit;
 
 #[derive(Clone)]
@@ -38,23 +38,172 @@ impl<'repo> TrackedCommit<'repo> {
         }
     }
 
-    pub fn remote_branch(&self) -> anyhow::Result<Branch> {
+    pub fn remote_branch(&self) -> anyhow::Result<Branch<'_>> {
         let remote_branch = self
             .repo
             .find_branch(
-                &format!("origin/{}", self.meta_data.remote_branch_name),
+                &format!(
+                    "{}/{}",
+                    self.git_repo.remote_name(),
+                    self.meta_data.remote_branch_name
+                ),
                 git2::BranchType::Remote,
             )
             .context("Find the remote branch")?;
         Ok(remote_branch)
     }
 
-    pub fn local_branch_head(&self) -> anyhow::Result<Commit> {
+    /// Link to the remote branch on the forge hosting the remote, if it is a known one.
+    pub fn remote_url(&self) -> Option<String> {
+        let remote = self.repo.find_remote(self.git_repo.remote_name()).ok()?;
+        crate::git::remote_url::branch_url(remote.url()?, &self.meta_data.remote_branch_name)
+    }
+
+    /// Link to the PR on the forge hosting the remote, if it has been opened and the forge
+    /// is a known one.
+    pub fn pr_url(&self) -> Option<String> {
+        let remote = self.repo.find_remote(self.git_repo.remote_name()).ok()?;
+        crate::git::remote_url::pr_url(remote.url()?, self.meta_data.pr_number?)
+    }
+
+    /// How many commits the local branch head, the last commit made for the PR, is ahead
+    /// and behind the remote branch as it was last fetched. `None` if the remote branch
+    /// doesn't exist, e.g. because it hasn't been pushed.
+    pub fn ahead_behind(&self) -> anyhow::Result<Option<(usize, usize)>> {
+        let remote_ref = format!(
+            "refs/remotes/{}/{}",
+            self.git_repo.remote_name(),
+            self.meta_data.remote_branch_name
+        );
+        let remote_head = match self.repo.refname_to_id(&remote_ref) {
+            Err(err) if err.code() == git2::ErrorCode::NotFound => return Ok(None),
+            result => result?,
+        };
+        Ok(Some(self.repo.graph_ahead_behind(
+            self.meta_data.remote_commit,
+            remote_head,
+        )?))
+    }
+
+    /// The commit the PR is based on. That is the head of the branch it is stacked on,
+    /// if any, otherwise the base commit of the repo.
+    pub fn pr_base_commit(&self) -> anyhow::Result<Commit<'repo>> {
+        match &self.meta_data.base_branch_name {
+            Some(base_branch_name) => {
+                let remote_ref = format!(
+                    "refs/remotes/{}/{}",
+                    self.git_repo.remote_name(),
+                    base_branch_name
+                );
+                let base_commit = self
+                    .repo
+                    .find_reference(&remote_ref)
+                    .and_then(|r| r.peel_to_commit())
+                    .with_context(|| format!("Find the head of base branch '{}'", remote_ref))?;
+                Ok(base_commit)
+            }
+            None => Ok(self.repo.find_commit(self.git_repo.base_commit()?.id())?),
+        }
+    }
+
+    /// Fail if the remote branch no longer contains the commit that was last pushed or
+    /// fetched, i.e. someone force-pushed a rewritten history to it.
+    pub fn ensure_remote_not_rewritten(&self) -> anyhow::Result<()> {
+        let Some(remote_branch) = self.remote_branch().ok() else {
+            return Ok(());
+        };
+        let remote_head = remote_branch.get().peel_to_commit()?.id();
+        let known_commit = self.meta_data.remote_commit;
+        if remote_head != known_commit
+            && !self.repo.graph_descendant_of(remote_head, known_commit)?
+        {
+            anyhow::bail!(
+                "remote branch was rewritten; re-create or reset '{}' ({} is not an ancestor of {})",
+                self.meta_data.remote_branch_name,
+                known_commit,
+                remote_head
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether the remote branch head already has the changes of the local commit, i.e. a
+    /// sync would not produce anything new to push.
+    pub fn is_in_sync(&self) -> anyhow::Result<bool> {
+        let remote_tree = self.remote_branch()?.get().peel_to_tree()?;
+        let mut complete_index = self.cherry_pick_on_pr_base()?;
+        if complete_index.has_conflicts() {
+            return Ok(false);
+        }
+        Ok(complete_index.write_tree_to(self.repo)? == remote_tree.id())
+    }
+
+    /// What the local commit would change on the remote branch, i.e. the diff from the head
+    /// of the remote branch to the local commit applied on the base of the PR. Empty when
+    /// the commit [is in sync](TrackedCommit::is_in_sync).
+    pub fn diff_against_remote(&self) -> anyhow::Result<git2::Diff<'repo>> {
+        let remote_tree = self.remote_branch()?.get().peel_to_tree()?;
+        let complete_index = self.cherry_pick_on_pr_base()?;
+        if complete_index.has_conflicts() {
+            anyhow::bail!("There are conflicts");
+        }
+        Ok(self
+            .repo
+            .diff_tree_to_index(Some(&remote_tree), Some(&complete_index), None)?)
+    }
+
+    /// The changes of the local commit on top of the base of the PR, in the mbox format of
+    /// `git format-patch` so that it can be applied with `git am`.
+    pub fn format_patch(&self) -> anyhow::Result<Vec<u8>> {
+        let base_tree = self.pr_base_commit()?.tree()?;
+        let complete_index = self.cherry_pick_on_pr_base()?;
+        if complete_index.has_conflicts() {
+            anyhow::bail!("There are conflicts");
+        }
+        let diff = self
+            .repo
+            .diff_tree_to_index(Some(&base_tree), Some(&complete_index), None)?;
+        let message = match &self.meta_data.remote_message {
+            Some(message) => message.as_str(),
+            None => self
+                .as_commit()
+                .message()
+                .context("Commit message is not valid UTF-8")?,
+        };
+        let (summary, body) = message.split_once('\n').unwrap_or((message, ""));
+        let email = git2::Email::from_diff(
+            &diff,
+            1,
+            1,
+            &self.commit.id(),
+            summary,
+            body.trim(),
+            &self.as_commit().author(),
+            &mut git2::EmailCreateOptions::new(),
+        )?;
+        Ok(email.as_slice().to_vec())
+    }
+
+    /// The local commit applied on the base of the PR, with conflicts resolved in favor of
+    /// the local commit.
+    fn cherry_pick_on_pr_base(&self) -> anyhow::Result<git2::Index> {
+        let origin_main_commit = self.pr_base_commit()?;
+        self.repo
+            .cherrypick_commit(
+                self.as_commit(),
+                &origin_main_commit,
+                0,
+                Some(MergeOptions::default().file_favor(git2::FileFavor::Theirs)),
+            )
+            .context("Cherry picking directly on master")
+    }
+
+    pub fn local_branch_head(&self) -> anyhow::Result<Commit<'_>> {
         let commit_meta_data = &self.meta_data;
         Ok(self.repo.find_commit(commit_meta_data.remote_commit)?)
     }
 
-    pub fn as_commit(&self) -> &Commit {
+    pub fn as_commit(&self) -> &Commit<'_> {
         &self.commit
     }
 
@@ -62,7 +211,7 @@ impl<'repo> TrackedCommit<'repo> {
         self.commit
     }
 
-    pub fn meta_data(&self) -> &CommitMetadata {
+    pub fn meta_data(&self) -> &CommitMetadata<'_> {
         &self.meta_data
     }
 
@@ -82,29 +231,31 @@ impl<'repo> TrackedCommit<'repo> {
     //              | /
     //  (origin)    *
     pub fn update_local_branch_head(self) -> anyhow::Result<Self> {
+        self.update_local_branch_head_in(&[])
+    }
+
+    /// Like [`update_local_branch_head`](TrackedCommit::update_local_branch_head), but only
+    /// the changes to files matching `paths` are applied. All changes are applied when
+    /// `paths` is empty.
+    pub fn update_local_branch_head_in(self, paths: &[String]) -> anyhow::Result<Self> {
         let remote_commit = self.repo.find_commit(self.meta_data().remote_commit)?;
 
         info!("Sync with branch head: {}", remote_commit.id());
 
-        let origin_main_commit = self.git_repo.base_commit()?;
-        let complete_index = self
-            .repo
-            .cherrypick_commit(
-                self.as_commit(),
-                &origin_main_commit,
-                0,
-                Some(&MergeOptions::default().file_favor(git2::FileFavor::Theirs)),
-            )
-            .context("Cherry picking directly on master")?;
+        let complete_index = self.cherry_pick_on_pr_base()?;
 
         if complete_index.has_conflicts() {
             anyhow::bail!("There are conflicts");
         }
 
+        let mut diff_options = git2::DiffOptions::new();
+        for path in paths {
+            diff_options.pathspec(path);
+        }
         let patch = self.repo.diff_tree_to_index(
             Some(&remote_commit.tree()?),
             Some(&complete_index),
-            None,
+            Some(&mut diff_options),
         )?;
         // Split the patch
         let mut new_index = self
@@ -113,40 +264,48 @@ impl<'repo> TrackedCommit<'repo> {
             .context("Apply commit patch to old branch")?;
 
         if new_index.has_conflicts() {
+            let mut paths = Vec::new();
             for c in new_index.conflicts()? {
                 let c = c?;
-                println!(
-                    "{} {} {}",
-                    c.our
-                        .as_ref()
-                        .map(|our| String::from_utf8(our.path.clone()).unwrap())
-                        .unwrap_or("NONE".to_string()),
-                    c.their
-                        .map(|our| String::from_utf8(our.path).unwrap())
-                        .unwrap_or("NONE".to_string()),
-                    c.ancestor
-                        .map(|our| String::from_utf8(our.path).unwrap())
-                        .unwrap_or("NONE".to_string())
-                );
+                if let Some(entry) = c.our.or(c.their).or(c.ancestor) {
+                    paths.push(String::from_utf8_lossy(&entry.path).into_owned());
+                }
             }
-            panic!("Conflicts while cherry-picking");
+            anyhow::bail!(
+                "Conflicts while applying {} on top of {}/{} in: {}",
+                self.as_commit().id(),
+                self.git_repo.remote_name(),
+                self.meta_data.remote_branch_name,
+                paths.join(", ")
+            );
         }
         if new_index.is_empty() {
             return std::result::Result::Ok(self);
         }
         let tree_id = new_index.write_tree_to(self.repo)?;
+        // Compare the trees rather than the number of deltas, so that a change of only the
+        // file mode is pushed too. Message changes are handled by `update_remote_message`.
         if tree_id == remote_commit.tree()?.id() {
             return std::result::Result::Ok(self);
         }
         let tree = self.repo.find_tree(tree_id)?;
 
+        // Named so that 'git rebase --autosquash' squashes it into the PR's first commit
+        let subject = match &self.meta_data.remote_message {
+            Some(message) => message.lines().next(),
+            None => self.as_commit().summary(),
+        };
+        let message = self.git_repo.remote_commit_message(
+            &format!("fixup! {}", subject.unwrap_or("")),
+            self.commit.id(),
+            &self.meta_data.remote_branch_name,
+        );
         let new_commit = {
             let signature = self.as_commit().author();
-            self.repo.commit(
-                None,
+            self.git_repo.create_commit(
                 &signature,
                 &signature,
-                "Fixup!",
+                &message,
                 &tree,
                 &[&remote_commit],
             )?
@@ -203,7 +362,7 @@ impl<'repo> TrackedCommit<'repo> {
 
         let new_remote_tree = new_remote_commit.tree()?;
         let diff = self.repo.diff_tree_to_tree(
-            Some(&self.git_repo.base_commit()?.tree()?),
+            Some(&self.pr_base_commit()?.tree()?),
             Some(&new_remote_tree),
             None,
         )?;
@@ -220,11 +379,9 @@ impl<'repo> TrackedCommit<'repo> {
         let tree = self.repo.find_tree(tree_id)?;
 
         let new_commit = {
-            let signature = self.as_commit().author();
-            self.repo.commit(
-                None,
-                &signature,
-                &signature,
+            self.git_repo.create_commit(
+                &self.as_commit().author(),
+                &self.git_repo.rewrite_committer(self.as_commit()),
                 self.commit.message().expect("Not valid UTF-8"),
                 &tree,
                 &[&parent_commit],
@@ -279,14 +436,105 @@ impl<'repo> TrackedCommit<'repo> {
         }
     }
 
+    ///
+    /// Propagate a changed commit message to the remote branch.
+    ///
+    /// The message is compared to the first commit of the remote branch, i.e. the one
+    /// pushed by `create`. If it differs that commit is reworded and the commits on top
+    /// of it are re-created with their trees unchanged.
+    pub fn update_remote_message(self) -> anyhow::Result<Self> {
+        let message = match &self.meta_data.remote_message {
+            Some(message) => message.as_str(),
+            None => self
+                .commit
+                .message()
+                .context("Commit message is not valid UTF-8")?,
+        };
+        let base_commit_id = self.pr_base_commit()?.id();
+        let in_base = |commit: &Commit| -> anyhow::Result<bool> {
+            Ok(commit.id() == base_commit_id
+                || self.repo.graph_descendant_of(base_commit_id, commit.id())?)
+        };
+
+        let head = self.repo.find_commit(self.meta_data.remote_commit)?;
+        if in_base(&head)? {
+            return Ok(self);
+        }
+        let mut branch_commits = vec![head];
+        loop {
+            let parent = branch_commits.last().expect("Never empty").parent(0)?;
+            if in_base(&parent)? {
+                break;
+            }
+            branch_commits.push(parent);
+        }
+
+        let first_commit = branch_commits.pop().expect("Never empty");
+        let first_message = first_commit.message().unwrap_or("");
+        let unchanged = match trailers::strip(first_message) {
+            // The trailers are appended after trimming the message
+            Some(first_message) => first_message == message.trim_end(),
+            None => first_message == message,
+        };
+        if unchanged {
+            return Ok(self);
+        }
+
+        info!("Updating message of remote commit {}", first_commit.id());
+        // Keep the fixups pointing at the first commit for 'git rebase --autosquash'
+        let old_fixup = format!("fixup! {}", first_commit.summary().unwrap_or(""));
+        let new_fixup = format!("fixup! {}", message.lines().next().unwrap_or(""));
+        let parents = first_commit.parents().collect::<Vec<_>>();
+        self.git_repo.warn_on_discarded_signature(&first_commit);
+        let mut new_head = self.git_repo.create_commit(
+            &first_commit.author(),
+            &first_commit.committer(),
+            &self.git_repo.remote_commit_message(
+                message,
+                self.commit.id(),
+                &self.meta_data.remote_branch_name,
+            ),
+            &first_commit.tree()?,
+            &parents.iter().collect::<Vec<_>>(),
+        )?;
+        for commit in branch_commits.iter().rev() {
+            let mut parents = vec![self.repo.find_commit(new_head)?];
+            parents.extend(commit.parents().skip(1));
+            let commit_message = commit.message().unwrap_or("");
+            let commit_message = match commit_message.strip_prefix(&old_fixup) {
+                Some(rest) => format!("{}{}", new_fixup, rest),
+                None => commit_message.to_string(),
+            };
+            self.git_repo.warn_on_discarded_signature(commit);
+            new_head = self.git_repo.create_commit(
+                &commit.author(),
+                &commit.committer(),
+                &commit_message,
+                &commit.tree()?,
+                &parents.iter().collect::<Vec<_>>(),
+            )?;
+        }
+
+        let new_meta = self.meta_data.update_commit(new_head);
+        self.git_repo.save_meta_data(&self.commit, &new_meta)?;
+        Ok(TrackedCommit {
+            repo: self.repo,
+            git_repo: self.git_repo,
+            commit: self.commit,
+            meta_data: new_meta,
+        })
+    }
+
     pub fn cont(
         self,
         new_remote_commit: &Commit<'repo>,
         new_parent: Option<&Commit<'repo>>,
     ) -> anyhow::Result<Self> {
         let new_remote_tree = new_remote_commit.tree()?;
+        // Like in `merge_remote_head`, only the changes of this PR, not those of the PR it
+        // is stacked on
         let diff = self.repo.diff_tree_to_tree(
-            Some(&self.git_repo.base_commit()?.tree()?),
+            Some(&self.pr_base_commit()?.tree()?),
             Some(&new_remote_tree),
             None,
         )?;
@@ -303,11 +551,9 @@ impl<'repo> TrackedCommit<'repo> {
         let tree = self.repo.find_tree(tree_id)?;
 
         let new_commit = {
-            let signature = self.as_commit().author();
-            self.repo.commit(
-                None,
-                &signature,
-                &signature,
+            self.git_repo.create_commit(
+                &self.as_commit().author(),
+                &self.git_repo.rewrite_committer(self.as_commit()),
                 self.commit.message().expect("Not valid UTF-8"),
                 &tree,
                 &[&parent_commit],
@@ -336,23 +582,45 @@ impl<'repo> TrackedCommit<'repo> {
     }
 
     fn merge(&self, commit1: &Commit, commit2: &Commit) -> anyhow::Result<Oid> {
-        let mut merge_index = self.repo.merge_commits(commit1, commit2, None)?;
+        // E.g. the same change was made on both sides, there is nothing to merge
+        if commit1.tree_id() == commit2.tree_id() {
+            info!(
+                "{} and {} have the same changes, nothing to merge",
+                commit1.id(),
+                commit2.id()
+            );
+            return Ok(commit2.id());
+        }
+        let mut merge_index =
+            self.repo
+                .merge_commits(commit1, commit2, Some(&self.git_repo.merge_options()))?;
 
         //self.repo.merge_analysis_for_ref
         if merge_index.has_conflicts() {
             for c in merge_index.conflicts()? {
                 let c = c?;
-                println!("Conclict {:?}", CString::new(c.our.unwrap().path).unwrap())
+                debug!("Conflict {:?}", CString::new(c.our.unwrap().path).unwrap())
             }
 
             self.repo.checkout_tree(commit1.tree()?.as_object(), None)?;
             self.repo
                 .set_head_detached(commit1.id())
                 .context("Detach HEAD")?;
+            // Label the conflict markers written to the working tree with where each side
+            // comes from, instead of HEAD and a commit id
+            let remote_label = format!(
+                "{}/{}",
+                self.git_repo.remote_name(),
+                self.meta_data.remote_branch_name
+            );
             self.repo.merge(
                 &[&self.repo.find_annotated_commit(commit2.id())?],
-                None,
-                None,
+                Some(&mut self.git_repo.merge_options()),
+                Some(
+                    git2::build::CheckoutBuilder::new()
+                        .our_label("local")
+                        .their_label(&remote_label),
+                ),
             )?;
             self.git_repo.save_sync_state(&SyncState {
                 main_commit_id: self.commit.id().into(),
@@ -360,25 +628,19 @@ impl<'repo> TrackedCommit<'repo> {
                 main_commit_parent_id: self.commit.parent(0)?.id().into(),
                 main_branch_name: self.git_repo.current_branch_name.clone(),
             })?;
-            let message = formatdoc! {"
-                    Unable to merge local commit ({local}) with commit from remote ({remote})
-                    Once all the conflicts has been resolved, run 'ubr sync --continue'
-                    ",
-                local = commit1.id(),
-                remote = commit2.id(),
-            };
-            anyhow::bail!(message);
-        }
-        if merge_index.is_empty() {
-            anyhow::bail!("Index is empty");
+            return Err(MergeConflict {
+                local: commit1.id(),
+                remote: commit2.id(),
+            }
+            .into());
         }
         let tree = merge_index
             .write_tree_to(self.repo)
             .context("write index to tree")?;
-        let oid = self.repo.commit(
-            None,
-            &self.repo.signature().context("No signature")?,
-            &self.repo.signature()?,
+        let signature = self.git_repo.committer_signature(commit1)?;
+        let oid = self.git_repo.create_commit(
+            &signature,
+            &signature,
             "Merge",
             &self.repo.find_tree(tree)?,
             &[commit1, commit2],
@@ -387,11 +649,75 @@ impl<'repo> TrackedCommit<'repo> {
         Ok(oid)
     }
 
-    pub(crate) fn untrack(self) -> anyhow::Result<UnTrackedCommit<'repo>> {
-        self.git_repo.remove_meta_data(&self.commit)?;
-        //self.git_repo.remove_remote_branch(&self.meta_data.remote_branch_name)?;
+    /// Move the local commit onto `parent_commit`, keeping its metadata.
+    pub(crate) fn rebase(self, parent_commit: &Commit<'_>) -> anyhow::Result<Self> {
+        let rebased = UnTrackedCommit::new(self.repo, self.git_repo, self.commit)
+            .rebase(parent_commit)?
+            .commit();
+        self.git_repo.save_meta_data(&rebased, &self.meta_data)?;
+        Ok(TrackedCommit::new(
+            self.repo,
+            self.git_repo,
+            rebased,
+            self.meta_data,
+        ))
+    }
+
+    /// Stack the PR on the branch in `base`, or on the base branch when `base` is `None`.
+    /// The PR's branch is replaced by a single commit on its new base. The metadata isn't
+    /// saved until the branch has been pushed.
+    pub(crate) fn set_base(self, base: Option<&CommitMetadata>) -> anyhow::Result<Self> {
+        let new_base = match base {
+            Some(base) => self.repo.find_commit(base.remote_commit)?,
+            None => self.repo.find_commit(self.git_repo.base_commit()?.id())?,
+        };
+        let mut index = self
+            .repo
+            .cherrypick_commit(self.as_commit(), &new_base, 0, None)?;
+        if index.has_conflicts() {
+            anyhow::bail!(
+                "Conflicts while moving {} onto {}",
+                self.commit.id(),
+                new_base.id()
+            );
+        }
+        let tree_id = index.write_tree_to(self.repo)?;
+        let tree = self.repo.find_tree(tree_id)?;
+        let message = match &self.meta_data.remote_message {
+            Some(message) => message.as_str(),
+            None => self
+                .commit
+                .message()
+                .context("Commit message is not valid UTF-8")?,
+        };
+        let remote_commit = {
+            let signature = self.as_commit().author();
+            self.git_repo.create_commit(
+                &signature,
+                &signature,
+                &self.git_repo.remote_commit_message(
+                    message,
+                    self.commit.id(),
+                    &self.meta_data.remote_branch_name,
+                ),
+                &tree,
+                &[&new_base],
+            )?
+        };
+        let mut meta_data = self.meta_data.update_commit(remote_commit);
+        meta_data.base_branch_name = base.map(|b| Cow::Owned(b.remote_branch_name.to_string()));
+        Ok(TrackedCommit {
+            repo: self.repo,
+            git_repo: self.git_repo,
+            commit: self.commit,
+            meta_data,
+        })
+    }
 
-        Ok(UnTrackedCommit::new(self.repo, self.git_repo, self.commit))
+    /// The commit without its metadata. The note is left as it is, so that it is only
+    /// replaced when the commit has been tracked again.
+    pub(crate) fn into_untracked(self) -> UnTrackedCommit<'repo> {
+        UnTrackedCommit::new(self.repo, self.git_repo, self.commit)
     }
 }
 
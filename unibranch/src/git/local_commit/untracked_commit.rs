@@ -3,7 +3,10 @@ use std::fmt::Debug;
 use anyhow::Context;
 use git2::{Commit, Repository};
 
-use crate::git::{local_commit::CommitMetadata, GitRepo};
+use crate::{
+    config::BranchNaming,
+    git::{local_commit::CommitMetadata, GitRepo},
+};
 
 use super::TrackedCommit;
 
@@ -21,7 +24,7 @@ impl<'repo> UnTrackedCommit<'repo> {
             commit,
         }
     }
-    pub fn as_commit(&self) -> &Commit {
+    pub fn as_commit(&self) -> &Commit<'_> {
         &self.commit
     }
 
@@ -29,24 +32,37 @@ impl<'repo> UnTrackedCommit<'repo> {
         self.commit
     }
 
+    pub fn is_ignored(&self) -> bool {
+        self.git_repo.is_ignored(&self.commit)
+    }
+
     pub(crate) fn rebase(self, parent_commit: &Commit<'_>) -> anyhow::Result<Self> {
         let mut index = self
             .repo
             .cherrypick_commit(self.as_commit(), parent_commit, 0, None)?;
+        if index.has_conflicts() {
+            anyhow::bail!(
+                "Conflicts while moving {} onto {}",
+                self.commit.id(),
+                parent_commit.id()
+            );
+        }
         let new_commit = {
-            let signature = self.as_commit().author();
             let tree_id = index.write_tree_to(self.repo)?;
             let tree = self.repo.find_tree(tree_id)?;
-            let new_commit_id = self.repo.commit(
-                None,
-                &signature,
-                &signature,
+            let new_commit_id = self.git_repo.create_commit(
+                &self.as_commit().author(),
+                &self.git_repo.rewrite_committer(self.as_commit()),
                 self.commit.message().expect("Not valid UTF-8 message"),
                 &tree,
                 &[parent_commit],
             )?;
             self.repo.find_commit(new_commit_id)?
         };
+        // A commit ignored by its trailer still has it, only copy the note
+        if self.git_repo.has_ignore_note(self.as_commit()) {
+            self.git_repo.set_ignored(&new_commit, true)?;
+        }
         Ok(UnTrackedCommit {
             repo: self.repo,
             git_repo: self.git_repo,
@@ -54,14 +70,27 @@ impl<'repo> UnTrackedCommit<'repo> {
         })
     }
 
-    pub(crate) fn track(self) -> anyhow::Result<TrackedCommit<'repo>> {
+    /// Track the commit with a PR stacked on the branch in `base`, or on the base
+    /// branch when `base` is `None`. The PR's commit gets `message` instead of the
+    /// commit's own message, and `author` instead of the commit's author, if given. The
+    /// metadata isn't saved until the branch has been pushed, see
+    /// [`GitRepo::finish_create`](crate::git::GitRepo::finish_create).
+    pub(crate) fn track(
+        self,
+        base: Option<&CommitMetadata>,
+        message: Option<&str>,
+        author: Option<&git2::Signature>,
+    ) -> anyhow::Result<TrackedCommit<'repo>> {
         let commit_msg = self
             .as_commit()
             .message()
             .context("Commit message is not valid UTF-8")?;
 
-        let branch_name = self.generate_remote_branch_name(commit_msg)?;
-        let origin_main_commit = self.git_repo.base_commit()?;
+        let branch_name = self.generate_remote_branch_name(commit_msg);
+        let origin_main_commit = match base {
+            Some(base) => self.repo.find_commit(base.remote_commit)?,
+            None => self.repo.find_commit(self.git_repo.base_commit()?.id())?,
+        };
         let mut complete_index = self
             .repo
             .cherrypick_commit(self.as_commit(), &origin_main_commit, 0, None)
@@ -76,11 +105,14 @@ impl<'repo> UnTrackedCommit<'repo> {
 
         let remote_commit = {
             let signature = self.as_commit().author();
-            self.repo.commit(
-                None,
+            self.git_repo.create_commit(
+                author.unwrap_or(&signature),
                 &signature,
-                &signature,
-                commit_msg,
+                &self.git_repo.remote_commit_message(
+                    message.unwrap_or(commit_msg),
+                    self.commit.id(),
+                    &branch_name,
+                ),
                 &tree,
                 &[&origin_main_commit],
             )?
@@ -88,10 +120,11 @@ impl<'repo> UnTrackedCommit<'repo> {
 
         //Create meta_data
         let meta_data = CommitMetadata {
-            remote_branch_name: std::borrow::Cow::Owned(branch_name),
-            remote_commit,
+            base_branch_name: base
+                .map(|b| std::borrow::Cow::Owned(b.remote_branch_name.to_string())),
+            remote_message: message.map(str::to_string),
+            ..CommitMetadata::new(branch_name, remote_commit)
         };
-        self.git_repo.save_meta_data(self.as_commit(), &meta_data)?;
         Ok(TrackedCommit::new(
             self.repo,
             self.git_repo,
@@ -100,20 +133,39 @@ impl<'repo> UnTrackedCommit<'repo> {
         ))
     }
 
-    fn generate_remote_branch_name(&self, commit_msg: &str) -> anyhow::Result<String> {
-        let branch_name = {
-            let title = commit_msg
-                .lines()
-                .next()
-                .expect("Must have at least one line");
-            title
-                .replace(
-                    |c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'),
-                    "-",
-                )
-                .to_ascii_lowercase()
-        };
-        Ok(branch_name)
+    /// The name of the remote branch of a new PR for the commit, with the configured
+    /// `branch-prefix`. The prefix is part of the name stored in the metadata, so it is
+    /// only added here.
+    fn generate_remote_branch_name(&self, commit_msg: &str) -> String {
+        let name = self.remote_branch_name_without_prefix(commit_msg);
+        match &self.git_repo.config().branch_prefix {
+            Some(prefix) => format!("{}{}", prefix, name),
+            None => name,
+        }
+    }
+
+    fn remote_branch_name_without_prefix(&self, commit_msg: &str) -> String {
+        let id = self.commit.id().to_string();
+        let commit_id_name = format!("ubr/{}", &id[..7]);
+        if self.git_repo.config().branch_naming == BranchNaming::CommitId {
+            return commit_id_name;
+        }
+        // Characters that aren't ASCII, like emoji, are dropped rather than turned into
+        // dashes. A subject without any ASCII letters or digits falls back to the commit id.
+        let title = crate::git::subject(commit_msg)
+            .chars()
+            .filter(char::is_ascii)
+            .collect::<String>();
+        let title = title.trim();
+        if !title.chars().any(|c| c.is_ascii_alphanumeric()) {
+            return commit_id_name;
+        }
+        title
+            .replace(
+                |c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'),
+                "-",
+            )
+            .to_ascii_lowercase()
     }
 }
 
@@ -0,0 +1,96 @@
+//! `.ubr/LOCK` in the main working tree, held by the commands that update the notes or the
+//! branches so that two `ubr` processes don't overwrite each other's changes, like git's
+//! `index.lock`.
+//!
+//! The lock holds the PID of its process. A lock whose process is no longer running was
+//! left behind by a process that crashed, and is taken over.
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Context;
+
+pub const LOCK_FILE: &str = ".ubr/LOCK";
+
+/// How long a lock without a PID is given to have it written before it is stale
+pub const WRITE_GRACE: Duration = Duration::from_secs(10);
+
+/// Released when dropped.
+#[derive(Debug)]
+pub struct Lock {
+    path: PathBuf,
+}
+
+impl Lock {
+    pub(crate) fn acquire(root: &Path) -> anyhow::Result<Self> {
+        let path = root.join(LOCK_FILE);
+        std::fs::create_dir_all(path.parent().expect("Has a parent"))?;
+        match create(&path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                if !is_stale(&path) {
+                    anyhow::bail!(
+                        "another ubr process is running, remove {} if it has crashed",
+                        path.display()
+                    );
+                }
+                tracing::warn!("Taking over stale lock {}", path.display());
+                std::fs::remove_file(&path)?;
+                create(&path).with_context(|| format!("Creating {}", path.display()))?;
+            }
+            Err(err) => return Err(err).with_context(|| format!("Creating {}", path.display())),
+        }
+        Ok(Lock { path })
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+        // Only succeeds if there is nothing else (like a config file) left in the directory
+        let _ = std::fs::remove_dir(self.path.parent().expect("Has a parent"));
+    }
+}
+
+fn create(path: &Path) -> std::io::Result<()> {
+    let mut file = std::fs::File::create_new(path)?;
+    writeln!(file, "{}", std::process::id())
+}
+
+/// Whether the process that holds the lock at `path` is no longer running.
+pub(crate) fn is_stale(path: &Path) -> bool {
+    match std::fs::read_to_string(path) {
+        Ok(content) => match content.trim().parse() {
+            Ok(pid) => !is_running(pid),
+            Err(_) => std::fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|age| age > WRITE_GRACE),
+        },
+        Err(_) => false,
+    }
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    let proc = Path::new("/proc");
+    if proc.is_dir() {
+        return proc.join(pid.to_string()).exists();
+    }
+    // Only checks whether the process could be signalled
+    std::process::Command::new("kill")
+        .args(["-0", &pid.to_string()])
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// There is no portable way to check, so the lock is only removed by `clean --force`
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    true
+}
@@ -1,20 +1,34 @@
-use std::path::{Path, PathBuf};
+use std::{
+    cell::Cell,
+    path::{Path, PathBuf},
+};
 
 use anyhow::Context;
 use clap::builder::OsStr;
 use git2::{Commit, Repository, RepositoryOpenFlags};
 use serde::{Deserialize, Serialize};
 
+use crate::config::{Config, ForgeKind, StrategyOption};
+
 use self::{
+    hooks::Hook,
     local_commit::{CommitMetadata, MainCommit, TrackedCommit},
-    remote_command::RemoteGitCommand,
+    remote_command::{RemoteGitCommand, RemoteOptions, Retry},
 };
 
+pub mod hooks;
 pub mod local_commit;
+pub mod lock;
 mod oid;
 pub mod remote_command;
+mod remote_url;
+mod signing;
+pub mod trailers;
 pub use oid::Oid;
 
+/// Note of a commit marked with `ubr ignore`
+const IGNORE_NOTE: &str = "ignore: true";
+
 pub enum CommandOption {
     Default,
     Silent,
@@ -29,12 +43,28 @@ pub struct SyncState {
     pub main_branch_name: String,
 }
 
+/// A `ubr rebase` that stopped on conflicts, stored in `.ubr/REBASE`.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug)]
+pub struct RebaseState {
+    pub main_branch_name: String,
+    /// The commit whose conflicts are left in the working tree
+    pub commit_id: Oid,
+    /// The commits after it, which still have to be moved
+    pub remaining: Vec<Oid>,
+}
+
 pub struct GitRepo {
     repo: git2::Repository,
     pub current_branch_name: String,
+    base_branch_name: String,
     path: PathBuf,
+    main_worktree: PathBuf,
     git_command_option: CommandOption,
     sync_state: Option<SyncState>,
+    rebase_state: Option<RebaseState>,
+    config: Config,
+    base_commit_id: Cell<Option<git2::Oid>>,
+    since: Option<git2::Oid>,
 }
 
 impl GitRepo {
@@ -49,19 +79,98 @@ impl GitRepo {
     where
         P: AsRef<Path>,
     {
-        let repo = Repository::open_ext(
-            path.as_ref(),
-            RepositoryOpenFlags::empty(),
-            &[] as &[&OsStr],
-        )
-        .context("Opening git repository")?;
-        if let Some(state) = GitRepo::try_load_sync_state(path.as_ref()) {
+        let config = Config::load(GitRepo::main_worktree(path.as_ref())?)?;
+        GitRepo::open_with_config(path, remote, config)
+    }
+
+    /// The root of the main working tree, which holds the `.ubr/` config and hooks shared
+    /// by all the worktrees of the repository (see `git worktree`).
+    pub fn main_worktree<P>(path: P) -> anyhow::Result<PathBuf>
+    where
+        P: AsRef<Path>,
+    {
+        let repo = GitRepo::open_repository(path.as_ref())?;
+        Ok(GitRepo::main_worktree_of(&repo, path.as_ref()))
+    }
+
+    /// Open the repository that `path` is in, which may be anywhere in a working tree or its
+    /// git dir. The search for the git dir continues across file system boundaries, such as
+    /// bind mounts in containers. Bare repositories are rejected, ubr needs a working tree.
+    pub(crate) fn open_repository(path: &Path) -> anyhow::Result<Repository> {
+        let repo = Repository::open_ext(path, RepositoryOpenFlags::CROSS_FS, &[] as &[&OsStr])
+            .context("Opening git repository")?;
+        if repo.is_bare() {
+            anyhow::bail!("ubr cannot run in a bare repository");
+        }
+        Ok(repo)
+    }
+
+    /// The git dir shared by all worktrees. A linked worktree points to it with the
+    /// `commondir` file in its own git dir.
+    fn common_dir(repo: &Repository) -> PathBuf {
+        std::fs::read_to_string(repo.path().join("commondir"))
+            .map(|dir| repo.path().join(dir.trim()))
+            .unwrap_or_else(|_| repo.path().to_path_buf())
+    }
+
+    fn main_worktree_of(repo: &Repository, path: &Path) -> PathBuf {
+        let workdir = if repo.is_worktree() {
+            Repository::open(GitRepo::common_dir(repo))
+                .ok()
+                .and_then(|main| main.workdir().map(Path::to_path_buf))
+        } else {
+            None
+        };
+        workdir
+            .or_else(|| repo.workdir().map(Path::to_path_buf))
+            .unwrap_or_else(|| path.to_path_buf())
+    }
+
+    pub fn open_with_config<P>(
+        path: P,
+        remote: CommandOption,
+        config: Config,
+    ) -> anyhow::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        if let Some(notes_ref) = &config.notes_ref {
+            if !notes_ref.starts_with("refs/notes/") {
+                anyhow::bail!("notes-ref '{}' must start with 'refs/notes/'", notes_ref);
+            }
+        }
+        let repo = GitRepo::open_repository(path.as_ref())?;
+        let main_worktree = GitRepo::main_worktree_of(&repo, path.as_ref());
+        // The root of the working tree, also when opened from a subdirectory or the git dir
+        let path = repo
+            .workdir()
+            .map_or_else(|| path.as_ref().to_path_buf(), Path::to_path_buf);
+        let sync_state = GitRepo::try_load_sync_state(&path);
+        let rebase_state = GitRepo::try_load_rebase_state(&path);
+        // HEAD is detached while a sync or rebase is stopped on conflicts
+        let stopped_on_branch = sync_state
+            .as_ref()
+            .map(|state| state.main_branch_name.clone())
+            .or_else(|| {
+                rebase_state
+                    .as_ref()
+                    .map(|state| state.main_branch_name.clone())
+            });
+        if let Some(current_branch_name) = stopped_on_branch {
+            let base_branch_name =
+                GitRepo::detect_base_branch(&repo, &config, &current_branch_name);
             return Ok(GitRepo {
                 repo,
-                current_branch_name: state.main_branch_name.clone(),
-                path: path.as_ref().into(),
+                current_branch_name,
+                base_branch_name,
+                path,
+                main_worktree,
                 git_command_option: remote,
-                sync_state: Some(state),
+                sync_state,
+                rebase_state,
+                config,
+                base_commit_id: Cell::new(None),
+                since: None,
             });
             //anyhow::bail!("{:?}", state);
         }
@@ -74,59 +183,353 @@ impl GitRepo {
         let current_branch_name = current_branch_name
             .strip_prefix("refs/heads/")
             .expect("Unknown branch format");
-        let current_branch_name = current_branch_name.into();
+        let current_branch_name: String = current_branch_name.into();
 
         drop(head);
+        let base_branch_name = GitRepo::detect_base_branch(&repo, &config, &current_branch_name);
 
-        let mut config = repo.config()?;
-        config.set_str("notes.rewriteRef", "refs/notes/*")?;
+        // Covers both the default notes ref and a custom 'notes-ref' from the config
+        let mut git_config = repo.config()?;
+        git_config.set_str("notes.rewriteRef", "refs/notes/*")?;
 
-        {
-            std::fs::write(repo.path().join("info/exclude"), ".ubr")?;
-        }
+        // Linked worktrees have their own git dir, but git only reads the exclude file in
+        // the common one
+        GitRepo::exclude_ubr_dir(&GitRepo::common_dir(&repo).join("info"))?;
         Ok(GitRepo {
             repo,
-            path: path.as_ref().into(),
+            path,
+            main_worktree,
             current_branch_name,
+            base_branch_name,
             git_command_option: remote,
             sync_state: None,
+            rebase_state: None,
+            config,
+            base_commit_id: Cell::new(None),
+            since: None,
+        })
+    }
+
+    /// The branch on the remote that the stack is based on: `base` from the config, or else
+    /// the current branch if the remote has it, or else the remote's default branch that
+    /// `refs/remotes/<remote>/HEAD` points to. Falls back to the current branch when
+    /// nothing else is known.
+    fn detect_base_branch(repo: &Repository, config: &Config, current_branch: &str) -> String {
+        if let Some(base) = &config.base {
+            return base.clone();
+        }
+        let remote_refs = format!("refs/remotes/{}/", config.remote);
+        if repo
+            .refname_to_id(&format!("{}{}", remote_refs, current_branch))
+            .is_ok()
+        {
+            return current_branch.to_string();
+        }
+        repo.find_reference(&format!("{}HEAD", remote_refs))
+            .ok()
+            .and_then(|head| {
+                head.symbolic_target()?
+                    .strip_prefix(&remote_refs)
+                    .map(str::to_string)
+            })
+            .unwrap_or_else(|| current_branch.to_string())
+    }
+
+    /// Add `.ubr` to the `exclude` file in `info_dir` unless it is already there. The line
+    /// is appended rather than the file rewritten, so that the user's own entries are never
+    /// lost, also when another ubr process opens the repository at the same time.
+    fn exclude_ubr_dir(info_dir: &Path) -> anyhow::Result<()> {
+        let path = info_dir.join("exclude");
+        let exclude = std::fs::read_to_string(&path).unwrap_or_default();
+        if exclude.lines().any(|line| line.trim() == ".ubr") {
+            return Ok(());
+        }
+        std::fs::create_dir_all(info_dir)?;
+        let separator = if exclude.is_empty() || exclude.ends_with('\n') {
+            ""
+        } else {
+            "\n"
+        };
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("Opening {}", path.display()))?;
+        // A single write, so that concurrent appends don't interleave
+        std::io::Write::write_all(&mut file, format!("{}.ubr\n", separator).as_bytes())?;
+        Ok(())
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn notes_ref(&self) -> Option<&str> {
+        self.config.notes_ref.as_deref()
+    }
+
+    pub fn remote_name(&self) -> &str {
+        &self.config.remote
+    }
+
+    /// The branch on the remote that the stack is based on.
+    pub fn base_branch_name(&self) -> &str {
+        &self.base_branch_name
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The editor git would use: `GIT_EDITOR`, `core.editor`, `VISUAL`, `EDITOR` and
+    /// finally `vi`.
+    pub fn editor(&self) -> String {
+        std::env::var("GIT_EDITOR")
+            .ok()
+            .or_else(|| {
+                self.repo
+                    .config()
+                    .ok()
+                    .and_then(|config| config.get_string("core.editor").ok())
+            })
+            .or_else(|| std::env::var("VISUAL").ok())
+            .or_else(|| std::env::var("EDITOR").ok())
+            .filter(|editor| !editor.is_empty())
+            .unwrap_or_else(|| "vi".to_string())
+    }
+
+    /// The forge from the config, or detected from the URL of the remote.
+    pub fn forge_kind(&self) -> Option<ForgeKind> {
+        self.config.forge.or_else(|| {
+            let remote = self.repo.find_remote(self.remote_name()).ok()?;
+            remote_url::detect_forge(remote.url()?, &self.config.forge_hosts)
         })
     }
 
-    pub fn remote(&self) -> RemoteGitCommand {
+    /// The host of the remote and the path of the repository on it, for the forge API.
+    #[cfg(feature = "gitlab")]
+    pub(crate) fn forge_repo(&self) -> Option<(String, String)> {
+        let remote = self.repo.find_remote(self.remote_name()).ok()?;
+        let (host, path) = remote_url::repo_path(remote.url()?)?;
+        Some((host.to_string(), path.to_string()))
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        matches!(self.git_command_option, CommandOption::DryRun)
+    }
+
+    pub fn remote(&self) -> RemoteGitCommand<'_> {
+        let options = RemoteOptions {
+            path: &self.path,
+            remote_name: &self.config.remote,
+            retry: Retry {
+                retries: self.config.retries,
+                delay: std::time::Duration::from_millis(self.config.retry_delay_ms),
+            },
+            ssh_command: self.config.ssh_command.as_deref(),
+            env: &self.config.remote_env,
+        };
         match self.git_command_option {
-            CommandOption::Default => RemoteGitCommand::Default(&self.path),
-            CommandOption::Silent => RemoteGitCommand::Silent(&self.path),
-            CommandOption::DryRun => RemoteGitCommand::DryRun(&self.path),
+            CommandOption::Default => RemoteGitCommand::Default(options),
+            CommandOption::Silent => RemoteGitCommand::Silent(options),
+            CommandOption::DryRun => RemoteGitCommand::DryRun(options),
+        }
+    }
+
+    /// The commit the stack is based on. It is resolved once and then cached, call
+    /// [`GitRepo::invalidate_base`] when the remote refs have changed.
+    pub fn base_commit(&self) -> anyhow::Result<Commit<'_>> {
+        if let Some(id) = self.base_commit_id.get() {
+            return Ok(self.repo.find_commit(id)?);
+        }
+        let commit = self.resolve_base_commit()?;
+        self.base_commit_id.set(Some(commit.id()));
+        Ok(commit)
+    }
+
+    /// Only consider the commits after `rev` to be part of the stack, instead of all
+    /// commits after the base commit.
+    pub fn set_since(&mut self, rev: &str) -> anyhow::Result<()> {
+        let since = self
+            .repo
+            .revparse_single(rev)
+            .and_then(|obj| obj.peel_to_commit())
+            .with_context(|| format!("Bad revision '{}'", rev))?;
+        let head = self.head()?.id();
+        if since.id() != head && !self.repo.graph_descendant_of(head, since.id())? {
+            anyhow::bail!("'{}' is not an ancestor of HEAD", rev);
         }
+        self.since = Some(since.id());
+        Ok(())
+    }
+
+    /// The commit the stack starts from, see [`GitRepo::set_since`].
+    pub fn stack_base(&self) -> anyhow::Result<Commit<'_>> {
+        match self.since {
+            Some(since) => Ok(self.repo.find_commit(since)?),
+            None => self.base_commit(),
+        }
+    }
+
+    /// Where the current branch forked from the base commit. Same as the base commit unless
+    /// the base branch has moved on since the stack was last synced.
+    pub fn merge_base_with_upstream(&self) -> anyhow::Result<git2::Oid> {
+        let base = self.base_commit()?.id();
+        // Not HEAD, which is detached while a sync or rebase is stopped on conflicts
+        let branch_head = self
+            .repo
+            .find_branch(&self.current_branch_name, git2::BranchType::Local)?
+            .into_reference()
+            .peel_to_commit()?;
+        self.repo
+            .merge_base(branch_head.id(), base)
+            .with_context(|| format!("Find merge base of HEAD and {}", base))
+    }
+
+    pub fn invalidate_base(&self) {
+        self.base_commit_id.set(None);
+    }
+
+    /// Update the remote tracking refs, `refs/remotes/<remote>/*`, from the remote.
+    pub fn fetch(&self) -> anyhow::Result<()> {
+        self.remote().fetch()?;
+        self.invalidate_base();
+        Ok(())
     }
 
-    pub fn base_commit(&self) -> anyhow::Result<Commit> {
-        let remote_ref = format!("refs/remotes/origin/{}", self.current_branch_name);
-        let base_commit_id = self.repo.refname_to_id(&remote_ref)?;
+    /// Update the remote tracking branch of `branch_name` only.
+    pub fn fetch_branch(&self, branch_name: &str) -> anyhow::Result<()> {
+        self.remote().fetch_branch(branch_name)
+    }
+
+    fn resolve_base_commit(&self) -> anyhow::Result<Commit<'_>> {
+        let remote_ref = format!(
+            "refs/remotes/{}/{}",
+            self.remote_name(),
+            self.base_branch_name()
+        );
+        let base_commit_id = match self.repo.refname_to_id(&remote_ref) {
+            Ok(id) => id,
+            Err(err) if err.code() == git2::ErrorCode::NotFound => {
+                self.default_branch_merge_base()?
+            }
+            Err(err) => return Err(err.into()),
+        };
         Ok(self.repo.find_commit(base_commit_id)?)
     }
 
-    pub fn head(&self) -> anyhow::Result<Commit> {
+    /// Merge base of HEAD and the remote's default branch, used when the base branch
+    /// has never been pushed to the remote.
+    fn default_branch_merge_base(&self) -> anyhow::Result<git2::Oid> {
+        let default_ref = format!("refs/remotes/{}/HEAD", self.remote_name());
+        let Ok(default_branch_head) = self.repo.refname_to_id(&default_ref) else {
+            anyhow::bail!(
+                "Upstream for '{}' not found; push it or pass --base",
+                self.base_branch_name()
+            );
+        };
+        let merge_base = self
+            .repo
+            .merge_base(self.head()?.id(), default_branch_head)
+            .context("Find merge base with the default branch")?;
+        tracing::debug!(
+            "{}/{} not found, using merge base {} with {}",
+            self.remote_name(),
+            self.base_branch_name(),
+            merge_base,
+            default_ref
+        );
+        Ok(merge_base)
+    }
+
+    /// Keep other `ubr` processes from changing the repository until the returned lock is
+    /// dropped, see [`lock`].
+    pub fn lock(&self) -> anyhow::Result<lock::Lock> {
+        lock::Lock::acquire(&self.main_worktree)
+    }
+
+    /// The root of the main working tree, see [`GitRepo::main_worktree`].
+    pub fn main_worktree_path(&self) -> &Path {
+        &self.main_worktree
+    }
+
+    /// Whether git has a merge, cherry-pick or similar in progress, e.g. because a sync
+    /// or a rebase stopped on conflicts.
+    pub fn operation_in_progress(&self) -> bool {
+        self.repo.state() != git2::RepositoryState::Clean
+    }
+
+    /// Run `hook` from `.ubr/hooks/` for the given branch, see [`hooks`].
+    pub fn run_hook(&self, hook: Hook, meta_data: &CommitMetadata) -> anyhow::Result<()> {
+        if self.is_dry_run() {
+            return Ok(());
+        }
+        hooks::run(&self.main_worktree, &self.path, hook, meta_data)
+    }
+
+    /// The changes `commit_id` introduces on top of the base commit.
+    pub fn diff_from_base(&self, commit_id: git2::Oid) -> anyhow::Result<git2::Diff<'_>> {
+        let base_tree = self.base_commit()?.tree()?;
+        let tree = self.repo.find_commit(commit_id)?.tree()?;
+        Ok(self
+            .repo
+            .diff_tree_to_tree(Some(&base_tree), Some(&tree), None)?)
+    }
+
+    /// Whether `ancestor` is reachable from `commit`.
+    pub fn is_ancestor(&self, ancestor: git2::Oid, commit: git2::Oid) -> anyhow::Result<bool> {
+        Ok(self.repo.graph_descendant_of(commit, ancestor)?)
+    }
+
+    pub fn head(&self) -> anyhow::Result<Commit<'_>> {
         Ok(self.repo.head()?.peel_to_commit()?)
     }
 
-    pub fn find_head_of_remote_branch(&self, branch_name: &str) -> Option<Commit> {
+    /// The subject of the commit at HEAD, see [`commit_subject`].
+    pub fn head_subject(&self) -> anyhow::Result<String> {
+        Ok(commit_subject(&self.head()?))
+    }
+
+    /// Whether `branch_name` exists on the remote, as last fetched. Unlike
+    /// [`GitRepo::find_head_of_remote_branch`] a branch that doesn't point to a commit
+    /// still exists.
+    pub fn remote_branch_exists(&self, branch_name: &str) -> bool {
         self.repo
-            .find_branch(&format!("origin/{}", branch_name), git2::BranchType::Remote)
+            .find_branch(
+                &format!("{}/{}", self.remote_name(), branch_name),
+                git2::BranchType::Remote,
+            )
+            .is_ok()
+    }
+
+    pub fn find_head_of_remote_branch(&self, branch_name: &str) -> Option<Commit<'_>> {
+        self.repo
+            .find_branch(
+                &format!("{}/{}", self.remote_name(), branch_name),
+                git2::BranchType::Remote,
+            )
             .ok()
             .and_then(|b| b.get().peel_to_commit().ok())
     }
 
-    pub fn find_unpushed_commit(&self, commit_ref: &str) -> anyhow::Result<MainCommit> {
+    pub fn find_unpushed_commit(&self, commit_ref: &str) -> anyhow::Result<MainCommit<'_>> {
         let (obj, _) = self
             .repo
             .revparse_ext(commit_ref)
             .with_context(|| format!("Bad revision '{}'", commit_ref))?;
-        let commit = obj.peel_to_commit()?;
-        if !self
-            .repo
-            .graph_descendant_of(commit.id(), self.base_commit()?.id())?
+        let commit = obj.peel_to_commit().with_context(|| {
+            format!(
+                "'{}' resolves to {} {}, not a commit",
+                commit_ref,
+                obj.kind().map_or("unknown object", |kind| kind.str()),
+                obj.id()
+            )
+        })?;
+        let base = self.base_commit()?.id();
+        let merge_base = self.merge_base_with_upstream()?;
+        if !self.repo.graph_descendant_of(commit.id(), merge_base)?
+            || commit.id() == base
+            || self.repo.graph_descendant_of(base, commit.id())?
         {
             anyhow::bail!(format!(
                 "Commit {} is already pushed to the remote",
@@ -137,21 +540,222 @@ impl GitRepo {
         Ok(MainCommit::new(self, &self.repo, commit)?)
     }
 
-    pub fn save_meta_data(
+    /// The commits in a range like `base..HEAD`, oldest first. All of them must be
+    /// unpushed, see [`GitRepo::find_unpushed_commit`].
+    pub fn commits_in_range(&self, range: &str) -> anyhow::Result<Vec<git2::Oid>> {
+        let spec = self
+            .repo
+            .revparse(range)
+            .with_context(|| format!("Bad revision '{}'", range))?;
+        let (Some(from), Some(to)) = (spec.from(), spec.to()) else {
+            anyhow::bail!("'{}' is not a range", range);
+        };
+        if spec.mode().contains(git2::RevparseMode::MERGE_BASE) {
+            anyhow::bail!("Symmetric ranges like '{}' are not supported", range);
+        }
+        let mut walk = self.repo.revwalk()?;
+        walk.set_sorting(git2::Sort::TOPOLOGICAL.union(git2::Sort::REVERSE))?;
+        walk.push(to.id())?;
+        walk.hide(from.id())?;
+        let commits = walk.collect::<Result<Vec<_>, _>>()?;
+        for id in &commits {
+            self.find_unpushed_commit(&id.to_string())?;
+        }
+        Ok(commits)
+    }
+
+    /// Signature for the commits and notes created by ubr. Uses `user.name` and
+    /// `user.email` from the git config, falling back to the committer of `commit` when
+    /// they are not configured.
+    ///
+    /// The time can be fixed with `GIT_COMMITTER_DATE` (`[@]<unix seconds> [+-hhmm]`).
+    pub fn committer_signature(
         &self,
         commit: &Commit,
-        meta_data: &CommitMetadata,
-    ) -> Result<(), git2::Error> {
-        let committer = self.repo.signature().or_else(|_| {
+    ) -> Result<git2::Signature<'static>, git2::Error> {
+        let signature = self.repo.signature().or_else(|_| {
             git2::Signature::now(
                 String::from_utf8_lossy(commit.committer().name_bytes()).as_ref(),
                 String::from_utf8_lossy(commit.committer().email_bytes()).as_ref(),
             )
         })?;
+        match std::env::var("GIT_COMMITTER_DATE")
+            .ok()
+            .and_then(|date| parse_git_date(&date))
+        {
+            Some(time) => git2::Signature::new(
+                String::from_utf8_lossy(signature.name_bytes()).as_ref(),
+                String::from_utf8_lossy(signature.email_bytes()).as_ref(),
+                &time,
+            ),
+            None => Ok(signature),
+        }
+    }
+
+    /// Point the tracking config of `branch_name` at the branch with the same name on the
+    /// remote, so that git knows where to fetch the PR branch from even though there is no
+    /// local branch. Writes `branch.<branch_name>.remote` and `branch.<branch_name>.merge`.
+    pub fn set_upstream(&self, branch_name: &str) -> anyhow::Result<()> {
+        if self.is_dry_run() {
+            return Ok(());
+        }
+        let mut config = self.repo.config()?;
+        config.set_str(
+            &format!("branch.{}.remote", branch_name),
+            self.remote_name(),
+        )?;
+        config.set_str(
+            &format!("branch.{}.merge", branch_name),
+            &format!("refs/heads/{}", branch_name),
+        )?;
+        Ok(())
+    }
+
+    /// Committer of a rewrite of the local `commit`. That is the author of the commit,
+    /// unless `keep-committer-date` is set.
+    pub(crate) fn rewrite_committer<'a>(&self, commit: &'a Commit) -> git2::Signature<'a> {
+        if self.config.keep_committer_date {
+            commit.committer()
+        } else {
+            commit.author()
+        }
+    }
+
+    /// Message of a commit pushed to `branch_name` for the local commit `source`, with the
+    /// [`trailers`] appended if they are enabled.
+    pub(crate) fn remote_commit_message(
+        &self,
+        message: &str,
+        source: git2::Oid,
+        branch_name: &str,
+    ) -> String {
+        if self.config.trailers {
+            trailers::append(message, source, branch_name)
+        } else {
+            message.to_string()
+        }
+    }
+
+    /// Metadata for `commit` recovered from the [`trailers`] of the remote branch heads,
+    /// `None` if no branch was last pushed from it.
+    pub fn meta_data_from_trailers(
+        &self,
+        commit: git2::Oid,
+    ) -> anyhow::Result<Option<CommitMetadata<'static>>> {
+        let prefix = format!("{}/", self.remote_name());
+        for branch in self.repo.branches(Some(git2::BranchType::Remote))? {
+            let (branch, _) = branch?;
+            let Some(name) = branch.name()?.and_then(|name| name.strip_prefix(&prefix)) else {
+                continue;
+            };
+            let Ok(head) = branch.get().peel_to_commit() else {
+                continue;
+            };
+            if let Some((source, meta_data)) = trailers::parse(&head) {
+                if source == commit && meta_data.remote_branch_name == name {
+                    return Ok(Some(meta_data));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Options for merging a tracked commit with its remote branch, from the configured
+    /// [`StrategyOption`]s.
+    pub(crate) fn merge_options(&self) -> git2::MergeOptions {
+        let mut options = git2::MergeOptions::new();
+        for option in &self.config.strategy_options {
+            match option {
+                StrategyOption::Ours => options.file_favor(git2::FileFavor::Ours),
+                StrategyOption::Theirs => options.file_favor(git2::FileFavor::Theirs),
+                StrategyOption::Union => options.file_favor(git2::FileFavor::Union),
+                StrategyOption::Patience => options.patience(true),
+                StrategyOption::Minimal => options.minimal(true),
+                StrategyOption::IgnoreSpaceChange => options.ignore_whitespace_change(true),
+                StrategyOption::IgnoreAllSpace => options.ignore_whitespace(true),
+                StrategyOption::IgnoreSpaceAtEol => options.ignore_whitespace_eol(true),
+            };
+        }
+        options
+    }
+
+    /// Whether the created commits are signed, see [`signing`].
+    fn sign_commits(&self) -> bool {
+        !self.is_dry_run()
+            && (self.config.sign
+                || self
+                    .repo
+                    .config()
+                    .and_then(|config| config.get_bool("commit.gpgsign"))
+                    .unwrap_or(false))
+    }
+
+    /// Warn that the signature of `commit`, e.g. one made by a collaborator on a PR branch,
+    /// is lost because the commit is re-created. The new commit is only signed, with the
+    /// user's own key, if signing is enabled.
+    pub(crate) fn warn_on_discarded_signature(&self, commit: &Commit) {
+        if self.repo.extract_signature(&commit.id(), None).is_err() {
+            return;
+        }
+        if self.sign_commits() {
+            tracing::warn!(
+                "The signature of {} is replaced by your own signature",
+                commit.id()
+            );
+        } else {
+            tracing::warn!(
+                "The signature of {} is discarded, enable 'sign' to sign the new commit",
+                commit.id()
+            );
+        }
+    }
+
+    /// Create a commit without updating any ref, signed if `commit.gpgsign` or the `sign`
+    /// option is set. The committer gets the author's date if
+    /// `committer-date-is-author-date` is set.
+    pub(crate) fn create_commit(
+        &self,
+        author: &git2::Signature,
+        committer: &git2::Signature,
+        message: &str,
+        tree: &git2::Tree,
+        parents: &[&Commit],
+    ) -> anyhow::Result<git2::Oid> {
+        let committer = &if self.config.committer_date_is_author_date {
+            git2::Signature::new(
+                String::from_utf8_lossy(committer.name_bytes()).as_ref(),
+                String::from_utf8_lossy(committer.email_bytes()).as_ref(),
+                &author.when(),
+            )?
+        } else {
+            committer.to_owned()
+        };
+        if !self.sign_commits() {
+            return Ok(self
+                .repo
+                .commit(None, author, committer, message, tree, parents)?);
+        }
+        let buffer = self
+            .repo
+            .commit_create_buffer(author, committer, message, tree, parents)?;
+        let buffer = buffer.as_str().context("Commit is not valid UTF-8")?;
+        let signature = signing::sign(&self.repo.config()?, buffer, committer)?;
+        Ok(self.repo.commit_signed(buffer, &signature, None)?)
+    }
+
+    pub fn save_meta_data(
+        &self,
+        commit: &Commit,
+        meta_data: &CommitMetadata,
+    ) -> Result<(), git2::Error> {
+        if self.is_dry_run() {
+            return Ok(());
+        }
+        let committer = self.committer_signature(commit)?;
         self.repo.note(
             &committer,
             &committer,
-            None,
+            self.notes_ref(),
             commit.id(),
             &format!("{}", meta_data),
             true,
@@ -159,16 +763,137 @@ impl GitRepo {
         std::result::Result::Ok(())
     }
 
+    /// Save the metadata of a commit whose PR branch was just pushed by `create`.
+    ///
+    /// If it can't be saved, the metadata `replaced` by `create --force` is put back and a
+    /// branch that was new is deleted again, see [`GitRepo::abort_create`]. A branch that
+    /// was on the remote before is kept, as it may have an open PR.
+    pub fn finish_create(
+        &self,
+        commit: git2::Oid,
+        meta_data: &CommitMetadata,
+        replaced: Option<&CommitMetadata>,
+        new_branch: bool,
+    ) -> anyhow::Result<()> {
+        let commit = self.repo.find_commit(commit)?;
+        if let Err(err) = self.save_meta_data(&commit, meta_data) {
+            if let Some(replaced) = replaced {
+                if let Err(restore_err) = self.save_meta_data(&commit, replaced) {
+                    tracing::warn!("Restoring the metadata of {}: {}", commit.id(), restore_err);
+                }
+            }
+            if new_branch {
+                if let Err(abort_err) = self.abort_create(meta_data) {
+                    tracing::warn!("{:?}", abort_err);
+                }
+            }
+            return Err(
+                anyhow::Error::new(err).context(format!("Saving the metadata of {}", commit.id()))
+            );
+        }
+        Ok(())
+    }
+
+    /// Delete the branch pushed by a `create` that failed before the commit was tracked,
+    /// so that it isn't left on the remote without anything tracking it. Only for branches
+    /// that the `create` pushed for the first time.
+    pub fn abort_create(&self, meta_data: &CommitMetadata) -> anyhow::Result<()> {
+        self.remote()
+            .delete_branch(&meta_data.remote_branch_name)
+            .with_context(|| format!("Deleting {}", meta_data.remote_branch_name))
+    }
+
+    /// Remove the note of `commit`. A commit without a note is left as it is, so that a
+    /// removal that was interrupted can be run again.
     pub fn remove_meta_data(&self, commit: &Commit) -> Result<(), git2::Error> {
-        let committer = self.repo.signature().or_else(|_| {
-            git2::Signature::now(
-                String::from_utf8_lossy(commit.committer().name_bytes()).as_ref(),
-                String::from_utf8_lossy(commit.committer().email_bytes()).as_ref(),
-            )
-        })?;
+        if self.is_dry_run() {
+            return Ok(());
+        }
+        let committer = self.committer_signature(commit)?;
+        match self
+            .repo
+            .note_delete(commit.id(), self.notes_ref(), &committer, &committer)
+        {
+            Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(()),
+            result => result,
+        }
+    }
+
+    /// Whether the untracked `commit` is marked with `ubr ignore` or has an `Ubr-Ignore`
+    /// trailer, so that no PR is created for it.
+    pub fn is_ignored(&self, commit: &Commit) -> bool {
+        self.has_ignore_note(commit) || commit.message().is_some_and(trailers::is_ignored)
+    }
+
+    /// Whether `commit` is marked with `ubr ignore`, i.e. its note is the ignore mark.
+    pub(crate) fn has_ignore_note(&self, commit: &Commit) -> bool {
         self.repo
-            .note_delete(commit.id(), None, &committer, &committer)?;
-        std::result::Result::Ok(())
+            .find_note(self.notes_ref(), commit.id())
+            .is_ok_and(|note| note.message().map(str::trim) == Some(IGNORE_NOTE))
+    }
+
+    /// Mark the untracked `commit` as ignored, or remove the mark, in its note. The notes
+    /// may be shared with other tools, so a note that isn't the mark is never overwritten
+    /// or removed.
+    pub fn set_ignored(&self, commit: &Commit, ignored: bool) -> anyhow::Result<()> {
+        if self.is_dry_run() {
+            return Ok(());
+        }
+        let note = self
+            .repo
+            .find_note(self.notes_ref(), commit.id())
+            .ok()
+            .map(|note| note.message().map(str::trim) == Some(IGNORE_NOTE));
+        let committer = self.committer_signature(commit)?;
+        match (ignored, note) {
+            (true, None) => {
+                self.repo.note(
+                    &committer,
+                    &committer,
+                    self.notes_ref(),
+                    commit.id(),
+                    IGNORE_NOTE,
+                    false,
+                )?;
+            }
+            (true, Some(false)) => {
+                anyhow::bail!("{} already has a note, not overwriting it", commit.id())
+            }
+            (false, Some(true)) => {
+                self.repo
+                    .note_delete(commit.id(), self.notes_ref(), &committer, &committer)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn conflicting_paths(&self) -> anyhow::Result<Vec<PathBuf>> {
+        let index = self.repo.index()?;
+        let mut paths = Vec::new();
+        for conflict in index.conflicts()? {
+            let conflict = conflict?;
+            if let Some(entry) = conflict.our.or(conflict.their).or(conflict.ancestor) {
+                paths.push(PathBuf::from(String::from_utf8_lossy(&entry.path).as_ref()));
+            }
+        }
+        Ok(paths)
+    }
+
+    /// Run 'git mergetool' on the conflicting `paths` of the index, letting the user resolve
+    /// them with their configured `merge.tool`.
+    pub fn run_mergetool(&self, paths: &[PathBuf]) -> anyhow::Result<()> {
+        let status = std::process::Command::new("git")
+            .current_dir(&self.path)
+            .arg("mergetool")
+            .arg("--")
+            .args(paths)
+            .status()
+            .context("Running git mergetool")?;
+        if !status.success() {
+            tracing::warn!("git mergetool failed ({})", status);
+        }
+        Ok(())
     }
 
     fn try_load_sync_state<P>(path: P) -> Option<SyncState>
@@ -182,18 +907,138 @@ impl GitRepo {
     }
 
     fn cleanup_state(&self) -> anyhow::Result<()> {
-        std::fs::remove_file(self.path.join(".ubr/SYNC_MERGE_HEAD")).context("Cleanup sync state")
+        std::fs::remove_file(self.path.join(".ubr/SYNC_MERGE_HEAD"))
+            .context("Cleanup sync state")?;
+        // Only succeeds if there is nothing else (like a config file) left in the directory
+        let _ = std::fs::remove_dir(self.path.join(".ubr"));
+        Ok(())
     }
 
-    pub fn save_sync_state(&self, state: &SyncState) -> anyhow::Result<()> {
-        std::fs::create_dir_all(format!("{}/.ubr", self.path.display()))?;
-        let file =
-            std::fs::File::create_new(format!("{}/.ubr/SYNC_MERGE_HEAD", self.path.display()))?;
-        serde_json::to_writer(file, state)?;
+    fn try_load_rebase_state(path: &Path) -> Option<RebaseState> {
+        let file = std::fs::File::open(path.join(".ubr/REBASE")).ok()?;
+        serde_json::from_reader(file).ok()
+    }
+
+    pub fn rebase_state(&self) -> Option<&RebaseState> {
+        self.rebase_state.as_ref()
+    }
+
+    /// Whether moving `commit` onto `onto` conflicts.
+    pub(crate) fn has_conflicts(&self, commit: &Commit, onto: &Commit) -> anyhow::Result<bool> {
+        Ok(self
+            .repo
+            .cherrypick_commit(commit, onto, 0, None)?
+            .has_conflicts())
+    }
+
+    /// Leave the conflicts of moving `commit` onto `onto` in the working tree, with HEAD
+    /// detached at `onto`, for the user to resolve before `ubr rebase --continue`.
+    pub(crate) fn stop_rebase(
+        &self,
+        commit: &Commit,
+        onto: &Commit,
+        remaining: Vec<git2::Oid>,
+    ) -> anyhow::Result<()> {
+        self.repo.checkout_tree(onto.tree()?.as_object(), None)?;
+        self.repo
+            .set_head_detached(onto.id())
+            .context("Detach HEAD")?;
+        self.repo.cherrypick(commit, None)?;
+        std::fs::create_dir_all(self.path.join(".ubr"))?;
+        write_state(
+            &self.path.join(".ubr/REBASE"),
+            &RebaseState {
+                main_branch_name: self.current_branch_name.clone(),
+                commit_id: commit.id().into(),
+                remaining: remaining.into_iter().map(Oid::from).collect(),
+            },
+        )
+    }
+
+    /// Commit the resolved conflicts of a stopped rebase, keeping the metadata of the
+    /// commit. Returns the new commit and the commits that still have to be moved.
+    pub(crate) fn finish_rebase(&self) -> anyhow::Result<(Commit<'_>, Vec<git2::Oid>)> {
+        let state = self
+            .rebase_state
+            .as_ref()
+            .context("No rebase in progress")?;
+        let mut index = self.repo.index()?;
+        if index.has_conflicts() {
+            anyhow::bail!("Resolve all the conflicts and 'git add' the files first");
+        }
+        let commit = self.repo.find_commit(state.commit_id.into())?;
+        let tree = self.repo.find_tree(index.write_tree()?)?;
+        let head_commit = self.repo.head()?.peel_to_commit()?;
+        let new_commit = self.create_commit(
+            &commit.author(),
+            &self.rewrite_committer(&commit),
+            commit
+                .message()
+                .context("Commit message is not valid UTF-8")?,
+            &tree,
+            &[&head_commit],
+        )?;
+        let new_commit = self.repo.find_commit(new_commit)?;
+        if let MainCommit::Tracked(tracked) = MainCommit::new(self, &self.repo, commit)? {
+            self.save_meta_data(&new_commit, tracked.meta_data())?;
+        }
+        self.repo.cleanup_state()?;
+        self.cleanup_rebase_state()?;
+        Ok((
+            new_commit,
+            state.remaining.iter().map(|&id| id.into()).collect(),
+        ))
+    }
+
+    /// Abort a stopped rebase and move back to the main branch as it was before.
+    pub fn abort_rebase(&self) -> anyhow::Result<()> {
+        self.rebase_state
+            .as_ref()
+            .context("No rebase in progress")?;
+        self.repo.cleanup_state()?;
+        self.repo
+            .set_head(&format!("refs/heads/{}", self.current_branch_name))
+            .context("Moving HEAD back to main branch")?;
+        self.repo
+            .checkout_head(Some(git2::build::CheckoutBuilder::new().force()))?;
+        self.cleanup_rebase_state()
+    }
+
+    fn cleanup_rebase_state(&self) -> anyhow::Result<()> {
+        std::fs::remove_file(self.path.join(".ubr/REBASE")).context("Cleanup rebase state")?;
+        // Only succeeds if there is nothing else (like a config file) left in the directory
+        let _ = std::fs::remove_dir(self.path.join(".ubr"));
         Ok(())
     }
 
-    pub fn unpushed_commits(&self) -> anyhow::Result<Vec<MainCommit>> {
+    pub fn sync_state(&self) -> Option<&SyncState> {
+        self.sync_state.as_ref()
+    }
+
+    /// Abort an ongoing sync and move back to the main branch as it was before the sync.
+    pub fn abort_sync(&self) -> anyhow::Result<()> {
+        self.sync_state.as_ref().context("No sync in progress")?;
+        self.abandon_merge()
+    }
+
+    /// Throw away a merge that was left in the working tree by a conflict and move back to
+    /// the main branch.
+    pub(crate) fn abandon_merge(&self) -> anyhow::Result<()> {
+        self.repo.cleanup_state()?;
+        self.repo
+            .set_head(&format!("refs/heads/{}", self.current_branch_name))
+            .context("Moving HEAD back to main branch")?;
+        self.repo
+            .checkout_head(Some(git2::build::CheckoutBuilder::new().force()))?;
+        self.cleanup_state()
+    }
+
+    pub fn save_sync_state(&self, state: &SyncState) -> anyhow::Result<()> {
+        std::fs::create_dir_all(self.path.join(".ubr"))?;
+        write_state(&self.path.join(".ubr/SYNC_MERGE_HEAD"), state)
+    }
+
+    pub fn unpushed_commits(&self) -> anyhow::Result<Vec<MainCommit<'_>>> {
         let mut walk = self.repo.revwalk()?;
         walk.set_sorting(git2::Sort::TOPOLOGICAL.union(git2::Sort::REVERSE))?;
 
@@ -206,8 +1051,10 @@ impl GitRepo {
 
         let base_commit_id = if let Some(sync_state) = &self.sync_state {
             sync_state.main_commit_id.into()
+        } else if self.since.is_some() {
+            self.stack_base()?.id()
         } else {
-            self.base_commit()?.id()
+            self.merge_base_with_upstream()?
         };
 
         walk.hide(base_commit_id)?;
@@ -219,8 +1066,106 @@ impl GitRepo {
         Ok(result?)
     }
 
+    /// Number of commits in the stack, see [`GitRepo::unpushed_commits`].
+    pub fn current_stack_depth(&self) -> anyhow::Result<usize> {
+        Ok(self.unpushed_commits()?.len())
+    }
+
+    /// Same as [`GitRepo::unpushed_commits`] but only the tracked commits.
+    pub fn tracked_commits(&self) -> anyhow::Result<Vec<TrackedCommit<'_>>> {
+        Ok(self
+            .unpushed_commits()?
+            .into_iter()
+            .filter_map(|commit| match commit {
+                MainCommit::UnTracked(_) => None,
+                MainCommit::Tracked(tracked) => Some(tracked),
+            })
+            .collect())
+    }
+
+    /// Squash the commit at `commit_ref` into its parent, like a `fixup`. The squashed
+    /// commit keeps the metadata of the parent.
+    ///
+    /// Returns the squashed commit, the commits above it that still have to be moved onto
+    /// it, and the metadata of the commit that was squashed, if it was tracked.
+    pub fn squash(
+        &self,
+        commit_ref: &str,
+    ) -> anyhow::Result<(Commit<'_>, Vec<git2::Oid>, Option<CommitMetadata<'static>>)> {
+        let child = self.find_unpushed_commit(commit_ref)?;
+        let mut commits = self.unpushed_commits()?.into_iter();
+        let parent = commits
+            .by_ref()
+            .take_while(|c| c.id() != child.id())
+            .last()
+            .with_context(|| {
+                format!("'{}' has no parent in the stack to squash into", commit_ref)
+            })?;
+
+        let parent_commit = parent.as_commit();
+        let squashed = self.create_commit(
+            &parent_commit.author(),
+            &self.rewrite_committer(parent_commit),
+            parent
+                .message()
+                .context("Commit message is not valid UTF-8")?,
+            &child.as_commit().tree()?,
+            &parent_commit
+                .parents()
+                .collect::<Vec<_>>()
+                .iter()
+                .collect::<Vec<_>>(),
+        )?;
+        let squashed = self.repo.find_commit(squashed)?;
+        if let MainCommit::Tracked(tracked) = &parent {
+            self.save_meta_data(&squashed, tracked.meta_data())?;
+        }
+
+        let meta_data = match child {
+            MainCommit::UnTracked(_) => None,
+            MainCommit::Tracked(tracked) => Some(tracked.meta_data().clone().into_owned()),
+        };
+        Ok((squashed, commits.map(|c| c.id()).collect(), meta_data))
+    }
+
+    /// Whether any tracked file has changes that are not committed.
+    pub fn has_uncommitted_changes(&self) -> anyhow::Result<bool> {
+        let statuses = self.repo.statuses(Some(
+            git2::StatusOptions::new()
+                .include_untracked(false)
+                .include_ignored(false),
+        ))?;
+        Ok(statuses
+            .iter()
+            .any(|entry| entry.status() != git2::Status::CURRENT))
+    }
+
+    /// Stash the uncommitted changes of tracked files, like `git stash`, so that moving the
+    /// current branch can't overwrite them. Returns whether anything was stashed.
+    pub fn stash_changes(&mut self) -> anyhow::Result<bool> {
+        if self.is_dry_run() || !self.has_uncommitted_changes()? {
+            return Ok(false);
+        }
+        let signature = self.committer_signature(&self.head()?)?;
+        self.repo
+            .stash_save(&signature, "ubr: uncommitted changes", None)
+            .context("Stashing the uncommitted changes")?;
+        Ok(true)
+    }
+
+    /// Restore the changes stashed by [`GitRepo::stash_changes`]. If they don't apply,
+    /// they are kept in the stash.
+    pub fn pop_stash(&mut self) -> anyhow::Result<()> {
+        self.repo
+            .stash_pop(
+                0,
+                Some(git2::StashApplyOptions::new().reinstantiate_index()),
+            )
+            .context("Restoring the uncommitted changes, they are kept in 'git stash'")
+    }
+
     pub fn update_current_branch(&self, new_head: &Commit) -> anyhow::Result<()> {
-        if matches!(self.git_command_option, CommandOption::DryRun) {
+        if self.is_dry_run() {
             println!(
                 "Setting {} to point to {}",
                 self.current_branch_name,
@@ -228,6 +1173,23 @@ impl GitRepo {
             );
             return Ok(());
         }
+        let old_head = self.head()?;
+        if let Err(err) = self.move_current_branch(new_head) {
+            // Don't leave HEAD detached, or the working tree at the new head, behind
+            if let Err(restore_err) = self.restore_current_branch(&old_head, new_head) {
+                tracing::warn!(
+                    "Unable to restore {} to {}: {:?}",
+                    self.current_branch_name,
+                    old_head.id(),
+                    restore_err
+                );
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    fn move_current_branch(&self, new_head: &Commit) -> anyhow::Result<()> {
         self.repo
             .checkout_tree(new_head.tree()?.as_object(), None)?;
         self.repo
@@ -243,32 +1205,68 @@ impl GitRepo {
         Ok(())
     }
 
-    pub(crate) fn finish_merge(&self) -> anyhow::Result<TrackedCommit> {
-        let state = self.sync_state.as_ref().expect("Must have a sync state");
-        let tree = self.repo.index()?.write_tree()?;
+    /// Check out the current branch at `old_head` again after moving it to `new_head`
+    /// failed half way.
+    fn restore_current_branch(&self, old_head: &Commit, new_head: &Commit) -> anyhow::Result<()> {
+        let branch_ref = format!("refs/heads/{}", self.current_branch_name);
+        if self.repo.refname_to_id(&branch_ref)? != old_head.id() {
+            self.repo
+                .branch(&self.current_branch_name, old_head, true)?;
+        }
+        // The working tree is at the new head, so that is what the checkout starts from
+        self.repo.set_head_detached(new_head.id())?;
+        self.repo
+            .checkout_tree(old_head.tree()?.as_object(), None)?;
+        self.repo.set_head(&branch_ref)?;
+        Ok(())
+    }
+
+    pub(crate) fn finish_merge(&self) -> anyhow::Result<TrackedCommit<'_>> {
+        let state = self.sync_state.as_ref().context("No sync in progress")?;
+        // The metadata lives in a note, which may have been removed while the sync was
+        // stopped. Check before finishing the merge, so that the sync can still be aborted.
+        let tracked_commit =
+            match self.find_unpushed_commit(&format!("{}", state.main_commit_id))? {
+                MainCommit::UnTracked(commit) => anyhow::bail!(
+                    "Commit {} is no longer tracked. Run 'ubr sync --abort' and then \
+                     'ubr create' to track it again",
+                    commit.as_commit().id()
+                ),
+                MainCommit::Tracked(commit) => commit,
+            };
+        let mut index = self.repo.index()?;
+        if index.has_conflicts() {
+            let paths = self
+                .conflicting_paths()?
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            anyhow::bail!(
+                "unresolved conflicts remain in {}. Resolve them, 'git add' the files and run \
+                 'ubr sync --continue' again",
+                paths
+            );
+        }
+        let tree = index.write_tree()?;
         let tree = self.repo.find_tree(tree)?;
-        let author = self.repo.signature()?;
+        let head_commit = self.repo.head()?.peel_to_commit()?;
+        let author = self.committer_signature(&head_commit)?;
 
         tracing::info!("Continuing previous sync {:?}", state);
 
-        let merge_commit_id = self.repo.commit(
-            None,
+        let merge_commit_id = self.create_commit(
             &author,
             &author,
             "Merge",
             &tree,
             &[
-                &self.repo.head()?.peel_to_commit()?,
+                &head_commit,
                 &self.repo.find_commit(state.remote_commit_id.into())?,
             ],
         )?;
         self.repo.cleanup_state()?;
         self.cleanup_state()?;
-        let tracked_commit =
-            match self.find_unpushed_commit(&format!("{}", state.main_commit_id))? {
-                MainCommit::UnTracked(_) => todo!(),
-                MainCommit::Tracked(commit) => commit,
-            };
 
         tracked_commit.cont(
             &self.repo.find_commit(merge_commit_id)?,
@@ -277,6 +1275,66 @@ impl GitRepo {
     }
 }
 
+/// The first line of the message of `commit`, trimmed. Unlike [`Commit::summary`] the
+/// rest of the first paragraph isn't joined onto it.
+pub fn commit_subject(commit: &Commit) -> String {
+    subject(&String::from_utf8_lossy(commit.message_bytes())).to_string()
+}
+
+/// The first non-blank line of `message`, trimmed, or `""` if there is none.
+pub fn subject(message: &str) -> &str {
+    message
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .unwrap_or("")
+}
+
+/// Parse the internal git date format, `[@]<unix seconds> [+-hhmm]`.
+fn parse_git_date(date: &str) -> Option<git2::Time> {
+    let mut parts = date.split_whitespace();
+    let seconds = parts.next()?.trim_start_matches('@').parse::<i64>().ok()?;
+    let offset = match parts.next() {
+        Some(offset) => {
+            let (sign, digits) = match offset.split_at_checked(1)? {
+                ("+", digits) => (1, digits),
+                ("-", digits) => (-1, digits),
+                _ => return None,
+            };
+            if digits.len() != 4 {
+                return None;
+            }
+            let hours = digits[..2].parse::<i32>().ok()?;
+            let minutes = digits[2..].parse::<i32>().ok()?;
+            sign * (hours * 60 + minutes)
+        }
+        None => 0,
+    };
+    Some(git2::Time::new(seconds, offset))
+}
+
+/// Write `state` to `path`, which must not exist yet. The state is written to a temporary
+/// file that is renamed into place, so that a process that is interrupted never leaves a
+/// partially written state behind.
+fn write_state<T: Serialize>(path: &Path, state: &T) -> anyhow::Result<()> {
+    if path.exists() {
+        anyhow::bail!("{} already exists", path.display());
+    }
+    let tmp_path = write_state_tmp(path, state)?;
+    std::fs::rename(&tmp_path, path).with_context(|| format!("Renaming {}", tmp_path.display()))
+}
+
+/// Write `state` to `<path>.tmp`, replacing the leftovers of an interrupted write, and make
+/// sure it is on disk before it is renamed.
+fn write_state_tmp<T: Serialize>(path: &Path, state: &T) -> anyhow::Result<PathBuf> {
+    let tmp_path = path.with_extension("tmp");
+    let mut file = std::fs::File::create(&tmp_path)
+        .with_context(|| format!("Creating {}", tmp_path.display()))?;
+    serde_json::to_writer(&mut file, state)?;
+    file.sync_all()?;
+    Ok(tmp_path)
+}
+
 #[cfg(test)]
 mod test {
     use std::fs::File;
@@ -284,21 +1342,44 @@ mod test {
     use std::process::{Command, Stdio};
     use tempfile::tempdir;
 
-    use super::GitRepo;
+    use super::{parse_git_date, subject, write_state, write_state_tmp, GitRepo, SyncState};
 
     #[test]
-    fn open_git_repo_from_subdir() {
-        let dir = tempdir().unwrap();
+    fn test_subject() {
+        assert_eq!(subject("Add a feature"), "Add a feature");
+        assert_eq!(subject("Add a feature\n"), "Add a feature");
+        assert_eq!(
+            subject("  Add a feature \nthat wraps\n\nAnd a body\n"),
+            "Add a feature"
+        );
+        assert_eq!(subject("\nAdd a feature\r\n"), "Add a feature");
+        assert_eq!(subject(""), "");
+        assert_eq!(subject("\n \n"), "");
+    }
+
+    #[test]
+    fn parse_git_dates() {
+        let time = parse_git_date("@1700000000 +0130").unwrap();
+        assert_eq!((time.seconds(), time.offset_minutes()), (1700000000, 90));
+        let time = parse_git_date("1700000000 -0200").unwrap();
+        assert_eq!((time.seconds(), time.offset_minutes()), (1700000000, -120));
+        let time = parse_git_date("1700000000").unwrap();
+        assert_eq!((time.seconds(), time.offset_minutes()), (1700000000, 0));
+        assert!(parse_git_date("yesterday").is_none());
+        assert!(parse_git_date("1700000000 0100").is_none());
+    }
 
-        let subdir_path = dir.path().join("dir1");
+    /// A repository with a file in `dir1/` and a remote tracking branch for the base.
+    fn init_repo(dir: &std::path::Path) {
+        let subdir_path = dir.join("dir1");
         std::fs::create_dir_all(subdir_path).unwrap();
-        let file_path = dir.path().join("dir1/file1");
+        let file_path = dir.join("dir1/file1");
         let mut tmp_file = File::create(file_path).unwrap();
         writeln!(tmp_file, "This is a file").unwrap();
 
-        let repo = git2::Repository::init(dir.path()).unwrap();
+        let repo = git2::Repository::init(dir).unwrap();
         assert!(Command::new("git")
-            .current_dir(dir.path())
+            .current_dir(dir)
             .arg("add")
             .arg(".")
             .stdout(Stdio::null())
@@ -307,7 +1388,7 @@ mod test {
             .unwrap()
             .success());
         assert!(Command::new("git")
-            .current_dir(dir.path())
+            .current_dir(dir)
             .arg("commit")
             .arg("-a")
             .arg("-m")
@@ -318,17 +1399,76 @@ mod test {
             .unwrap()
             .success());
 
-        std::fs::create_dir_all(dir.path().join(".git/refs/remotes/origin/")).unwrap();
-        let mut ref_file =
-            File::create(dir.path().join(".git/refs/remotes/origin/master")).unwrap();
+        std::fs::create_dir_all(dir.join(".git/refs/remotes/origin/")).unwrap();
+        let mut ref_file = File::create(dir.join(".git/refs/remotes/origin/master")).unwrap();
         writeln!(
             ref_file,
             "{}",
             repo.head().unwrap().peel_to_commit().unwrap().id()
         )
         .unwrap();
+    }
+
+    #[test]
+    fn open_git_repo_from_subdir() {
+        let dir = tempdir().unwrap();
+        init_repo(dir.path());
 
         let repo = GitRepo::open(dir.path().join("dir1/"));
         assert!(repo.is_ok(), "{:?}", repo.err());
+        assert_eq!(
+            repo.unwrap().path().canonicalize().unwrap(),
+            dir.path().canonicalize().unwrap()
+        );
+    }
+
+    #[test]
+    fn open_git_repo_from_git_dir() {
+        let dir = tempdir().unwrap();
+        init_repo(dir.path());
+
+        let repo = GitRepo::open(dir.path().join(".git"));
+        assert!(repo.is_ok(), "{:?}", repo.err());
+        assert_eq!(
+            repo.unwrap().path().canonicalize().unwrap(),
+            dir.path().canonicalize().unwrap()
+        );
+    }
+
+    fn sync_state(branch_name: &str) -> SyncState {
+        SyncState {
+            main_commit_id: git2::Oid::zero().into(),
+            remote_commit_id: git2::Oid::zero().into(),
+            main_commit_parent_id: git2::Oid::zero().into(),
+            main_branch_name: branch_name.to_string(),
+        }
+    }
+
+    #[test]
+    fn interrupted_state_write_keeps_the_old_state() {
+        let dir = tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join(".ubr")).unwrap();
+        let path = dir.path().join(".ubr/SYNC_MERGE_HEAD");
+        let large = "x".repeat(1 << 20);
+
+        // Interrupted before anything was renamed into place
+        let tmp_path = write_state_tmp(&path, &sync_state(&large)).unwrap();
+        std::fs::write(&tmp_path, &std::fs::read(&tmp_path).unwrap()[..1000]).unwrap();
+        assert_eq!(GitRepo::try_load_sync_state(dir.path()), None);
+
+        write_state(&path, &sync_state(&large)).unwrap();
+        assert!(!tmp_path.exists());
+        assert_eq!(
+            GitRepo::try_load_sync_state(dir.path()),
+            Some(sync_state(&large))
+        );
+
+        // Interrupted while replacing an existing state
+        write_state_tmp(&path, &sync_state("other")).unwrap();
+        assert_eq!(
+            GitRepo::try_load_sync_state(dir.path()),
+            Some(sync_state(&large))
+        );
+        assert!(write_state(&path, &sync_state("other")).is_err());
     }
 }
@@ -1,74 +1,457 @@
 use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
     path::Path,
-    process::{Command, Stdio},
+    process::{Command, ExitStatus, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
 };
 
 use anyhow::Context;
+use tracing::{debug, warn};
 
 use super::local_commit::CommitMetadata;
 
+pub struct RemoteOptions<'a> {
+    pub path: &'a Path,
+    pub remote_name: &'a str,
+    pub retry: Retry,
+    /// Set as `GIT_SSH_COMMAND`
+    pub ssh_command: Option<&'a str>,
+    /// Extra environment of the git commands
+    pub env: &'a BTreeMap<String, String>,
+}
+
+/// How often to retry a remote command that failed because of a network problem. The
+/// delay doubles after every attempt.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Retry {
+    pub retries: u32,
+    pub delay: Duration,
+}
+
+/// Error output of git that indicates a failure worth retrying
+const TRANSIENT_ERRORS: &[&str] = &[
+    "could not resolve host",
+    "connection timed out",
+    "operation timed out",
+    "connection reset",
+    "connection refused",
+    "network is unreachable",
+    "temporary failure",
+    "the remote end hung up unexpectedly",
+    "early eof",
+];
+
+/// Error output of git that is never worth retrying, even if it also looks like a
+/// network problem
+const PERMANENT_ERRORS: &[&str] = &[
+    "authentication failed",
+    "permission denied",
+    "could not read username",
+];
+
+fn is_transient(stderr: &str) -> bool {
+    let stderr = stderr.to_lowercase();
+    !PERMANENT_ERRORS.iter().any(|e| stderr.contains(e))
+        && TRANSIENT_ERRORS.iter().any(|e| stderr.contains(e))
+}
+
+/// Run `command`, retrying it according to `retry` when it fails with a transient error.
+///
+/// Unless `quiet`, stdout goes straight to the terminal and stderr is echoed as it
+/// arrives, so progress is shown while git runs. Stderr is also captured, to decide
+/// whether to retry and to include it in the returned error. When `quiet`, both are only
+/// captured.
+///
+/// Any non-zero exit status is an error, so a push that the remote rejects fails the
+/// command instead of only being logged.
+fn run(command: &mut Command, retry: Retry, quiet: bool) -> anyhow::Result<()> {
+    let name = format!("{:?}", command);
+    let mut delay = retry.delay;
+    for attempt in 0.. {
+        let (status, stderr) = run_once(command, quiet).with_context(|| name.clone())?;
+        let stderr = String::from_utf8_lossy(&stderr);
+        debug!(
+            command = %name,
+            status = %status,
+            stderr = %stderr.trim(),
+            "remote command finished"
+        );
+        if status.success() {
+            return Ok(());
+        }
+        if !is_transient(&stderr) || attempt >= retry.retries {
+            anyhow::bail!("{} failed ({}): {}", name, status, stderr.trim());
+        }
+        warn!("{} failed ({}), retrying in {:?}", name, status, delay);
+        std::thread::sleep(delay);
+        delay *= 2;
+    }
+    unreachable!()
+}
+
+/// Run `command` once, returning its exit status and stderr.
+fn run_once(command: &mut Command, quiet: bool) -> std::io::Result<(ExitStatus, Vec<u8>)> {
+    command.stdin(Stdio::null()).stderr(Stdio::piped());
+    if quiet {
+        let output = command.stdout(Stdio::piped()).output()?;
+        return Ok((output.status, output.stderr));
+    }
+    let mut child = command.stdout(Stdio::inherit()).spawn()?;
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let stderr = tee(stderr, &mut std::io::stderr())?;
+    Ok((child.wait()?, stderr))
+}
+
+/// Copy everything from `reader` to `out` as it arrives, returning a copy of it.
+fn tee(mut reader: impl Read, out: &mut impl Write) -> std::io::Result<Vec<u8>> {
+    let mut captured = Vec::new();
+    let mut buf = [0; 4096];
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => return Ok(captured),
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        out.write_all(&buf[..n])?;
+        out.flush()?;
+        captured.extend_from_slice(&buf[..n]);
+    }
+}
+
 pub enum RemoteGitCommand<'a> {
-    Default(&'a Path),
-    Silent(&'a Path),
-    DryRun(&'a Path),
+    Default(RemoteOptions<'a>),
+    Silent(RemoteOptions<'a>),
+    DryRun(RemoteOptions<'a>),
 }
 
 impl<'a> RemoteGitCommand<'a> {
+    /// Force push the local commit of `meta_data` to its remote branch. Fails if git
+    /// exits with an error, e.g. because the remote rejected the push.
     pub fn push(&self, meta_data: &CommitMetadata) -> anyhow::Result<()> {
         match self {
-            RemoteGitCommand::Default(path) => {
-                RemoteGitCommand::push_real(path, meta_data, Stdio::inherit)
+            RemoteGitCommand::Default(options) => {
+                git(options, &push_args(options, meta_data), false)
             }
-            RemoteGitCommand::Silent(path) => {
-                RemoteGitCommand::push_real(path, meta_data, Stdio::null)
+            RemoteGitCommand::Silent(options) => git(options, &push_args(options, meta_data), true),
+            RemoteGitCommand::DryRun(options) => {
+                print_dry_run(&push_args(options, meta_data), &mut std::io::stdout())
             }
-            RemoteGitCommand::DryRun(_) => Ok(println!(
-                "Pushing commit {} to origin/{}",
-                meta_data.remote_commit, meta_data.remote_branch_name
-            )),
         }
     }
 
-    fn push_real<F>(path: &Path, meta_data: &CommitMetadata, stdio: F) -> anyhow::Result<()>
-    where
-        F: Fn() -> Stdio,
-    {
-        Command::new("git")
-            .current_dir(path)
-            .arg("push")
-            .arg("--no-verify")
-            .arg("--force-with-lease")
-            .arg("--")
-            .arg("origin")
-            .arg(format!(
-                "{}:refs/heads/{}",
-                meta_data.remote_commit, &meta_data.remote_branch_name
-            ))
-            .stderr(stdio())
-            .stdout(stdio())
-            .status()?;
-        Ok(())
-    }
-
-    fn fetch_real<F>(path: &Path, stdio: F) -> anyhow::Result<()>
-    where
-        F: Fn() -> Stdio,
-    {
-        Command::new("git")
-            .current_dir(path)
-            .arg("fetch")
-            .stdout(stdio())
-            .stderr(stdio())
-            .status()
-            .context("git fetch")?;
-        Ok(())
+    /// Push all the branches using up to `jobs` concurrent `git push` processes, calling
+    /// `on_pushed` on this thread as soon as a branch has been pushed. A failing
+    /// `on_pushed` doesn't stop the other branches from being pushed, and the first error
+    /// is returned.
+    ///
+    /// Dry runs are always done serially to keep the output deterministic.
+    pub fn push_all(
+        &self,
+        meta_data: &[CommitMetadata],
+        jobs: usize,
+        mut on_pushed: impl FnMut(&CommitMetadata) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        if jobs <= 1 || meta_data.len() <= 1 || matches!(self, RemoteGitCommand::DryRun(_)) {
+            let mut result = Ok(());
+            for m in meta_data {
+                self.push(m).with_context(|| format!("Pushing {}", m))?;
+                let pushed = on_pushed(m);
+                if result.is_ok() {
+                    result = pushed;
+                }
+            }
+            return result;
+        }
+
+        let next = AtomicUsize::new(0);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::scope(|scope| {
+            for _ in 0..jobs.min(meta_data.len()) {
+                let sender = sender.clone();
+                let next = &next;
+                scope.spawn(move || {
+                    while let Some(m) = meta_data.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        let pushed = self.push(m).with_context(|| format!("Pushing {}", m));
+                        let failed = pushed.is_err();
+                        if sender.send((m, pushed)).is_err() || failed {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(sender);
+            let mut result = Ok(());
+            for (m, pushed) in receiver {
+                let pushed = pushed.and_then(|()| on_pushed(m));
+                if result.is_ok() {
+                    result = pushed;
+                }
+            }
+            result
+        })
+    }
+
+    pub fn delete_branch(&self, branch_name: &str) -> anyhow::Result<()> {
+        match self {
+            RemoteGitCommand::Default(options) => {
+                git(options, &delete_branch_args(options, branch_name), false)
+            }
+            RemoteGitCommand::Silent(options) => {
+                git(options, &delete_branch_args(options, branch_name), true)
+            }
+            RemoteGitCommand::DryRun(options) => print_dry_run(
+                &delete_branch_args(options, branch_name),
+                &mut std::io::stdout(),
+            ),
+        }
     }
 
     pub(crate) fn fetch(&self) -> anyhow::Result<()> {
+        self.fetch_with_args(fetch_args)
+    }
+
+    /// Fetch only `branch_name`, updating its remote tracking branch.
+    pub(crate) fn fetch_branch(&self, branch_name: &str) -> anyhow::Result<()> {
+        self.fetch_with_args(|options| fetch_branch_args(options, branch_name))
+    }
+
+    fn fetch_with_args(&self, args: impl Fn(&RemoteOptions) -> Vec<String>) -> anyhow::Result<()> {
         match self {
-            RemoteGitCommand::Default(path) => RemoteGitCommand::fetch_real(path, Stdio::inherit),
-            RemoteGitCommand::Silent(path) => RemoteGitCommand::fetch_real(path, Stdio::null),
-            RemoteGitCommand::DryRun(path) => RemoteGitCommand::fetch_real(path, Stdio::inherit),
+            RemoteGitCommand::Default(options) => git(options, &args(options), false),
+            RemoteGitCommand::Silent(options) => git(options, &args(options), true),
+            RemoteGitCommand::DryRun(options) => {
+                // Fetching doesn't change the remote, and the dry run needs the remote
+                // branches to show what would be pushed
+                let args = args(options);
+                print_dry_run(&args, &mut std::io::stdout())?;
+                git(options, &args, false)
+            }
         }
     }
 }
+
+fn fetch_branch_args(options: &RemoteOptions, branch_name: &str) -> Vec<String> {
+    vec![
+        "fetch".to_string(),
+        options.remote_name.to_string(),
+        format!(
+            "refs/heads/{}:refs/remotes/{}/{}",
+            branch_name, options.remote_name, branch_name
+        ),
+    ]
+}
+
+fn push_args(options: &RemoteOptions, meta_data: &CommitMetadata) -> Vec<String> {
+    vec![
+        "push".to_string(),
+        "--no-verify".to_string(),
+        "--force-with-lease".to_string(),
+        "--".to_string(),
+        options.remote_name.to_string(),
+        format!(
+            "{}:refs/heads/{}",
+            meta_data.remote_commit, meta_data.remote_branch_name
+        ),
+    ]
+}
+
+fn delete_branch_args(options: &RemoteOptions, branch_name: &str) -> Vec<String> {
+    vec![
+        "push".to_string(),
+        "--no-verify".to_string(),
+        options.remote_name.to_string(),
+        "--delete".to_string(),
+        branch_name.to_string(),
+    ]
+}
+
+fn fetch_args(options: &RemoteOptions) -> Vec<String> {
+    vec!["fetch".to_string(), options.remote_name.to_string()]
+}
+
+/// Run `git` with `args` in the repository, see [`run`].
+fn git(options: &RemoteOptions, args: &[String], quiet: bool) -> anyhow::Result<()> {
+    let mut command = Command::new("git");
+    command
+        .current_dir(options.path)
+        .args(args)
+        .envs(options.env);
+    if let Some(ssh_command) = options.ssh_command {
+        command.env("GIT_SSH_COMMAND", ssh_command);
+    }
+    run(&mut command, options.retry, quiet)
+}
+
+/// Print the command line that [`git`] would run with `args`, so that it can be copied
+/// and run by hand.
+fn print_dry_run(args: &[String], out: &mut impl Write) -> anyhow::Result<()> {
+    writeln!(out, "git {}", args.join(" "))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeMap, process::Command, time::Duration};
+
+    use tempfile::tempdir;
+
+    use git2::Oid;
+
+    use super::{print_dry_run, push_args, run, tee, RemoteGitCommand, RemoteOptions, Retry};
+    use crate::git::local_commit::CommitMetadata;
+
+    /// A command that fails with `error` until it has been run `failures` times
+    fn flaky_command(dir: &std::path::Path, failures: u32, error: &str) -> Command {
+        let mut command = Command::new("sh");
+        command.current_dir(dir).arg("-c").arg(format!(
+            "echo x >> attempts; \
+             [ $(wc -l < attempts) -gt {} ] || {{ echo '{}' >&2; exit 128; }}",
+            failures, error
+        ));
+        command
+    }
+
+    fn attempts(dir: &std::path::Path) -> usize {
+        std::fs::read_to_string(dir.join("attempts"))
+            .unwrap()
+            .lines()
+            .count()
+    }
+
+    const RETRY: Retry = Retry {
+        retries: 3,
+        delay: Duration::ZERO,
+    };
+
+    #[test]
+    fn test_retry_transient_failures() {
+        let dir = tempdir().unwrap();
+        let mut command = flaky_command(
+            dir.path(),
+            2,
+            "fatal: unable to access: Could not resolve host: example.com",
+        );
+        run(&mut command, RETRY, true).unwrap();
+        assert_eq!(attempts(dir.path()), 3);
+    }
+
+    #[test]
+    fn test_give_up_after_retries() {
+        let dir = tempdir().unwrap();
+        let mut command = flaky_command(dir.path(), 10, "fatal: early EOF");
+        assert!(run(&mut command, RETRY, true).is_err());
+        assert_eq!(attempts(dir.path()), 4);
+    }
+
+    #[test]
+    fn test_do_not_retry_auth_failures() {
+        let dir = tempdir().unwrap();
+        let mut command = flaky_command(
+            dir.path(),
+            1,
+            "fatal: Authentication failed for 'https://example.com/repo.git/'",
+        );
+        let err = run(&mut command, RETRY, true).unwrap_err();
+        assert!(err.to_string().contains("Authentication failed"), "{}", err);
+        assert_eq!(attempts(dir.path()), 1);
+    }
+
+    #[test]
+    fn test_report_error_when_not_quiet() {
+        let dir = tempdir().unwrap();
+        let mut command = flaky_command(dir.path(), 1, "fatal: permission denied");
+        let err = run(&mut command, RETRY, false).unwrap_err();
+        assert!(err.to_string().contains("permission denied"), "{}", err);
+    }
+
+    #[test]
+    fn test_tee() {
+        let mut out = Vec::new();
+        let captured = tee("line 1\nline 2\n".as_bytes(), &mut out).unwrap();
+        assert_eq!(captured, b"line 1\nline 2\n");
+        assert_eq!(out, captured);
+    }
+
+    #[test]
+    fn test_failed_push_reports_git_error() {
+        let dir = tempdir().unwrap();
+        git2::Repository::init(dir.path()).unwrap();
+        let options = RemoteOptions {
+            path: dir.path(),
+            remote_name: "no-such-remote",
+            retry: RETRY,
+            ssh_command: None,
+            env: &BTreeMap::new(),
+        };
+
+        let err = RemoteGitCommand::Silent(options)
+            .delete_branch("feature")
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("'no-such-remote' does not appear to be a git repository"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_ssh_command_and_env() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        repo.remote("origin", "ssh://git@example.com/repo.git")
+            .unwrap();
+        // Stands in for ssh, records how it was called and fails the connection
+        let ssh = dir.path().join("fake-ssh");
+        std::fs::write(
+            &ssh,
+            "#!/bin/sh\necho \"$UBR_TEST $*\" >> \"$(dirname \"$0\")/ssh.log\"\nexit 1\n",
+        )
+        .unwrap();
+        std::fs::set_permissions(&ssh, std::os::unix::fs::PermissionsExt::from_mode(0o755))
+            .unwrap();
+        let ssh_command = format!("{} -i deploy_key", ssh.display());
+        let env = [("UBR_TEST".to_string(), "from-config".to_string())].into();
+        let options = RemoteOptions {
+            path: dir.path(),
+            remote_name: "origin",
+            retry: Retry::default(),
+            ssh_command: Some(&ssh_command),
+            env: &env,
+        };
+
+        assert!(RemoteGitCommand::Silent(options).fetch().is_err());
+        let log = std::fs::read_to_string(dir.path().join("ssh.log")).unwrap();
+        assert!(
+            log.starts_with("from-config -i deploy_key ") && log.contains("git@example.com"),
+            "{}",
+            log
+        );
+    }
+
+    #[test]
+    fn test_dry_run_prints_push_command() {
+        let options = RemoteOptions {
+            path: std::path::Path::new("."),
+            remote_name: "origin",
+            retry: Retry::default(),
+            ssh_command: None,
+            env: &BTreeMap::new(),
+        };
+        let meta_data = CommitMetadata::new(
+            "user/feature",
+            Oid::from_str("1234567890abcdef1234567890abcdef12345678").unwrap(),
+        );
+
+        let mut out = Vec::new();
+        print_dry_run(&push_args(&options, &meta_data), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "git push --no-verify --force-with-lease -- origin \
+             1234567890abcdef1234567890abcdef12345678:refs/heads/user/feature\n"
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+
+use crate::config::ForgeKind;
+
+/// Build a link to `branch` on the web UI of the forge hosting `remote_url`.
+///
+/// Both SSH (`git@github.com:org/repo.git`, `ssh://git@github.com/org/repo.git`) and
+/// HTTPS remotes are supported. Returns `None` for hosts that aren't recognized.
+pub fn branch_url(remote_url: &str, branch: &str) -> Option<String> {
+    match repo_path(remote_url)? {
+        ("github.com", path) => Some(format!("https://github.com/{}/tree/{}", path, branch)),
+        ("gitlab.com", path) => Some(format!("https://gitlab.com/{}/-/tree/{}", path, branch)),
+        _ => None,
+    }
+}
+
+/// Build a link to PR `number` on the web UI of the forge hosting `remote_url`, see
+/// [`branch_url`].
+pub fn pr_url(remote_url: &str, number: u64) -> Option<String> {
+    match repo_path(remote_url)? {
+        ("github.com", path) => Some(format!("https://github.com/{}/pull/{}", path, number)),
+        ("gitlab.com", path) => Some(format!(
+            "https://gitlab.com/{}/-/merge_requests/{}",
+            path, number
+        )),
+        _ => None,
+    }
+}
+
+/// The host and the path of the repository on it, without `.git`.
+pub(crate) fn repo_path(remote_url: &str) -> Option<(&str, &str)> {
+    let (host, path) = split_remote_url(remote_url)?;
+    let path = path.trim_matches('/');
+    let path = path.strip_suffix(".git").unwrap_or(path);
+    if path.is_empty() {
+        return None;
+    }
+    Some((host, path))
+}
+
+/// The forge hosting `remote_url`: github.com, gitlab.com or one of the `hosts`
+/// configured with `forge-hosts`. Only exact host names match.
+pub fn detect_forge(remote_url: &str, hosts: &BTreeMap<String, ForgeKind>) -> Option<ForgeKind> {
+    let (host, _) = split_remote_url(remote_url)?;
+    match host {
+        "github.com" => Some(ForgeKind::Github),
+        "gitlab.com" => Some(ForgeKind::Gitlab),
+        _ => hosts.get(host).copied(),
+    }
+}
+
+fn split_remote_url(remote_url: &str) -> Option<(&str, &str)> {
+    let url_without_scheme = ["https://", "http://", "ssh://"]
+        .iter()
+        .find_map(|scheme| remote_url.strip_prefix(scheme));
+    let (host, path) = if let Some(rest) = url_without_scheme {
+        let (authority, path) = rest.split_once('/')?;
+        let host = authority.rsplit('@').next()?;
+        (host.split(':').next()?, path)
+    } else {
+        // scp-like syntax, user@host:path
+        let (user_host, path) = remote_url.split_once(':')?;
+        (user_host.rsplit('@').next()?, path)
+    };
+    Some((host, path))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{branch_url, detect_forge, pr_url};
+    use crate::config::ForgeKind;
+
+    #[test]
+    fn test_github_ssh() {
+        assert_eq!(
+            branch_url("git@github.com:org/repo.git", "my-branch"),
+            Some("https://github.com/org/repo/tree/my-branch".to_string())
+        );
+        assert_eq!(
+            branch_url("ssh://git@github.com/org/repo.git", "my-branch"),
+            Some("https://github.com/org/repo/tree/my-branch".to_string())
+        );
+    }
+
+    #[test]
+    fn test_github_https() {
+        assert_eq!(
+            branch_url("https://github.com/org/repo.git", "my-branch"),
+            Some("https://github.com/org/repo/tree/my-branch".to_string())
+        );
+        assert_eq!(
+            branch_url("https://user@github.com/org/repo/", "my-branch"),
+            Some("https://github.com/org/repo/tree/my-branch".to_string())
+        );
+    }
+
+    #[test]
+    fn test_gitlab() {
+        assert_eq!(
+            branch_url("git@gitlab.com:group/sub/repo.git", "my-branch"),
+            Some("https://gitlab.com/group/sub/repo/-/tree/my-branch".to_string())
+        );
+        assert_eq!(
+            branch_url("https://gitlab.com/group/repo", "my-branch"),
+            Some("https://gitlab.com/group/repo/-/tree/my-branch".to_string())
+        );
+    }
+
+    #[test]
+    fn test_pr_url() {
+        assert_eq!(
+            pr_url("git@github.com:org/repo.git", 12),
+            Some("https://github.com/org/repo/pull/12".to_string())
+        );
+        assert_eq!(
+            pr_url("https://gitlab.com/group/repo.git", 7),
+            Some("https://gitlab.com/group/repo/-/merge_requests/7".to_string())
+        );
+        assert_eq!(pr_url("/tmp/some/local/repo", 7), None);
+    }
+
+    #[test]
+    fn test_self_hosted() {
+        assert_eq!(branch_url("git@git.example.com:org/repo.git", "b"), None);
+        assert_eq!(
+            branch_url("ssh://git@git.example.com:2222/org/repo", "b"),
+            None
+        );
+        assert_eq!(branch_url("/tmp/some/local/repo", "b"), None);
+    }
+
+    #[test]
+    fn test_detect_forge() {
+        let hosts = [("git.example.com".to_string(), ForgeKind::Gitlab)].into();
+        assert_eq!(
+            detect_forge("git@github.com:org/repo.git", &hosts),
+            Some(ForgeKind::Github)
+        );
+        assert_eq!(
+            detect_forge("https://gitlab.com/group/repo.git", &hosts),
+            Some(ForgeKind::Gitlab)
+        );
+        assert_eq!(
+            detect_forge("ssh://git@git.example.com:2222/org/repo", &hosts),
+            Some(ForgeKind::Gitlab)
+        );
+        assert_eq!(
+            detect_forge("https://notgithub.com/org/repo.git", &hosts),
+            None
+        );
+        assert_eq!(
+            detect_forge("https://gitlab.example.com/group/repo.git", &hosts),
+            None
+        );
+        assert_eq!(detect_forge("/tmp/some/local/repo", &hosts), None);
+    }
+}
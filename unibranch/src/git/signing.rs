@@ -0,0 +1,66 @@
+//! Signing of the commits created for the PR branches, for remotes that only accept signed
+//! commits. It is configured like `git commit -S`: `gpg.format` picks `openpgp` (the
+//! default) or `ssh`, `user.signingkey` the key and `gpg.program`/`gpg.ssh.program` the
+//! program that signs.
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use anyhow::Context;
+use git2::Signature;
+
+/// Sign the commit in `buffer`, returning the armored signature.
+pub(crate) fn sign(
+    config: &git2::Config,
+    buffer: &str,
+    committer: &Signature,
+) -> anyhow::Result<String> {
+    let format = config
+        .get_string("gpg.format")
+        .unwrap_or_else(|_| "openpgp".to_string());
+    let key = config.get_string("user.signingkey").ok();
+    let mut command = match format.as_str() {
+        "openpgp" => {
+            let program = config
+                .get_string("gpg.program")
+                .unwrap_or_else(|_| "gpg".to_string());
+            // Like git, fall back to the key of the committer
+            let key = key.unwrap_or_else(|| committer.to_string());
+            let mut command = Command::new(program);
+            command.arg("--status-fd=2").arg("-bsau").arg(key);
+            command
+        }
+        "ssh" => {
+            let program = config
+                .get_string("gpg.ssh.program")
+                .unwrap_or_else(|_| "ssh-keygen".to_string());
+            let key = key.context("'user.signingkey' must be set to sign with ssh")?;
+            let mut command = Command::new(program);
+            command.args(["-Y", "sign", "-n", "git", "-f"]).arg(key);
+            command
+        }
+        format => anyhow::bail!("Signing with gpg.format '{}' is not supported", format),
+    };
+
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Running {:?}", command))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(buffer.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "Signing the commit failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    String::from_utf8(output.stdout).context("Signature is not valid UTF-8")
+}
@@ -0,0 +1,127 @@
+//! `Ubr-Source` and `Ubr-Branch` trailers on the commits pushed to the PR branches, when
+//! `trailers` is set in `.ubr/config.toml`:
+//!
+//! ```text
+//! Ubr-Source: <local-commit>
+//! Ubr-Branch: <remote-branch>
+//! ```
+//!
+//! They link a PR's commits to the local commit even if the notes are lost. Other trailers
+//! can be added to a PR with `create --trailer`.
+//!
+//! A local commit with `Ubr-Ignore: true` is never turned into a PR, like one marked with
+//! `ubr ignore`.
+use git2::{Commit, Oid};
+
+use super::local_commit::CommitMetadata;
+
+pub const SOURCE: &str = "Ubr-Source";
+pub const BRANCH: &str = "Ubr-Branch";
+pub const IGNORE: &str = "Ubr-Ignore";
+
+/// `message` with the trailers for `source` and `branch_name` appended.
+pub fn append(message: &str, source: Oid, branch_name: &str) -> String {
+    add(
+        message,
+        [(SOURCE, source.to_string().as_str()), (BRANCH, branch_name)],
+    )
+}
+
+/// `message` with `trailers` appended, to the trailers that the message already ends with
+/// if it does.
+pub fn add<'a>(message: &str, trailers: impl IntoIterator<Item = (&'a str, &'a str)>) -> String {
+    let has_trailers = git2::message_trailers_strs(message).is_ok_and(|t| t.len() > 0);
+    let mut result = message.trim_end().to_string();
+    result.push_str(if has_trailers { "\n" } else { "\n\n" });
+    for (key, value) in trailers {
+        result.push_str(&format!("{}: {}\n", key, value));
+    }
+    result
+}
+
+/// `message` without the trailers added by [`append`], `None` if it has none.
+pub fn strip(message: &str) -> Option<&str> {
+    let start = message.rfind(&format!("\n{}: ", SOURCE))?;
+    let ours = message[start..].trim().lines().all(|line| {
+        line.starts_with(&format!("{}: ", SOURCE)) || line.starts_with(&format!("{}: ", BRANCH))
+    });
+    ours.then(|| message[..start].trim_end())
+}
+
+/// Whether `message` has an `Ubr-Ignore: true` trailer.
+pub fn is_ignored(message: &str) -> bool {
+    git2::message_trailers_strs(message).is_ok_and(|trailers| {
+        trailers
+            .iter()
+            .any(|(key, value)| key == IGNORE && value.trim().eq_ignore_ascii_case("true"))
+    })
+}
+
+/// The local commit and the metadata recorded in the trailers of `commit`, the head of a
+/// remote branch.
+pub fn parse(commit: &Commit) -> Option<(Oid, CommitMetadata<'static>)> {
+    let trailers = git2::message_trailers_strs(commit.message()?).ok()?;
+    let mut source = None;
+    let mut branch_name = None;
+    for (key, value) in trailers.iter() {
+        if key == SOURCE {
+            source = value.trim().parse::<Oid>().ok();
+        } else if key == BRANCH {
+            branch_name = Some(value.trim().to_string());
+        }
+    }
+    let meta_data = CommitMetadata::new(branch_name?, commit.id());
+    Some((source?, meta_data))
+}
+
+#[cfg(test)]
+mod test {
+    use git2::Oid;
+
+    use super::{add, append, is_ignored, strip};
+
+    #[test]
+    fn test_strip_appended_trailers() {
+        let source = Oid::from_str("6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84").unwrap();
+        let message = append("Subject\n\nBody\n", source, "subject");
+        assert_eq!(
+            message,
+            "Subject\n\nBody\n\n\
+             Ubr-Source: 6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84\n\
+             Ubr-Branch: subject\n"
+        );
+        assert_eq!(strip(&message), Some("Subject\n\nBody"));
+        assert_eq!(strip("Subject\n\nSigned-off-by: Someone\n"), None);
+
+        let message = append("Subject\n\nSigned-off-by: Someone\n", source, "subject");
+        assert_eq!(
+            message,
+            "Subject\n\nSigned-off-by: Someone\n\
+             Ubr-Source: 6ec67b364e67bbd74c66fc8f0cbb95e6ac155d84\n\
+             Ubr-Branch: subject\n"
+        );
+        assert_eq!(strip(&message), Some("Subject\n\nSigned-off-by: Someone"));
+    }
+
+    #[test]
+    fn test_add_trailers() {
+        assert_eq!(
+            add("Subject\n", [("Fixes", "#12")]),
+            "Subject\n\nFixes: #12\n"
+        );
+        assert_eq!(
+            add(
+                "Subject\n\nBody\n\nFixes: #12\n",
+                [("Reviewed-by", "Someone")]
+            ),
+            "Subject\n\nBody\n\nFixes: #12\nReviewed-by: Someone\n"
+        );
+    }
+
+    #[test]
+    fn test_is_ignored() {
+        assert!(is_ignored("WIP\n\nUbr-Ignore: true\n"));
+        assert!(!is_ignored("WIP\n\nUbr-Ignore: false\n"));
+        assert!(!is_ignored("Ubr-Ignore: true\n"));
+    }
+}
@@ -1,2 +1,5 @@
 pub mod commands;
+pub mod config;
+pub mod forge;
 pub mod git;
+pub mod progress;
@@ -1,41 +1,158 @@
+use std::{path::PathBuf, process::ExitCode};
+
 use anyhow::Context;
-use clap::{command, Parser, Subcommand};
+use clap::{ArgAction, Parser, Subcommand};
 use tracing::level_filters::LevelFilter;
 use tracing_subscriber::EnvFilter;
 use ubr::{
-    commands::{create, push, sync},
+    commands::{
+        clean,
+        color::{self, ColorChoice},
+        create, doctor, drop, ignore, log, publish, push, rebase, resync_notes, set_base, squash,
+        status, sync, verbosity, version, ExitStatus,
+    },
+    config::{Config, StrategyOption},
     git::{CommandOption, GitRepo},
+    progress::{NoProgress, ProgressReporter, StderrProgress},
 };
 
+mod select;
+
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
 
-    #[arg(short, long)]
+    /// Only print errors
+    #[arg(short, long, conflicts_with = "verbose")]
     quiet: bool,
 
     #[arg(short, long)]
     dry_run: bool,
 
-    #[arg(short, long)]
-    verbose: bool,
+    /// Run as if ubr was started in this directory instead of the current one, like
+    /// 'git -C'
+    #[arg(
+        short = 'C',
+        long = "repo",
+        global = true,
+        value_name = "PATH",
+        default_value = "."
+    )]
+    repo: PathBuf,
+
+    /// Remote to push to, overrides 'remote' in .ubr/config.toml
+    #[arg(long, global = true)]
+    remote: Option<String>,
+
+    /// Remote branch the stack is based on, overrides 'base' in .ubr/config.toml
+    #[arg(long, global = true)]
+    base: Option<String>,
+
+    /// Prepended to the names of the remote branches of new PRs, overrides 'branch-prefix'
+    /// in .ubr/config.toml
+    #[arg(long, global = true, value_name = "PREFIX")]
+    branch_prefix: Option<String>,
+
+    /// Number of times to retry a fetch or push that failed because of a network problem,
+    /// overrides 'retries' in .ubr/config.toml
+    #[arg(long, global = true, value_name = "N")]
+    retries: Option<u32>,
+
+    /// Command used by git to connect over ssh, like 'GIT_SSH_COMMAND', overrides
+    /// 'ssh-command' in .ubr/config.toml
+    #[arg(long, global = true, value_name = "COMMAND")]
+    ssh_command: Option<String>,
+
+    /// Sign the created commits, overrides 'sign' in .ubr/config.toml
+    #[arg(long, global = true)]
+    sign: bool,
+
+    /// Option for merging the local changes with the remote branches, can be given
+    /// multiple times, overrides 'strategy-options' in .ubr/config.toml
+    #[arg(short = 'X', long, global = true, value_enum, value_name = "OPTION")]
+    strategy_option: Vec<StrategyOption>,
+
+    /// Keep the committer dates of rewritten commits, overrides 'keep-committer-date' in
+    /// .ubr/config.toml
+    #[arg(long, global = true)]
+    keep_committer_date: bool,
+
+    /// Use the author date as the committer date of every commit ubr creates, overrides
+    /// 'committer-date-is-author-date' in .ubr/config.toml
+    #[arg(long, global = true)]
+    committer_date_is_author_date: bool,
+
+    /// When to color the output
+    #[arg(long, global = true, value_enum, default_value_t = ColorChoice::Auto)]
+    color: ColorChoice,
+
+    /// Increase the log level, '-v' for debug and '-vv' for trace output
+    #[arg(short, long, action = ArgAction::Count)]
+    verbose: u8,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    Create(create::Options),
+    Create {
+        #[command(flatten)]
+        options: create::Options,
+        /// Select the commits to create PRs for from a list of the commits in the stack
+        #[arg(short, long, conflicts_with_all = ["commit_refs", "stdin"])]
+        interactive: bool,
+    },
+    /// Update every tracked commit with the changes on its remote branch and push the
+    /// local changes
+    #[command(alias = "pull")]
     Sync(sync::Options),
     Push,
+    /// Create PRs for the untracked commits in the stack and sync the tracked ones
+    Publish(publish::Options),
+    /// Move the stack onto the head of the base branch, keeping the PRs of the commits
+    Rebase(rebase::Options),
+    ResyncNotes(resync_notes::Options),
+    /// Stack the PR of a commit on the PR of another commit, or on the base branch
+    SetBase(set_base::Options),
+    Squash(squash::Options),
+    /// Stop tracking a commit, deleting its remote branch and closing its PR
+    Drop(drop::Options),
+    /// Show the commits of the stack and the state of their PRs
+    Status(status::Options),
+    Log(log::Options),
+    /// Never create a PR for an untracked commit
+    Ignore(ignore::Options),
+    /// Allow creating a PR for a commit marked with 'ignore' again
+    Unignore(ignore::Options),
+    /// Check the repository and the configuration for common problems
+    Doctor,
+    /// Remove the files in .ubr/ left behind by a ubr process that was killed
+    Clean(clean::Options),
+    /// Show the version of ubr and of the libgit2 it uses, for bug reports
+    Version,
 }
 
-fn main() -> anyhow::Result<()> {
+/// Exit codes:
+///   0 - success
+///   1 - error
+///   2 - stopped on merge conflicts, resolve them and run again with '--continue'
+fn main() -> ExitCode {
     let cli = Cli::parse();
-    let level = if cli.verbose {
-        LevelFilter::DEBUG
-    } else {
-        LevelFilter::INFO
+    match run(cli) {
+        Ok(()) => ExitStatus::Success.into(),
+        Err(err) => {
+            eprintln!("Error: {:?}", err);
+            ExitStatus::from(&err).into()
+        }
+    }
+}
+
+fn run(cli: Cli) -> anyhow::Result<()> {
+    let level = match cli.verbose {
+        _ if cli.quiet => LevelFilter::ERROR,
+        0 => LevelFilter::INFO,
+        1 => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
     };
     let subscriber = tracing_subscriber::fmt()
         .pretty()
@@ -44,6 +161,7 @@ fn main() -> anyhow::Result<()> {
         .with_thread_ids(false)
         .without_time()
         .with_target(false)
+        .with_writer(std::io::stderr)
         .with_env_filter(
             EnvFilter::builder()
                 .with_default_directive(level.into())
@@ -52,6 +170,12 @@ fn main() -> anyhow::Result<()> {
         .finish();
     tracing::subscriber::set_global_default(subscriber)?;
 
+    color::set_choice(cli.color);
+    verbosity::set_quiet(cli.quiet);
+    if let Commands::Version = cli.command {
+        return version::execute();
+    }
+
     let remote_option = if cli.dry_run {
         CommandOption::DryRun
     } else if cli.quiet {
@@ -59,12 +183,112 @@ fn main() -> anyhow::Result<()> {
     } else {
         CommandOption::Default
     };
-    let git_repo = GitRepo::open_with_remote(".", remote_option).context("Opening GIT repo")?;
+    let mut config = Config::load(GitRepo::main_worktree(&cli.repo)?)?;
+    if let Some(remote) = cli.remote {
+        config.remote = remote;
+    }
+    if cli.base.is_some() {
+        config.base = cli.base;
+    }
+    if cli.branch_prefix.is_some() {
+        config.branch_prefix = cli.branch_prefix;
+    }
+    if let Some(retries) = cli.retries {
+        config.retries = retries;
+    }
+    if cli.ssh_command.is_some() {
+        config.ssh_command = cli.ssh_command;
+    }
+    if cli.sign {
+        config.sign = true;
+    }
+    if cli.keep_committer_date {
+        config.keep_committer_date = true;
+    }
+    if cli.committer_date_is_author_date {
+        config.committer_date_is_author_date = true;
+    }
+    if !cli.strategy_option.is_empty() {
+        config.strategy_options = cli.strategy_option;
+    }
+    if let Commands::Doctor = cli.command {
+        // Opening the repo changes the git config, the doctor only looks
+        return doctor::execute(&cli.repo, &config);
+    }
+    let git_repo =
+        GitRepo::open_with_config(&cli.repo, remote_option, config).context("Opening GIT repo")?;
+
+    let progress: &dyn ProgressReporter = if cli.quiet {
+        &NoProgress
+    } else {
+        &StderrProgress
+    };
 
     match cli.command {
-        Commands::Create(config) => create::execute(config, git_repo)?,
-        Commands::Sync(config) => sync::execute(config, git_repo)?,
-        Commands::Push => push::execute(".")?,
+        Commands::Create {
+            mut options,
+            interactive,
+        } => {
+            if interactive {
+                options.commit_refs = select::select_commits(&git_repo)?;
+                if options.commit_refs.is_empty() {
+                    return Ok(());
+                }
+            }
+            create::execute_with_progress(options, git_repo, progress)?
+        }
+        Commands::Sync(config) => sync::execute_with_progress(config, git_repo, progress)?,
+        Commands::Push => push::execute(git_repo)?,
+        Commands::Publish(config) => publish::execute_with_progress(config, git_repo, progress)?,
+        Commands::Rebase(config) => rebase::execute(config, git_repo)?,
+        Commands::ResyncNotes(config) => resync_notes::execute(config, git_repo)?,
+        Commands::SetBase(config) => set_base::execute(config, git_repo)?,
+        Commands::Squash(config) => squash::execute(config, git_repo)?,
+        Commands::Drop(config) => drop::execute(config, git_repo)?,
+        Commands::Status(config) => status::execute(config, git_repo)?,
+        Commands::Log(config) => log::execute(config, git_repo)?,
+        Commands::Ignore(config) => ignore::execute(config, git_repo)?,
+        Commands::Unignore(config) => ignore::execute_unignore(config, git_repo)?,
+        Commands::Clean(config) => clean::execute(config, git_repo)?,
+        Commands::Doctor => unreachable!("handled before opening the repo"),
+        Commands::Version => unreachable!("handled before loading the config"),
     };
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use clap::Parser;
+
+    use super::{Cli, Commands};
+
+    #[test]
+    fn test_quiet_conflicts_with_verbose() {
+        let err = Cli::try_parse_from(["ubr", "--quiet", "-v", "sync"])
+            .err()
+            .expect("'--quiet' and '--verbose' should conflict");
+        assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+        assert!(Cli::try_parse_from(["ubr", "--quiet", "sync"]).is_ok());
+    }
+
+    #[test]
+    fn test_pull_is_an_alias_for_sync() {
+        let cli = Cli::try_parse_from(["ubr", "pull", "--continue"]).unwrap();
+        assert!(matches!(cli.command, Commands::Sync(options) if options.cont));
+    }
+
+    #[test]
+    fn test_interactive_create() {
+        let cli = Cli::try_parse_from(["ubr", "create", "-i"]).unwrap();
+        assert!(matches!(cli.command, Commands::Create { interactive, .. } if interactive));
+        for args in [
+            ["ubr", "create", "-i", "HEAD"],
+            ["ubr", "create", "-i", "--stdin"],
+        ] {
+            let err = Cli::try_parse_from(args)
+                .err()
+                .expect("'--interactive' should conflict with the commits");
+            assert_eq!(err.kind(), clap::error::ErrorKind::ArgumentConflict);
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use std::path::PathBuf;
+
+use git2::Commit;
+
+/// Receives progress events from the commands while they are working through the stack.
+///
+/// All methods have empty default implementations so that implementors only need to
+/// override the events they care about.
+pub trait ProgressReporter {
+    fn on_commit_start(&self, _commit: &Commit) {}
+
+    fn on_push(&self, _remote_branch_name: &str) {}
+
+    fn on_conflict(&self, _paths: &[PathBuf]) {}
+}
+
+/// Ignores all progress events.
+pub struct NoProgress;
+
+impl ProgressReporter for NoProgress {}
+
+/// Writes progress events to stderr, keeping stdout free for command output.
+pub struct StderrProgress;
+
+impl ProgressReporter for StderrProgress {
+    fn on_commit_start(&self, commit: &Commit) {
+        eprintln!(
+            "Processing {} {}",
+            commit.id(),
+            commit.summary().unwrap_or("")
+        );
+    }
+
+    fn on_push(&self, remote_branch_name: &str) {
+        eprintln!("Pushing branch {}", remote_branch_name);
+    }
+
+    fn on_conflict(&self, paths: &[PathBuf]) {
+        eprintln!("Conflicts in:");
+        for path in paths {
+            eprintln!("    {}", path.display());
+        }
+    }
+}
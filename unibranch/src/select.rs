@@ -0,0 +1,81 @@
+//! `create --interactive`, which asks on the terminal which commits of the stack to create
+//! PRs for. The selected commits are passed to [`create`](ubr::commands::create) like commits given on the
+//! command line.
+use std::io::{BufRead, IsTerminal};
+
+use ubr::git::{local_commit::MainCommit, GitRepo};
+
+/// Ask which of the commits in the stack to create PRs for, oldest first.
+pub fn select_commits(git_repo: &GitRepo) -> anyhow::Result<Vec<String>> {
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!(
+            "'--interactive' needs a terminal, pass the commits to create PRs for instead"
+        );
+    }
+    let mut commits = git_repo.unpushed_commits()?;
+    commits.retain(|commit| !commit.is_ignored());
+    if commits.is_empty() {
+        anyhow::bail!("There are no commits in the stack");
+    }
+    for (i, commit) in commits.iter().enumerate() {
+        let id = commit.id().to_string();
+        let tracked = match commit {
+            MainCommit::Tracked(tracked) => {
+                format!(" ({})", tracked.meta_data().remote_branch_name)
+            }
+            MainCommit::UnTracked(_) => String::new(),
+        };
+        eprintln!(
+            "{:>3}) {} {}{}",
+            i + 1,
+            &id[..7],
+            commit.as_commit().summary().unwrap_or(""),
+            tracked
+        );
+    }
+    eprint!("Create PRs for (e.g. '1 3-4', empty for none): ");
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(parse_selection(&answer, commits.len())?
+        .into_iter()
+        .map(|i| commits[i].id().to_string())
+        .collect())
+}
+
+/// The 0-based indexes of the 1-based numbers and ranges like `3-4` in `answer`, in
+/// increasing order.
+fn parse_selection(answer: &str, len: usize) -> anyhow::Result<Vec<usize>> {
+    let mut selected = std::collections::BTreeSet::new();
+    for item in answer.split(|c: char| c == ',' || c.is_whitespace()) {
+        if item.is_empty() {
+            continue;
+        }
+        let (first, last) = item.split_once('-').unwrap_or((item, item));
+        let parse = |number: &str| match number.parse::<usize>() {
+            Ok(n) if (1..=len).contains(&n) => Ok(n - 1),
+            _ => Err(anyhow::anyhow!(
+                "'{}' is not a number from 1 to {}",
+                item,
+                len
+            )),
+        };
+        selected.extend(parse(first)?..=parse(last)?);
+    }
+    Ok(selected.into_iter().collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_selection;
+
+    #[test]
+    fn test_parse_selection() {
+        assert_eq!(parse_selection("3 1-2\n", 4).unwrap(), vec![0, 1, 2]);
+        assert_eq!(parse_selection("2,4, 2", 4).unwrap(), vec![1, 3]);
+        assert_eq!(parse_selection("\n", 4).unwrap(), Vec::<usize>::new());
+        assert_eq!(
+            parse_selection("1 5", 4).unwrap_err().to_string(),
+            "'5' is not a number from 1 to 4"
+        );
+    }
+}
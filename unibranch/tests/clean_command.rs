@@ -0,0 +1,104 @@
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::clean,
+    git::{lock, GitRepo},
+};
+
+use pretty_assertions::assert_eq;
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+#[test]
+fn stale_state_is_removed() {
+    let remote_repo = RemoteRepo::new();
+    let repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push();
+    let ubr_dir = repo.path().join(".ubr");
+    std::fs::create_dir(&ubr_dir).unwrap();
+    std::fs::write(ubr_dir.join("SYNC_MERGE_HEAD"), "{}").unwrap();
+    std::fs::write(ubr_dir.join("SYNC_MERGE_HEAD.tmp"), "{").unwrap();
+
+    let result = clean::plan(clean::Options::default(), &git_repo(&repo)).unwrap();
+
+    assert_eq!(
+        result.removed,
+        vec![
+            ubr_dir.join("SYNC_MERGE_HEAD"),
+            ubr_dir.join("SYNC_MERGE_HEAD.tmp")
+        ]
+    );
+    assert!(result.kept.is_empty());
+    assert!(!ubr_dir.exists());
+}
+
+#[test]
+fn active_sync_is_only_removed_with_force() {
+    let remote_repo = RemoteRepo::new();
+    let repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .checkout_new_branch("other")
+        .append_file("File1", "Other change")
+        .commit_all("other")
+        .checkout("master")
+        .append_file("File1", "Conflicting change")
+        .commit_all("commit2");
+    assert!(!repo
+        .run_command()
+        .args(["merge", "other"])
+        .status()
+        .unwrap()
+        .success());
+    let ubr_dir = repo.path().join(".ubr");
+    std::fs::create_dir(&ubr_dir).unwrap();
+    std::fs::write(ubr_dir.join("SYNC_MERGE_HEAD"), "{}").unwrap();
+
+    let result = clean::plan(clean::Options::default(), &git_repo(&repo)).unwrap();
+    assert!(result.removed.is_empty());
+    assert_eq!(result.kept, vec![ubr_dir.join("SYNC_MERGE_HEAD")]);
+    assert!(ubr_dir.join("SYNC_MERGE_HEAD").exists());
+
+    let result = clean::plan(clean::Options { force: true }, &git_repo(&repo)).unwrap();
+    assert_eq!(result.removed, vec![ubr_dir.join("SYNC_MERGE_HEAD")]);
+    assert!(!ubr_dir.exists());
+}
+
+#[test]
+fn lock_is_only_removed_when_its_process_is_gone_or_with_force() {
+    let remote_repo = RemoteRepo::new();
+    let repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push();
+    let lock_file = repo.path().join(lock::LOCK_FILE);
+    std::fs::create_dir(lock_file.parent().unwrap()).unwrap();
+    std::fs::write(&lock_file, format!("{}\n", std::process::id())).unwrap();
+
+    let err = clean::plan(clean::Options::default(), &git_repo(&repo)).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "another ubr process is running, run with '--force' to remove its lock anyway"
+    );
+    assert!(lock_file.exists());
+
+    let result = clean::plan(clean::Options { force: true }, &git_repo(&repo)).unwrap();
+    assert_eq!(result.removed, vec![lock_file.clone()]);
+    assert!(!lock_file.exists());
+
+    let mut child = std::process::Command::new("true").spawn().unwrap();
+    child.wait().unwrap();
+    std::fs::create_dir(lock_file.parent().unwrap()).unwrap();
+    std::fs::write(&lock_file, format!("{}\n", child.id())).unwrap();
+
+    let result = clean::plan(clean::Options::default(), &git_repo(&repo)).unwrap();
+    assert_eq!(result.removed, vec![lock_file.clone()]);
+    assert!(!repo.path().join(".ubr").exists());
+}
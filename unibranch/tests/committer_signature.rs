@@ -0,0 +1,58 @@
+//! Kept in its own test binary since it changes the environment of the whole process.
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, sync},
+    git::GitRepo,
+};
+
+use pretty_assertions::assert_eq;
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+#[test]
+fn sync_without_configured_identity() {
+    let home = tempfile::tempdir().unwrap();
+    std::env::set_var("HOME", home.path());
+    std::env::set_var("XDG_CONFIG_HOME", home.path());
+    std::env::set_var("GIT_CONFIG_NOSYSTEM", "1");
+    // Only picked up by the git binary used to set up the test repos, not by libgit2
+    for (key, value) in [
+        ("GIT_AUTHOR_NAME", "Test Author"),
+        ("GIT_AUTHOR_EMAIL", "author@example.com"),
+        ("GIT_COMMITTER_NAME", "Test Author"),
+        ("GIT_COMMITTER_EMAIL", "author@example.com"),
+    ] {
+        std::env::set_var(key, value);
+    }
+    std::env::set_var("GIT_COMMITTER_DATE", "@1700000000 +0100");
+
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Some more changes")
+        .commit_all("pr commit");
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+
+    let local_repo = local_repo
+        .create_file("File2", "Some other changes")
+        .commit_all_amend();
+    remote_repo
+        .clone_repo()
+        .checkout("pr-commit")
+        .append_file("File1", "Remote fixes")
+        .commit_all("Fixup")
+        .push();
+
+    sync::execute(sync::Options::default(), git_repo(&local_repo)).unwrap();
+
+    let merge = local_repo.find_commit_by_reference("refs/remotes/origin/pr-commit");
+    assert_eq!(merge.message(), Some("Merge"));
+    assert_eq!(merge.committer().email(), Some("author@example.com"));
+    assert_eq!(merge.committer().when().seconds(), 1700000000);
+    assert_eq!(merge.committer().when().offset_minutes(), 60);
+}
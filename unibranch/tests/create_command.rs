@@ -1,14 +1,21 @@
-use git2::Oid;
+use std::cell::RefCell;
+
+use git2::{Commit, Oid};
 use indoc::indoc;
 use test_repo::{RemoteRepo, TestRepoWithRemote};
-use ubr::{commands::create, git::GitRepo};
+use ubr::{
+    commands::{create, sync},
+    config::Config,
+    git::{local_commit::MainCommit, CommandOption, GitRepo},
+    progress::ProgressReporter,
+};
 
 use pretty_assertions::assert_eq;
 
 fn create_options(commit_ref: Option<Oid>) -> create::Options {
     create::Options {
-        commit_ref: commit_ref.map(|id| format!("{}", id)),
-        force: false,
+        commit_refs: commit_ref.map(|id| format!("{}", id)).into_iter().collect(),
+        ..Default::default()
     }
 }
 
@@ -65,7 +72,7 @@ fn basic_test() {
         ),
         1,
     );
-    assert_eq!(repo.find_note("head"), expected_note,);
+    assert_eq!(repo.find_note("HEAD"), expected_note,);
 }
 
 #[test]
@@ -118,11 +125,11 @@ fn test_create_from_not_head_commit() {
         ),
         1,
     );
-    assert_eq!(repo.find_note("head^"), expected_note,);
+    assert_eq!(repo.find_note("HEAD^"), expected_note,);
 }
 
 #[test]
-fn should_not_be_able_to_call_create_for_same_commit_twice() {
+fn create_for_same_commit_twice_is_a_no_op() {
     let remote = RemoteRepo::new();
     let repo = remote.clone_repo();
 
@@ -138,8 +145,36 @@ fn should_not_be_able_to_call_create_for_same_commit_twice() {
     let current_dir = repo.path();
 
     create::execute(create_options(None), GitRepo::open(current_dir).unwrap()).unwrap();
+    let remote_head = repo.ls_remote_heads("commit2").stdout;
+    let note = repo.find_note("HEAD");
+
+    let result = create::plan(
+        create_options(None),
+        &GitRepo::open(current_dir).unwrap(),
+        &ubr::progress::NoProgress,
+    )
+    .unwrap();
+    assert!(result.branches[0].up_to_date);
+    assert_eq!(result.to_string(), "commit2: up to date\n");
+    assert_eq!(repo.ls_remote_heads("commit2").stdout, remote_head);
+    assert_eq!(repo.find_note("HEAD"), note);
+}
+
+#[test]
+fn should_not_be_able_to_create_changed_commit_twice() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
 
-    let result = create::execute(create_options(None), GitRepo::open(current_dir).unwrap());
+    create::execute(create_options(None), git_repo(&repo)).unwrap();
+    let repo = repo.append_file("File1", "More lines").commit_all_amend();
+
+    let result = create::execute(create_options(None), git_repo(&repo));
     assert!(result.is_err());
 }
 
@@ -164,9 +199,1063 @@ fn force_if_already_tracked() {
     create::execute(
         create::Options {
             force: true,
-            commit_ref: None,
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+}
+
+#[derive(Default)]
+struct RecordingProgress {
+    events: RefCell<Vec<String>>,
+}
+
+impl ProgressReporter for RecordingProgress {
+    fn on_commit_start(&self, commit: &Commit) {
+        self.events
+            .borrow_mut()
+            .push(format!("start {}", commit.summary().unwrap()));
+    }
+
+    fn on_push(&self, remote_branch_name: &str) {
+        self.events
+            .borrow_mut()
+            .push(format!("push {}", remote_branch_name));
+    }
+}
+
+#[test]
+fn reports_progress() {
+    let remote = RemoteRepo::new();
+    let repo = remote.clone_repo();
+
+    let repo = repo
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push();
+
+    let repo = repo
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+
+    let progress = RecordingProgress::default();
+    create::execute_with_progress(create_options(None), git_repo(&repo), &progress).unwrap();
+
+    assert_eq!(
+        progress.events.into_inner(),
+        vec!["start commit2".to_string(), "push commit2".to_string()]
+    );
+}
+
+#[test]
+fn branch_naming_from_config() {
+    let remote = RemoteRepo::new();
+    let repo = remote.clone_repo();
+
+    let repo = repo
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push();
+
+    let repo = repo
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+
+    std::fs::create_dir_all(repo.path().join(".ubr")).unwrap();
+    std::fs::write(
+        repo.path().join(".ubr/config.toml"),
+        "branch-naming = \"commit-id\"\n",
+    )
+    .unwrap();
+
+    create::execute(create_options(None), git_repo(&repo)).unwrap();
+
+    let branch_name = format!("ubr/{}", &repo.head().to_string()[..7]);
+    let remote_head = repo.ls_remote_heads(&branch_name);
+    assert!(!remote_head.stdout.is_empty());
+    assert!(repo.ls_remote_heads("commit2").stdout.is_empty());
+}
+
+#[test]
+fn custom_notes_ref_survives_amend() {
+    let remote = RemoteRepo::new();
+    let repo = remote.clone_repo();
+
+    let repo = repo
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push();
+
+    let repo = repo
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+
+    std::fs::create_dir_all(repo.path().join(".ubr")).unwrap();
+    std::fs::write(
+        repo.path().join(".ubr/config.toml"),
+        "notes-ref = \"refs/notes/ubr\"\n",
+    )
+    .unwrap();
+
+    create::execute(create_options(None), git_repo(&repo)).unwrap();
+
+    let repo = repo.append_file("File1", "More lines").commit_all_amend();
+
+    let expected_note = indoc! {"
+            remote-branch: commit2
+            remote-commit: {}
+        "}
+    .replacen("{}", &repo.rev_parse("origin/commit2"), 1);
+    assert_eq!(
+        repo.find_note_in_ref("refs/notes/ubr", "HEAD"),
+        expected_note
+    );
+    assert_eq!(repo.find_note("HEAD"), "");
+}
+
+#[test]
+fn create_multiple_commits() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2")
+        .create_file("File3", "Hello")
+        .commit_all("commit3");
+
+    create::execute(
+        create::Options {
+            commit_refs: vec!["HEAD^".to_string(), "HEAD".to_string()],
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+
+    assert!(!repo.ls_remote_heads("commit2").stdout.is_empty());
+    assert!(!repo.ls_remote_heads("commit3").stdout.is_empty());
+}
+
+#[test]
+fn create_multiple_commits_stops_at_first_failure() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2")
+        .create_file("File3", "Hello")
+        .commit_all("commit3");
+
+    let result = create::execute(
+        create::Options {
+            commit_refs: vec![
+                "HEAD^".to_string(),
+                "HEAD~2".to_string(),
+                "HEAD".to_string(),
+            ],
+            ..Default::default()
+        },
+        git_repo(&repo),
+    );
+
+    assert_eq!(
+        format!("{}", result.unwrap_err()),
+        "Creating PR for 'HEAD~2'"
+    );
+    assert!(!repo.ls_remote_heads("commit2").stdout.is_empty());
+    assert!(repo.ls_remote_heads("commit3").stdout.is_empty());
+}
+
+#[test]
+fn create_multiple_commits_in_parallel() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2")
+        .create_file("File3", "Hello")
+        .commit_all("commit3")
+        .create_file("File4", "Hello")
+        .commit_all("commit4");
+
+    create::execute(
+        create::Options {
+            commit_refs: vec![
+                "HEAD~2".to_string(),
+                "HEAD^".to_string(),
+                "HEAD".to_string(),
+            ],
+            jobs: 2,
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+
+    for branch in ["commit2", "commit3", "commit4"] {
+        assert!(!repo.ls_remote_heads(branch).stdout.is_empty());
+    }
+}
+
+#[test]
+fn dry_run_does_not_push_or_track() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+
+    let git_repo = GitRepo::open_with_remote(repo.path(), CommandOption::DryRun).unwrap();
+    create::execute(create_options(None), git_repo).unwrap();
+
+    assert!(repo.ls_remote_heads("commit2").stdout.is_empty());
+    assert_eq!(repo.find_note("HEAD"), "");
+}
+
+fn install_hook(repo: &TestRepoWithRemote, name: &str, script: &str) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let hooks_dir = repo.path().join(".ubr/hooks");
+    std::fs::create_dir_all(&hooks_dir).unwrap();
+    let hook = hooks_dir.join(name);
+    std::fs::write(&hook, script).unwrap();
+    std::fs::set_permissions(&hook, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn failing_pre_push_hook_aborts_push() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+    install_hook(&repo, "pre-push", "#!/bin/sh\nexit 1\n");
+
+    let err = create::execute(create_options(None), git_repo(&repo)).unwrap_err();
+
+    assert_eq!(
+        err.to_string(),
+        "'pre-push' hook failed for commit2 (exit status: 1)"
+    );
+    assert!(repo.ls_remote_heads("commit2").stdout.is_empty());
+}
+
+#[test]
+fn no_verify_skips_failing_pre_push_hook() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+    install_hook(&repo, "pre-push", "#!/bin/sh\nexit 1\n");
+
+    create::execute(
+        create::Options {
+            no_verify: true,
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+    assert!(!repo.ls_remote_heads("commit2").stdout.is_empty());
+
+    let repo = repo
+        .append_file("File1", "Some PR review fixes")
+        .commit_all_amend();
+    sync::execute(
+        sync::Options {
+            no_verify: true,
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+    repo.assert_diff("origin/commit2", "master", "");
+}
+
+#[test]
+fn failing_to_save_note_deletes_pushed_branch() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+    // A stale lock makes writing the note fail
+    let lock = repo.path().join(".git/refs/notes/commits.lock");
+    std::fs::create_dir_all(lock.parent().unwrap()).unwrap();
+    std::fs::write(&lock, "").unwrap();
+
+    let err = create::execute(create_options(None), git_repo(&repo)).unwrap_err();
+
+    assert_eq!(
+        err.to_string(),
+        format!("Saving the metadata of {}", repo.head())
+    );
+    assert!(repo.ls_remote_heads("commit2").stdout.is_empty());
+    assert!(matches!(
+        git_repo(&repo).find_unpushed_commit("HEAD").unwrap(),
+        MainCommit::UnTracked(_)
+    ));
+}
+
+/// Make the remote reject pushes to `branch`
+fn reject_pushes_to(remote: &RemoteRepo, branch: &str) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let hook = remote.path().join("hooks/pre-receive");
+    std::fs::create_dir_all(hook.parent().unwrap()).unwrap();
+    std::fs::write(
+        &hook,
+        format!(
+            "#!/bin/sh\nwhile read old new ref; do\n  [ \"$ref\" = refs/heads/{} ] && exit 1\ndone\nexit 0\n",
+            branch
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&hook, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn failing_to_save_note_with_force_keeps_existing_branch() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+    create::execute(create_options(None), git_repo(&repo)).unwrap();
+    let repo = repo.append_file("File1", "More lines").commit_all_amend();
+    let note = repo.find_note("HEAD");
+    let lock = repo.path().join(".git/refs/notes/commits.lock");
+    std::fs::write(&lock, "").unwrap();
+
+    let err = create::execute(
+        create::Options {
+            force: true,
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err.to_string(),
+        format!("Saving the metadata of {}", repo.head())
+    );
+    assert!(!repo.ls_remote_heads("commit2").stdout.is_empty());
+    assert_eq!(repo.find_note("HEAD"), note);
+}
+
+#[test]
+fn failing_push_with_force_keeps_the_note() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+    create::execute(create_options(None), git_repo(&repo)).unwrap();
+    let repo = repo.append_file("File1", "More lines").commit_all_amend();
+    let note = repo.find_note("HEAD");
+    reject_pushes_to(&remote, "commit2");
+
+    create::execute(
+        create::Options {
+            force: true,
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap_err();
+
+    assert_eq!(repo.find_note("HEAD"), note);
+}
+
+#[test]
+fn create_multiple_commits_tracks_the_pushed_ones_when_a_push_fails() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2")
+        .create_file("File3", "Hello")
+        .commit_all("commit3");
+    reject_pushes_to(&remote, "commit3");
+
+    create::execute(
+        create::Options {
+            commit_refs: vec!["HEAD^".to_string(), "HEAD".to_string()],
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap_err();
+
+    assert!(!repo.ls_remote_heads("commit2").stdout.is_empty());
+    assert!(matches!(
+        git_repo(&repo).find_unpushed_commit("HEAD^").unwrap(),
+        MainCommit::Tracked(_)
+    ));
+    assert!(matches!(
+        git_repo(&repo).find_unpushed_commit("HEAD").unwrap(),
+        MainCommit::UnTracked(_)
+    ));
+}
+
+#[test]
+fn post_create_hook_gets_commit_and_branch() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+    install_hook(
+        &repo,
+        "post-create",
+        "#!/bin/sh\necho \"$1 $2 $UBR_REMOTE_BRANCH\" > hook-output\n",
+    );
+
+    create::execute(create_options(None), git_repo(&repo)).unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(repo.path().join("hook-output")).unwrap(),
+        format!("{} commit2 commit2\n", repo.rev_parse("origin/commit2"))
+    );
+}
+
+#[test]
+fn create_stacked_prs_for_a_range() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .append_file("File1", "Hello again")
+        .commit_all("pushed commit")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2")
+        .create_file("File3", "Hello again")
+        .commit_all("commit3")
+        .create_file("File4", "Hello a third time")
+        .commit_all("commit4");
+
+    let err = create::execute(
+        create::Options {
+            commit_refs: vec!["HEAD~4..HEAD".to_string()],
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "Commit {} is already pushed to the remote",
+            repo.find_commit(3).id()
+        )
+    );
+    assert!(repo.ls_remote_heads("commit2").stdout.is_empty());
+
+    create::execute(
+        create::Options {
+            commit_refs: vec!["HEAD~3..HEAD".to_string()],
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+
+    assert_eq!(
+        repo.rev_parse("origin/commit2^"),
+        repo.rev_parse("origin/master")
+    );
+    assert_eq!(
+        repo.rev_parse("origin/commit3^"),
+        repo.rev_parse("origin/commit2")
+    );
+    assert_eq!(
+        repo.rev_parse("origin/commit4^"),
+        repo.rev_parse("origin/commit3")
+    );
+    assert!(!repo.find_note("HEAD~2").contains("base-branch"));
+    assert!(repo.find_note("HEAD~1").ends_with("base-branch: commit2\n"));
+    assert!(repo.find_note("HEAD").ends_with("base-branch: commit3\n"));
+}
+
+#[test]
+fn create_stacked_on_another_pr() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2")
+        .create_file("File3", "Hello again")
+        .commit_all("commit3");
+    create::execute(
+        create::Options {
+            commit_refs: vec!["HEAD~1".to_string()],
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+
+    create::execute(
+        create::Options {
+            base_of: Some("HEAD~1".to_string()),
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+
+    assert_eq!(
+        repo.rev_parse("origin/commit3^"),
+        repo.rev_parse("origin/commit2")
+    );
+    assert_eq!(
+        repo.find_note("HEAD"),
+        format!(
+            "remote-branch: commit3\nremote-commit: {}\nbase-branch: commit2\n",
+            repo.rev_parse("origin/commit3")
+        )
+    );
+
+    let repo = repo.append_file("File3", "Review fixes").commit_all_amend();
+    sync::execute(sync::Options::default(), git_repo(&repo)).unwrap();
+
+    repo.assert_diff(
+        "origin/commit2",
+        "origin/commit3",
+        &repo_diff(&repo, "HEAD^", "HEAD"),
+    );
+}
+
+fn repo_diff(repo: &TestRepoWithRemote, rev1: &str, rev2: &str) -> String {
+    String::from_utf8(repo.diff(rev1, rev2).stdout).unwrap()
+}
+
+#[test]
+fn plan_returns_created_branches() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+
+    let result = create::plan(
+        create::Options::default(),
+        &git_repo(&repo),
+        &ubr::progress::NoProgress,
+    )
+    .unwrap();
+
+    assert_eq!(result.branches.len(), 1);
+    let created = &result.branches[0];
+    assert_eq!(created.commit.to_string(), repo.rev_parse("HEAD"));
+    assert_eq!(created.meta_data.remote_branch_name, "commit2");
+    assert_eq!(
+        created.meta_data.remote_commit.to_string(),
+        repo.rev_parse("origin/commit2")
+    );
+    assert_eq!(created.url, None);
+}
+
+#[test]
+fn create_with_message() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("local message");
+
+    create::execute(
+        create::Options {
+            message: Some("A better PR message".to_string()),
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+
+    assert_eq!(
+        repo.find_commit_by_reference("refs/remotes/origin/local-message")
+            .message(),
+        Some("A better PR message\n")
+    );
+    repo.assert_log(vec!["local message\n"]);
+    assert!(repo
+        .find_note("HEAD")
+        .starts_with("remote-branch: local-message\n"));
+
+    // The message is kept on later syncs
+    let repo = repo
+        .append_file("File1", "Some PR review fixes")
+        .commit_all_amend();
+    sync::execute(sync::Options::default(), git_repo(&repo)).unwrap();
+    let fixup = repo.find_commit_by_reference("refs/remotes/origin/local-message");
+    assert_eq!(fixup.message(), Some("fixup! A better PR message"));
+    assert_eq!(
+        fixup.parent(0).unwrap().message(),
+        Some("A better PR message\n")
+    );
+}
+
+#[test]
+fn create_with_empty_message() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+
+    let err = create::execute(
+        create::Options {
+            message: Some("  \n".to_string()),
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "The message given with '--message' is empty"
+    );
+    assert_eq!(repo.find_note("HEAD"), "");
+}
+
+#[test]
+fn create_signed_commits() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+
+    let key_dir = tempfile::tempdir().unwrap();
+    let key = key_dir.path().join("id_ed25519");
+    assert!(std::process::Command::new("ssh-keygen")
+        .args(["-q", "-t", "ed25519", "-N", "", "-f"])
+        .arg(&key)
+        .status()
+        .unwrap()
+        .success());
+    for (name, value) in [
+        ("gpg.format", "ssh"),
+        ("user.signingkey", key.to_str().unwrap()),
+        ("commit.gpgsign", "true"),
+    ] {
+        assert!(repo
+            .run_command()
+            .args(["config", name, value])
+            .status()
+            .unwrap()
+            .success());
+    }
+
+    create::execute(create_options(None), git_repo(&repo)).unwrap();
+
+    let remote_commit = repo.find_commit_by_reference("refs/remotes/origin/commit2");
+    let git2_repo = git2::Repository::open(repo.path()).unwrap();
+    let (signature, _) = git2_repo
+        .extract_signature(&remote_commit.id(), None)
+        .expect("Commit is not signed");
+    assert!(signature
+        .as_str()
+        .unwrap()
+        .starts_with("-----BEGIN SSH SIGNATURE-----"));
+}
+
+#[test]
+fn create_sets_upstream_of_pr_branch() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+
+    create::execute(create_options(None), git_repo(&repo)).unwrap();
+
+    let config = git2::Repository::open(repo.path())
+        .unwrap()
+        .config()
+        .unwrap()
+        .snapshot()
+        .unwrap();
+    assert_eq!(config.get_str("branch.commit2.remote").unwrap(), "origin");
+    assert_eq!(
+        config.get_str("branch.commit2.merge").unwrap(),
+        "refs/heads/commit2"
+    );
+}
+
+#[test]
+fn empty_commits_need_allow_empty() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push();
+    assert!(repo
+        .run_command()
+        .args(["commit", "-q", "--allow-empty", "-m", "empty"])
+        .status()
+        .unwrap()
+        .success());
+
+    let err = create::execute(create_options(None), git_repo(&repo)).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "{} is empty, there is nothing to create a PR for. Use '--allow-empty' to create it anyway",
+            repo.head()
+        )
+    );
+    assert!(repo.ls_remote_heads("empty").stdout.is_empty());
+
+    create::execute(
+        create::Options {
+            allow_empty: true,
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+    let remote_head = repo.rev_parse("origin/empty");
+
+    // Sync skips the empty commit instead of pushing it again
+    let result = sync::plan(
+        sync::Options::default(),
+        git_repo(&repo),
+        &ubr::progress::NoProgress,
+    )
+    .unwrap();
+    assert!(result.pushed.is_empty());
+    assert_eq!(result.empty.len(), 1);
+    assert_eq!(result.empty[0].remote_branch_name, "empty");
+    assert_eq!(result.empty[0].commit, repo.head());
+    assert_eq!(repo.rev_parse("origin/empty"), remote_head);
+
+    let result = sync::plan(
+        sync::Options {
+            allow_empty: true,
+            ..Default::default()
+        },
+        git_repo(&repo),
+        &ubr::progress::NoProgress,
+    )
+    .unwrap();
+    assert!(result.empty.is_empty());
+    assert_eq!(result.pushed.len(), 1);
+}
+
+#[test]
+fn interactive_create_needs_a_terminal() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_ubr"))
+        .current_dir(repo.path())
+        .args(["create", "--interactive"])
+        .stdin(std::process::Stdio::null())
+        .output()
+        .unwrap();
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .contains("'--interactive' needs a terminal, pass the commits to create PRs for instead"));
+    assert!(repo.ls_remote_heads("commit2").stdout.is_empty());
+}
+
+#[test]
+fn create_with_repo_path_from_another_directory() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+    let cwd = tempfile::tempdir().unwrap();
+
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_ubr"))
+        .current_dir(cwd.path())
+        .arg("-C")
+        .arg(repo.path())
+        .arg("create")
+        .status()
+        .unwrap();
+
+    assert!(status.success());
+    assert!(!repo.ls_remote_heads("commit2").stdout.is_empty());
+    assert!(!repo.find_note("HEAD").is_empty());
+}
+
+#[test]
+fn create_with_trailers() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+
+    create::execute(
+        create::Options {
+            trailers: vec![
+                ("Fixes".to_string(), "#12".to_string()),
+                ("Reviewed-by".to_string(), "Some One".to_string()),
+            ],
+            ..Default::default()
         },
         git_repo(&repo),
     )
     .unwrap();
+
+    let remote_commit = repo.find_commit_by_reference("refs/remotes/origin/commit2");
+    assert_eq!(
+        remote_commit.message(),
+        Some("commit2\n\nFixes: #12\nReviewed-by: Some One\n")
+    );
+    let remote_commit = remote_commit.id();
+    // The trailers are kept by a sync
+    sync::execute(sync::Options::default(), git_repo(&repo)).unwrap();
+    assert_eq!(
+        repo.rev_parse("origin/commit2").trim(),
+        remote_commit.to_string()
+    );
+}
+
+#[test]
+fn create_with_author() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+    let local_committer = repo.find_commit(0).author().name().map(str::to_string);
+
+    create::execute(
+        create::Options {
+            author: Some(("Some One".to_string(), "one@example.com".to_string())),
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+
+    let remote_commit = repo.find_commit_by_reference("refs/remotes/origin/commit2");
+    assert_eq!(remote_commit.author().name(), Some("Some One"));
+    assert_eq!(remote_commit.author().email(), Some("one@example.com"));
+    assert_eq!(
+        remote_commit.committer().name().map(str::to_string),
+        local_committer
+    );
+    // The local commit is left as it is
+    assert_ne!(repo.find_commit(0).author().name(), Some("Some One"));
+}
+
+#[test]
+fn create_with_branch_prefix() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+    let git_repo = |repo: &TestRepoWithRemote| {
+        let config = Config {
+            branch_prefix: Some("users/alice/".to_string()),
+            ..Default::default()
+        };
+        GitRepo::open_with_config(repo.path(), CommandOption::Default, config).unwrap()
+    };
+
+    create::execute(create::Options::default(), git_repo(&repo)).unwrap();
+
+    let remote_commit = repo.rev_parse("origin/users/alice/commit2");
+    assert_eq!(
+        repo.find_note("HEAD"),
+        format!(
+            "remote-branch: users/alice/commit2\nremote-commit: {}\n",
+            remote_commit
+        )
+    );
+
+    // The prefix isn't added again
+    let repo = repo
+        .append_file("File1", "Some PR review fixes")
+        .commit_all_amend();
+    sync::execute(sync::Options::default(), git_repo(&repo)).unwrap();
+    assert!(repo
+        .find_note("HEAD")
+        .starts_with("remote-branch: users/alice/commit2\n"));
+    assert_ne!(repo.rev_parse("origin/users/alice/commit2"), remote_commit);
+}
+
+#[test]
+fn create_from_stdin() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2")
+        .create_file("File3", "Hello again")
+        .commit_all("commit3");
+    let input = format!(
+        "{}\n\n# Not a commit\n{}\n",
+        repo.find_commit(0).id(),
+        repo.find_commit(1).id()
+    );
+
+    create::execute_from_reader(
+        create::Options::default(),
+        std::io::Cursor::new(input),
+        &git_repo(&repo),
+        &ubr::progress::NoProgress,
+    )
+    .unwrap();
+
+    // Every commit gets a PR of its own on the base branch
+    for branch in ["commit2", "commit3"] {
+        assert_eq!(
+            repo.rev_parse(&format!("origin/{}^", branch)),
+            repo.rev_parse("origin/master")
+        );
+    }
+}
+
+#[test]
+fn create_from_stdin_continues_after_a_failure() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2");
+
+    let err = create::execute_from_reader(
+        create::Options::default(),
+        std::io::Cursor::new("no-such-commit\nHEAD\n"),
+        &git_repo(&repo),
+        &ubr::progress::NoProgress,
+    )
+    .unwrap_err();
+
+    assert_eq!(err.to_string(), "Creating PRs failed for 1 of 2 commits");
+    assert!(!repo.ls_remote_heads("commit2").stdout.is_empty());
+}
+
+#[test]
+fn create_with_non_ascii_subjects() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("🎉🚀")
+        .create_file("File3", "Hello again")
+        .commit_all("修复登录错误")
+        .create_file("File4", "Hello once more")
+        .commit_all("🐛 Fix the login");
+    let ids = [2, 1].map(|n| repo.find_commit(n).id().to_string());
+
+    create::execute(
+        create::Options {
+            commit_refs: vec![
+                "HEAD~2".to_string(),
+                "HEAD~1".to_string(),
+                "HEAD".to_string(),
+            ],
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+
+    let branches = [
+        format!("ubr/{}", &ids[0][..7]),
+        format!("ubr/{}", &ids[1][..7]),
+        "fix-the-login".to_string(),
+    ];
+    for branch in branches {
+        assert!(
+            !repo.ls_remote_heads(&branch).stdout.is_empty(),
+            "{} was not pushed",
+            branch
+        );
+    }
 }
@@ -0,0 +1,50 @@
+//! Kept in its own test binary since it sets `GIT_EDITOR` for the whole process.
+use std::os::unix::fs::PermissionsExt;
+
+use pretty_assertions::assert_eq;
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{commands::create, git::GitRepo};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+#[test]
+fn edit_pr_title_and_body() {
+    let editor_dir = tempfile::tempdir().unwrap();
+    let editor = editor_dir.path().join("fake-editor");
+    std::fs::write(
+        &editor,
+        "#!/bin/sh\nprintf 'Better title\\n\\nSome details\\n# A comment\\n' > \"$1\"\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&editor, std::fs::Permissions::from_mode(0o755)).unwrap();
+    std::env::set_var("GIT_EDITOR", &editor);
+
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+
+    create::execute(
+        create::Options {
+            edit: true,
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+
+    let note = repo.find_note("HEAD");
+    assert!(note.contains("pr-title: Better title\n"), "{}", note);
+    assert!(note.contains("pr-body: Some details\n"), "{}", note);
+    assert_eq!(
+        repo.find_commit_by_reference("refs/remotes/origin/commit2")
+            .message(),
+        Some("commit2\n")
+    );
+}
@@ -0,0 +1,112 @@
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{commands::doctor, config::Config};
+
+fn failed_checks(repo: &TestRepoWithRemote) -> Vec<String> {
+    doctor::checks(repo.path(), &Config::default())
+        .unwrap()
+        .into_iter()
+        .filter(|check| !check.passed)
+        .map(|check| check.name)
+        .collect()
+}
+
+#[test]
+fn doctor_on_a_fresh_clone() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push();
+
+    assert_eq!(failed_checks(&repo), vec!["'notes.rewriteRef' is set"]);
+    doctor::execute(repo.path(), &Config::default()).unwrap();
+
+    // The doctor doesn't fix anything by itself
+    assert_eq!(failed_checks(&repo), vec!["'notes.rewriteRef' is set"]);
+}
+
+#[test]
+fn doctor_fails_on_detached_head() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push();
+    assert!(repo
+        .run_command()
+        .args(["checkout", "-q", "--detach"])
+        .status()
+        .unwrap()
+        .success());
+
+    assert!(failed_checks(&repo).contains(&"HEAD is a branch".to_string()));
+    let err = doctor::execute(repo.path(), &Config::default()).unwrap_err();
+    assert_eq!(err.to_string(), "1 critical check(s) failed");
+}
+
+#[test]
+fn doctor_warns_about_sync_in_progress() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push();
+    std::fs::create_dir_all(repo.path().join(".ubr")).unwrap();
+    std::fs::write(repo.path().join(".ubr/SYNC_MERGE_HEAD"), "{}").unwrap();
+
+    assert!(failed_checks(&repo).contains(&"no sync in progress".to_string()));
+    doctor::execute(repo.path(), &Config::default()).unwrap();
+}
+
+#[test]
+fn doctor_fails_without_base_branch() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push();
+    let config = Config {
+        base: Some("does-not-exist".to_string()),
+        remote: "upstream".to_string(),
+        ..Default::default()
+    };
+
+    let failed: Vec<_> = doctor::checks(repo.path(), &config)
+        .unwrap()
+        .into_iter()
+        .filter(|check| !check.passed)
+        .map(|check| check.name)
+        .collect();
+    assert!(failed.contains(&"base branch 'upstream/does-not-exist' exists".to_string()));
+}
+
+#[test]
+fn doctor_fails_when_ubr_is_not_writable() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push();
+    // Nothing can be created in it, whatever its permissions say
+    std::fs::write(repo.path().join(".ubr"), "").unwrap();
+
+    assert!(failed_checks(&repo).contains(&".ubr/ is writable".to_string()));
+    assert!(doctor::execute(repo.path(), &Config::default()).is_err());
+}
+
+#[test]
+fn missing_user_config_is_a_warning() {
+    let remote = RemoteRepo::new();
+    let repo = remote.clone_repo();
+
+    let checks = doctor::checks(repo.path(), &Config::default()).unwrap();
+    for name in ["'user.name' is set", "'user.email' is set"] {
+        let check = checks.iter().find(|check| check.name == name).unwrap();
+        assert!(!check.critical, "{}", name);
+    }
+}
@@ -0,0 +1,86 @@
+use pretty_assertions::assert_eq;
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, drop},
+    git::{CommandOption, GitRepo},
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+fn tracked_repo(remote: &RemoteRepo) -> TestRepoWithRemote<'_> {
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2");
+    create::execute(create::Options::default(), git_repo(&repo)).unwrap();
+    repo
+}
+
+#[test]
+fn drop_deletes_branch_and_note() {
+    let remote = RemoteRepo::new();
+    let repo = tracked_repo(&remote);
+    let head = repo.head();
+
+    let result = drop::plan(
+        drop::Options {
+            commit_ref: "HEAD".to_string(),
+        },
+        &git_repo(&repo),
+    )
+    .unwrap();
+
+    assert_eq!(result.commit, head);
+    assert_eq!(result.meta_data.remote_branch_name, "commit2");
+    assert!(repo.ls_remote_heads("commit2").stdout.is_empty());
+    assert!(repo.find_note("HEAD").is_empty());
+    assert_eq!(repo.head(), head);
+}
+
+#[test]
+fn dry_run_drop_keeps_branch_and_note() {
+    let remote = RemoteRepo::new();
+    let repo = tracked_repo(&remote);
+    let note = repo.find_note("HEAD");
+
+    let result = drop::plan(
+        drop::Options {
+            commit_ref: "HEAD".to_string(),
+        },
+        &GitRepo::open_with_remote(repo.path(), CommandOption::DryRun).unwrap(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        result.to_string(),
+        format!(
+            "would delete remote branch 'origin/commit2'\n\
+             would remove note on commit {}\n",
+            repo.head()
+        )
+    );
+    assert!(!repo.ls_remote_heads("commit2").stdout.is_empty());
+    assert_eq!(repo.find_note("HEAD"), note);
+}
+
+#[test]
+fn dry_run_drop_of_untracked_commit_fails() {
+    let remote = RemoteRepo::new();
+    let repo = tracked_repo(&remote)
+        .create_file("File3", "Hi")
+        .commit_all("commit3");
+
+    let err = drop::plan(
+        drop::Options {
+            commit_ref: "HEAD".to_string(),
+        },
+        &GitRepo::open_with_remote(repo.path(), CommandOption::DryRun).unwrap(),
+    )
+    .unwrap_err();
+    assert_eq!(err.to_string(), "'HEAD' is not tracked");
+}
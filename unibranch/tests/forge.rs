@@ -0,0 +1,173 @@
+//! The forge clients, with fake `gh` and `curl` commands on the PATH. The fakes append how
+//! they were called, and what they read on stdin, to `.git/forge.log` of the repository.
+#![cfg(all(feature = "github", feature = "gitlab"))]
+
+use std::sync::OnceLock;
+
+use tempfile::TempDir;
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{commands::create, forge, git::GitRepo};
+
+static FAKE_BIN: OnceLock<TempDir> = OnceLock::new();
+
+const FAKE_GH: &str = r#"#!/bin/sh
+echo "gh $*" >> .git/forge.log
+if [ "$1 $2" = "pr create" ]; then
+    echo "https://github.com/org/repo/pull/12"
+fi
+"#;
+
+const FAKE_CURL: &str = r#"#!/bin/sh
+echo "curl $*" >> .git/forge.log
+cat >> .git/forge.log
+echo '{"iid": 7, "state": "opened"}'
+"#;
+
+fn install_fakes() {
+    FAKE_BIN.get_or_init(|| {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        for (name, script) in [("gh", FAKE_GH), ("curl", FAKE_CURL)] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, script).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        let path = format!(
+            "{}:{}",
+            dir.path().display(),
+            std::env::var("PATH").unwrap()
+        );
+        std::env::set_var("PATH", path);
+        std::env::set_var("GITLAB_TOKEN", "secret-token");
+        dir
+    });
+}
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+fn forge_log(repo: &TestRepoWithRemote) -> String {
+    std::fs::read_to_string(repo.path().join(".git/forge.log")).unwrap()
+}
+
+fn set_forge(repo: &TestRepoWithRemote, forge: &str) {
+    std::fs::create_dir_all(repo.path().join(".ubr")).unwrap();
+    std::fs::write(
+        repo.path().join(".ubr/config.toml"),
+        format!("forge = \"{}\"\n", forge),
+    )
+    .unwrap();
+}
+
+/// Make the remote look like it is hosted on gitlab.com, while still pushing to `remote`
+fn set_gitlab_url(repo: &TestRepoWithRemote, remote: &RemoteRepo) {
+    for args in [
+        vec![
+            "remote",
+            "set-url",
+            "origin",
+            "https://gitlab.com/group/repo.git",
+        ],
+        vec![
+            "remote",
+            "set-url",
+            "--push",
+            "origin",
+            remote.path().to_str().unwrap(),
+        ],
+    ] {
+        assert!(repo.run_command().args(args).status().unwrap().success());
+    }
+}
+
+fn repo_with_commit(remote: &RemoteRepo) -> TestRepoWithRemote<'_> {
+    remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2\n\nThe body")
+}
+
+#[test]
+fn create_opens_a_github_pr() {
+    install_fakes();
+    let remote = RemoteRepo::new();
+    let repo = repo_with_commit(&remote);
+    set_forge(&repo, "github");
+
+    create::execute(create::Options::default(), git_repo(&repo)).unwrap();
+
+    assert_eq!(
+        forge_log(&repo),
+        "gh pr create --head commit2 --base master --title commit2 --body The body\n"
+    );
+    assert!(repo.find_note("HEAD").contains("pr-number: 12\n"));
+}
+
+#[test]
+fn github_client_updates_and_closes_prs() {
+    install_fakes();
+    let remote = RemoteRepo::new();
+    let repo = repo_with_commit(&remote);
+    set_forge(&repo, "github");
+
+    let git_repo = git_repo(&repo);
+    let client = forge::client(&git_repo).unwrap();
+    client.update_pr(12, "New title", "New body").unwrap();
+    client.close_pr(12).unwrap();
+
+    assert_eq!(
+        forge_log(&repo),
+        "gh pr edit 12 --title New title --body New body\ngh pr close 12\n"
+    );
+}
+
+#[test]
+fn create_opens_a_gitlab_merge_request() {
+    install_fakes();
+    let remote = RemoteRepo::new();
+    let repo = repo_with_commit(&remote);
+    set_gitlab_url(&repo, &remote);
+
+    create::execute(create::Options::default(), git_repo(&repo)).unwrap();
+
+    assert_eq!(
+        forge_log(&repo),
+        "curl --silent --show-error --fail-with-body --request POST --header @- \
+         --data-urlencode source_branch=commit2 --data-urlencode target_branch=master \
+         --data-urlencode title=commit2 --data-urlencode description=The body \
+         https://gitlab.com/api/v4/projects/group%2Frepo/merge_requests\n\
+         PRIVATE-TOKEN: secret-token\n"
+    );
+    assert!(repo.find_note("HEAD").contains("pr-number: 7\n"));
+}
+
+#[test]
+fn gitlab_client_updates_and_closes_merge_requests() {
+    install_fakes();
+    let remote = RemoteRepo::new();
+    let repo = repo_with_commit(&remote);
+    set_gitlab_url(&repo, &remote);
+
+    let git_repo = git_repo(&repo);
+    let client = forge::client(&git_repo).unwrap();
+    client.update_pr(7, "New title", "New body").unwrap();
+    client.close_pr(7).unwrap();
+
+    let url = "https://gitlab.com/api/v4/projects/group%2Frepo/merge_requests/7";
+    assert_eq!(
+        forge_log(&repo),
+        format!(
+            "curl --silent --show-error --fail-with-body --request PUT --header @- \
+             --data-urlencode title=New title --data-urlencode description=New body {url}\n\
+             PRIVATE-TOKEN: secret-token\n\
+             curl --silent --show-error --fail-with-body --request PUT --header @- \
+             --data-urlencode state_event=close {url}\n\
+             PRIVATE-TOKEN: secret-token\n"
+        )
+    );
+}
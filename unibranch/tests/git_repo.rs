@@ -1,8 +1,8 @@
 use pretty_assertions::assert_eq;
 use test_repo::{RemoteRepo, TestRepoWithRemote};
-use ubr::git::GitRepo;
+use ubr::{commands::create, git::GitRepo};
 
-fn init_repo(remote: &RemoteRepo) -> TestRepoWithRemote {
+fn init_repo(remote: &RemoteRepo) -> TestRepoWithRemote<'_> {
     let test_repo = remote.clone_repo();
 
     let test_repo = test_repo
@@ -87,3 +87,393 @@ fn find_an_already_pushed_commit() {
 
     assert!(repo.find_unpushed_commit("HEAD~4").is_err());
 }
+
+#[test]
+fn stack_starts_at_merge_base_when_upstream_has_moved() {
+    let remote_repo = RemoteRepo::new();
+    let test_repo = init_repo(&remote_repo);
+    let fork_point = test_repo.find_commit(4).id();
+    remote_repo
+        .clone_repo()
+        .create_file("File2", "Upstream changes")
+        .commit_all("upstream commit")
+        .push();
+    let test_repo = test_repo.fetch();
+    let repo = GitRepo::open(test_repo.path()).unwrap();
+
+    assert_eq!(repo.merge_base_with_upstream().unwrap(), fork_point);
+    assert_eq!(
+        repo.find_unpushed_commit("HEAD").unwrap().id(),
+        test_repo.find_commit(0).id()
+    );
+    assert!(repo.find_unpushed_commit("HEAD~4").is_err());
+    assert!(repo.find_unpushed_commit("origin/master").is_err());
+    let unpushed: Vec<_> = repo
+        .unpushed_commits()
+        .unwrap()
+        .iter()
+        .map(|c| c.id())
+        .collect();
+    assert_eq!(
+        unpushed,
+        (0..4)
+            .rev()
+            .map(|i| test_repo.find_commit(i).id())
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn tracked_commits_in_mixed_stack() {
+    let remote_repo = RemoteRepo::new();
+    let test_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2")
+        .create_file("File3", "Hello")
+        .commit_all("commit3")
+        .create_file("File4", "Hello")
+        .commit_all("commit4")
+        .create_file("File5", "Hello")
+        .commit_all("commit5");
+
+    for commit_ref in ["HEAD~2", "HEAD"] {
+        create::execute(
+            create::Options {
+                commit_refs: vec![commit_ref.to_string()],
+                ..Default::default()
+            },
+            GitRepo::open(test_repo.path()).unwrap(),
+        )
+        .unwrap();
+    }
+
+    let repo = GitRepo::open(test_repo.path()).unwrap();
+    let tracked: Vec<_> = repo
+        .tracked_commits()
+        .unwrap()
+        .iter()
+        .map(|c| c.as_commit().id())
+        .collect();
+
+    assert_eq!(
+        tracked,
+        vec![test_repo.find_commit(2).id(), test_repo.find_commit(0).id()]
+    );
+    assert_eq!(repo.unpushed_commits().unwrap().len(), 4);
+}
+
+#[test]
+fn base_commit_of_local_only_branch() {
+    let remote_repo = RemoteRepo::new();
+    let test_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .fetch();
+    let base = test_repo.head();
+    assert!(test_repo
+        .run_command()
+        .arg("remote")
+        .arg("set-head")
+        .arg("origin")
+        .arg("master")
+        .status()
+        .unwrap()
+        .success());
+    let test_repo = test_repo
+        .checkout_new_branch("feature")
+        .create_file("File2", "Hello")
+        .commit_all("commit2");
+
+    let repo = GitRepo::open(test_repo.path()).unwrap();
+    assert_eq!(repo.base_commit().unwrap().id(), base);
+    assert_eq!(repo.unpushed_commits().unwrap().len(), 1);
+}
+
+#[test]
+fn base_branch_is_the_default_branch_of_the_remote() {
+    let remote_repo = RemoteRepo::new();
+    let test_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Only on main")
+        .commit_all("main commit");
+    for args in [
+        &["push", "origin", "HEAD:main"][..],
+        &["remote", "set-head", "origin", "main"],
+    ] {
+        assert!(test_repo
+            .run_command()
+            .args(args)
+            .status()
+            .unwrap()
+            .success());
+    }
+    let main_head = test_repo.head();
+    let test_repo = test_repo
+        .checkout_new_branch("feature")
+        .create_file("File2", "Hello")
+        .commit_all("commit2");
+
+    let repo = GitRepo::open(test_repo.path()).unwrap();
+    assert_eq!(repo.base_branch_name(), "main");
+    assert_eq!(repo.base_commit().unwrap().id(), main_head);
+    assert_eq!(repo.unpushed_commits().unwrap().len(), 1);
+
+    // The current branch is used when the remote has it
+    let test_repo = test_repo.checkout("master");
+    let repo = GitRepo::open(test_repo.path()).unwrap();
+    assert_eq!(repo.base_branch_name(), "master");
+}
+
+#[test]
+fn base_commit_without_upstream() {
+    let remote_repo = RemoteRepo::new();
+    let test_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .checkout_new_branch("feature")
+        .create_file("File2", "Hello")
+        .commit_all("commit2");
+
+    let repo = GitRepo::open(test_repo.path()).unwrap();
+    assert_eq!(
+        format!("{}", repo.base_commit().unwrap_err()),
+        "Upstream for 'feature' not found; push it or pass --base"
+    );
+}
+
+#[test]
+fn base_commit_is_cached_until_invalidated() {
+    let remote_repo = RemoteRepo::new();
+    let test_repo = init_repo(&remote_repo);
+    let repo = GitRepo::open(test_repo.path()).unwrap();
+    let original_base = repo.base_commit().unwrap().id();
+
+    {
+        remote_repo
+            .clone_repo()
+            .append_file("File1", "Upstream change")
+            .commit_all("upstream")
+            .push();
+    }
+    let test_repo = test_repo.fetch();
+
+    assert_eq!(repo.base_commit().unwrap().id(), original_base);
+
+    repo.invalidate_base();
+    assert_eq!(
+        repo.base_commit().unwrap().id(),
+        test_repo
+            .find_commit_by_reference("refs/remotes/origin/master")
+            .id()
+    );
+}
+
+#[test]
+fn find_commit_by_tag() {
+    let remote_repo = RemoteRepo::new();
+    let test_repo = init_repo(&remote_repo);
+    test_repo
+        .run_command()
+        .args(["tag", "-a", "-m", "A tag", "my-tag", "HEAD~1"])
+        .status()
+        .unwrap();
+    let repo = GitRepo::open(test_repo.path()).unwrap();
+
+    assert_eq!(
+        repo.find_unpushed_commit("my-tag").unwrap().id(),
+        test_repo.find_commit(1).id()
+    );
+}
+
+#[test]
+fn find_commit_by_tree_ref() {
+    let remote_repo = RemoteRepo::new();
+    let test_repo = init_repo(&remote_repo);
+    let repo = GitRepo::open(test_repo.path()).unwrap();
+
+    let err = repo.find_unpushed_commit("HEAD^{tree}").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "'HEAD^{{tree}}' resolves to tree {}, not a commit",
+            test_repo.rev_parse("HEAD^{tree}")
+        )
+    );
+}
+
+#[test]
+fn unpushed_commits_since() {
+    let remote_repo = RemoteRepo::new();
+    let test_repo = init_repo(&remote_repo);
+    let mut repo = GitRepo::open(test_repo.path()).unwrap();
+
+    repo.set_since("HEAD~2").unwrap();
+
+    let messages = repo
+        .unpushed_commits()
+        .unwrap()
+        .iter()
+        .map(|c| c.message().unwrap().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(messages, vec!["commit4\n", "commit5\n"]);
+    assert_eq!(
+        repo.stack_base().unwrap().id(),
+        test_repo.find_commit(2).id()
+    );
+}
+
+#[test]
+fn since_must_be_an_ancestor_of_head() {
+    let remote_repo = RemoteRepo::new();
+    let test_repo = init_repo(&remote_repo)
+        .checkout_new_branch("other")
+        .create_file("File2", "On another branch")
+        .commit_all("other commit")
+        .checkout("master");
+    let mut repo = GitRepo::open(test_repo.path()).unwrap();
+
+    let err = repo.set_since("other").unwrap_err();
+
+    assert_eq!(err.to_string(), "'other' is not an ancestor of HEAD");
+}
+
+#[test]
+fn open_in_linked_worktree() {
+    let remote_repo = RemoteRepo::new();
+    let test_repo = init_repo(&remote_repo);
+    std::fs::create_dir_all(test_repo.path().join(".ubr")).unwrap();
+    std::fs::write(
+        test_repo.path().join(".ubr/config.toml"),
+        "base = \"master\"\nbranch-naming = \"commit-id\"\n",
+    )
+    .unwrap();
+
+    let worktree = tempfile::tempdir().unwrap();
+    let worktree_path = worktree.path().join("feature");
+    assert!(test_repo
+        .run_command()
+        .args(["worktree", "add", "-b", "feature"])
+        .arg(&worktree_path)
+        .arg("origin/master")
+        .status()
+        .unwrap()
+        .success());
+    std::fs::write(worktree_path.join("File2"), "A feature").unwrap();
+    for args in [vec!["add", "File2"], vec!["commit", "-q", "-m", "feature"]] {
+        assert!(test_repo
+            .run_command()
+            .current_dir(&worktree_path)
+            .args(args)
+            .status()
+            .unwrap()
+            .success());
+    }
+
+    let repo = GitRepo::open(&worktree_path).unwrap();
+    assert_eq!(repo.current_branch_name, "feature");
+    assert_eq!(
+        GitRepo::main_worktree(&worktree_path)
+            .unwrap()
+            .canonicalize()
+            .unwrap(),
+        test_repo.path().canonicalize().unwrap()
+    );
+    create::execute(create::Options::default(), repo).unwrap();
+
+    let head = test_repo.rev_parse("feature");
+    assert_eq!(
+        test_repo.rev_parse(&format!("origin/ubr/{}", &head[..7])),
+        head
+    );
+    let exclude = std::fs::read_to_string(test_repo.path().join(".git/info/exclude")).unwrap();
+    assert_eq!(exclude.lines().filter(|line| *line == ".ubr").count(), 1);
+    assert!(!test_repo
+        .path()
+        .join(".git/worktrees/feature/info/exclude")
+        .exists());
+}
+
+#[test]
+fn remote_branch_exists() {
+    let remote_repo = RemoteRepo::new();
+    let test_repo = init_repo(&remote_repo);
+    // A remote branch that doesn't point to a commit
+    let blob = test_repo.rev_parse("HEAD:File1");
+    test_repo
+        .run_command()
+        .args([
+            "update-ref",
+            "refs/remotes/origin/not-a-commit",
+            blob.trim(),
+        ])
+        .output()
+        .unwrap();
+    let repo = GitRepo::open(test_repo.path()).unwrap();
+
+    assert!(repo.remote_branch_exists("master"));
+    assert!(!repo.remote_branch_exists("no-such-branch"));
+    assert!(repo.remote_branch_exists("not-a-commit"));
+    assert!(repo.find_head_of_remote_branch("not-a-commit").is_none());
+}
+
+#[test]
+fn refuse_to_open_a_bare_repository() {
+    let dir = tempfile::tempdir().unwrap();
+    git2::Repository::init_bare(dir.path()).unwrap();
+
+    let err = GitRepo::open(dir.path())
+        .err()
+        .expect("Opened a bare repository");
+    assert_eq!(err.to_string(), "ubr cannot run in a bare repository");
+}
+
+#[test]
+fn remove_meta_data_twice() {
+    let remote_repo = RemoteRepo::new();
+    let test_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2");
+    create::execute(
+        create::Options::default(),
+        GitRepo::open(test_repo.path()).unwrap(),
+    )
+    .unwrap();
+    let repo = GitRepo::open(test_repo.path()).unwrap();
+    let head = repo.head().unwrap();
+
+    repo.remove_meta_data(&head).unwrap();
+    assert!(test_repo.find_note("HEAD").is_empty());
+    repo.remove_meta_data(&head)
+        .expect("Removing a missing note should succeed");
+}
+
+#[test]
+fn exclude_file_keeps_existing_entries() {
+    let remote_repo = RemoteRepo::new();
+    let test_repo = init_repo(&remote_repo);
+    let exclude_path = test_repo.path().join(".git/info/exclude");
+    std::fs::write(&exclude_path, "# my excludes\n*.swp\n/build").unwrap();
+
+    GitRepo::open(test_repo.path()).unwrap();
+    GitRepo::open(test_repo.path()).unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(&exclude_path).unwrap(),
+        "# my excludes\n*.swp\n/build\n.ubr\n"
+    );
+}
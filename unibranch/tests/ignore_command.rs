@@ -0,0 +1,166 @@
+use pretty_assertions::assert_eq;
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, ignore, publish, rebase, status},
+    git::GitRepo,
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+fn ubr_status(repo: &TestRepoWithRemote, args: &[&str]) -> String {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_ubr"))
+        .current_dir(repo.path())
+        .arg("status")
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn ignored_commit_is_not_published() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file(".envrc", "export DEBUG=1")
+        .commit_all("local tweak")
+        .create_file("File2", "Hello")
+        .commit_all("commit3");
+
+    ignore::execute(
+        ignore::Options {
+            commit_ref: "HEAD^".to_string(),
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+
+    let result = publish::plan(
+        publish::Options::default(),
+        git_repo(&repo),
+        &ubr::progress::NoProgress,
+    )
+    .unwrap();
+    assert_eq!(
+        result
+            .created
+            .branches
+            .iter()
+            .map(|b| b.meta_data.remote_branch_name.to_string())
+            .collect::<Vec<_>>(),
+        vec!["commit3".to_string()]
+    );
+    assert!(repo.ls_remote_heads("local-tweak").stdout.is_empty());
+
+    // The sync moved the ignored commit, it is still ignored
+    let commits = status::status(&git_repo(&repo)).unwrap();
+    assert_eq!(
+        commits
+            .iter()
+            .map(|c| (c.summary.as_str(), c.ignored))
+            .collect::<Vec<_>>(),
+        vec![("local tweak", true), ("commit3", false)]
+    );
+    assert!(!ubr_status(&repo, &[]).contains("local tweak"));
+    assert!(ubr_status(&repo, &["--all"]).contains("local tweak (ignored)"));
+
+    let err = create::execute(
+        create::Options {
+            commit_refs: vec!["HEAD^".to_string()],
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("is ignored"), "{}", err);
+
+    ignore::execute_unignore(
+        ignore::Options {
+            commit_ref: "HEAD^".to_string(),
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+    assert!(!status::status(&git_repo(&repo)).unwrap()[0].ignored);
+}
+
+#[test]
+fn ignore_trailer() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("WIP\n\nUbr-Ignore: true");
+
+    let commits = status::status(&git_repo(&repo)).unwrap();
+    assert!(commits[0].ignored);
+
+    let err = ignore::execute_unignore(
+        ignore::Options {
+            commit_ref: "HEAD".to_string(),
+        },
+        git_repo(&repo),
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("'Ubr-Ignore' trailer"), "{}", err);
+}
+
+#[test]
+fn ignore_keeps_other_notes() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2");
+    let status = repo
+        .run_command()
+        .args(["notes", "add", "-m", "Reviewed in person", "HEAD"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let options = || ignore::Options {
+        commit_ref: "HEAD".to_string(),
+    };
+
+    let err = ignore::execute(options(), git_repo(&repo)).unwrap_err();
+    assert!(err.to_string().contains("already has a note"), "{}", err);
+    ignore::execute_unignore(options(), git_repo(&repo)).unwrap();
+
+    assert_eq!(repo.find_note("HEAD"), "Reviewed in person\n");
+}
+
+#[test]
+fn rebase_does_not_add_a_note_to_trailer_ignored_commit() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file(".envrc", "export DEBUG=1")
+        .commit_all("WIP\n\nUbr-Ignore: true");
+    remote
+        .clone_repo()
+        .create_file("File2", "Upstream changes")
+        .commit_all("upstream")
+        .push();
+
+    rebase::plan(rebase::Options::default(), git_repo(&repo)).unwrap();
+
+    assert_eq!(repo.rev_parse("HEAD~1"), repo.rev_parse("origin/master"));
+    assert_eq!(repo.find_note("HEAD"), "");
+    let commits = status::status(&git_repo(&repo)).unwrap();
+    assert!(commits[0].ignored);
+}
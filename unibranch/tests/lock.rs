@@ -0,0 +1,103 @@
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, ignore, sync},
+    git::{lock, GitRepo},
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+/// The PID of a process that has exited
+fn dead_pid() -> u32 {
+    let mut child = std::process::Command::new("true").spawn().unwrap();
+    child.wait().unwrap();
+    child.id()
+}
+
+fn write_lock(repo: &TestRepoWithRemote, pid: u32) -> std::path::PathBuf {
+    let lock_file = repo.path().join(lock::LOCK_FILE);
+    std::fs::create_dir_all(lock_file.parent().unwrap()).unwrap();
+    std::fs::write(&lock_file, format!("{}\n", pid)).unwrap();
+    lock_file
+}
+
+fn init_repo(remote: &RemoteRepo) -> TestRepoWithRemote<'_> {
+    remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2")
+}
+
+#[test]
+fn second_command_fails_while_lock_is_held() {
+    let remote = RemoteRepo::new();
+    let repo = init_repo(&remote);
+
+    let first = git_repo(&repo);
+    let lock = first.lock().unwrap();
+    let err = sync::execute(sync::Options::default(), git_repo(&repo)).unwrap_err();
+    assert!(
+        err.to_string()
+            .starts_with("another ubr process is running"),
+        "{}",
+        err
+    );
+    let err = create::execute(create::Options::default(), git_repo(&repo)).unwrap_err();
+    assert!(
+        err.to_string()
+            .starts_with("another ubr process is running"),
+        "{}",
+        err
+    );
+    assert!(repo.ls_remote_heads("commit2").stdout.is_empty());
+    let err = ignore::execute(
+        ignore::Options {
+            commit_ref: "HEAD".to_string(),
+        },
+        git_repo(&repo),
+    )
+    .unwrap_err();
+    assert!(
+        err.to_string()
+            .starts_with("another ubr process is running"),
+        "{}",
+        err
+    );
+
+    drop(lock);
+    create::execute(create::Options::default(), git_repo(&repo)).unwrap();
+    assert!(!repo.path().join(lock::LOCK_FILE).exists());
+}
+
+#[test]
+fn stale_lock_is_taken_over() {
+    let remote = RemoteRepo::new();
+    let repo = init_repo(&remote);
+    let lock_file = write_lock(&repo, dead_pid());
+
+    create::execute(create::Options::default(), git_repo(&repo)).unwrap();
+
+    assert!(!repo.ls_remote_heads("commit2").stdout.is_empty());
+    assert!(!lock_file.exists());
+}
+
+#[test]
+fn lock_of_running_process_is_kept() {
+    let remote = RemoteRepo::new();
+    let repo = init_repo(&remote);
+    let lock_file = write_lock(&repo, std::process::id());
+
+    let err = create::execute(create::Options::default(), git_repo(&repo)).unwrap_err();
+
+    assert!(
+        err.to_string()
+            .starts_with("another ubr process is running"),
+        "{}",
+        err
+    );
+    assert!(lock_file.exists());
+}
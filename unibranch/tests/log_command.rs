@@ -0,0 +1,55 @@
+use pretty_assertions::assert_eq;
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, log},
+    git::GitRepo,
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+#[test]
+fn log_remote_branch() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Some more changes")
+        .commit_all("pr commit");
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+
+    remote_repo
+        .clone_repo()
+        .checkout("pr-commit")
+        .append_file("File1", "Remote fixes")
+        .commit_all("Review fixes")
+        .push();
+    let local_repo = local_repo.fetch();
+
+    let summaries = log::remote_log("HEAD", &git_repo(&local_repo))
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.summary)
+        .collect::<Vec<_>>();
+
+    assert_eq!(summaries, vec!["Review fixes", "pr commit"]);
+}
+
+#[test]
+fn log_untracked_commit() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Some more changes")
+        .commit_all("pr commit");
+
+    let err = log::remote_log("HEAD", &git_repo(&local_repo)).unwrap_err();
+
+    assert_eq!(err.to_string(), "'HEAD' is not tracked");
+}
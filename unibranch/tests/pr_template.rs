@@ -0,0 +1,43 @@
+use pretty_assertions::assert_eq;
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{forge, git::GitRepo};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+#[test]
+fn pr_body_with_template() {
+    let remote = RemoteRepo::new();
+    let repo = remote.clone_repo();
+    std::fs::create_dir(repo.path().join(".github")).unwrap();
+    let repo = repo
+        .create_file(
+            ".github/PULL_REQUEST_TEMPLATE.md",
+            "## Checklist\n- [ ] Tests",
+        )
+        .commit_all("Add PR template");
+
+    assert_eq!(
+        forge::pr_body(&git_repo(&repo), "\nFixes the frobnicator\n"),
+        "Fixes the frobnicator\n\n## Checklist\n- [ ] Tests"
+    );
+    assert_eq!(
+        forge::pr_body(&git_repo(&repo), ""),
+        "## Checklist\n- [ ] Tests"
+    );
+}
+
+#[test]
+fn pr_body_without_template() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1");
+
+    assert_eq!(
+        forge::pr_body(&git_repo(&repo), "\nFixes the frobnicator\n"),
+        "Fixes the frobnicator"
+    );
+}
@@ -0,0 +1,54 @@
+use pretty_assertions::assert_eq;
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, publish},
+    git::GitRepo,
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+#[test]
+fn publish_creates_untracked_and_syncs_tracked_commits() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2");
+    create::execute(create::Options::default(), git_repo(&repo)).unwrap();
+    let repo = repo
+        .append_file("File2", "Some PR review fixes")
+        .commit_all_amend()
+        .create_file("File3", "Hello again")
+        .commit_all("commit3");
+
+    let result = publish::plan(
+        publish::Options::default(),
+        git_repo(&repo),
+        &ubr::progress::NoProgress,
+    )
+    .unwrap();
+
+    assert_eq!(result.created.branches.len(), 1);
+    assert_eq!(
+        result.created.branches[0].meta_data.remote_branch_name,
+        "commit3"
+    );
+    assert_eq!(result.synced.pushed.len(), 1);
+    assert_eq!(result.synced.pushed[0].remote_branch_name, "commit2");
+    repo.assert_diff("origin/commit2", "HEAD^", "");
+    repo.assert_diff(
+        "origin/commit3",
+        "HEAD",
+        &String::from_utf8(repo.diff("origin/master", "HEAD^").stdout).unwrap(),
+    );
+    assert_eq!(
+        repo.find_commit_by_reference("refs/remotes/origin/commit2")
+            .message(),
+        Some("fixup! commit2")
+    );
+}
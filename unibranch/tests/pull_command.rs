@@ -28,8 +28,8 @@ fn update_commit_from_remote() {
     //Create a PR from local repo
     create::execute(
         create::Options {
-            commit_ref: Some("HEAD".to_string()),
-            force: false,
+            commit_refs: vec!["HEAD".to_string()],
+            ..Default::default()
         },
         git_repo(&local_repo),
     )
@@ -75,13 +75,13 @@ fn update_commit_from_remote() {
 
     local_repo.assert_note(
         "HEAD",
-        &CommitMetadata {
-            remote_branch_name: std::borrow::Cow::Owned("pr-commit".to_string()),
-            remote_commit: another_local_clone
+        CommitMetadata::new(
+            "pr-commit",
+            another_local_clone
                 .rev_parse("pr-commit")
                 .parse()
                 .expect("Not a valid object id"),
-        },
+        ),
     );
 }
 
@@ -99,8 +99,8 @@ fn update_commit_from_remote_with_local_changes() {
     //Create a PR from local repo
     create::execute(
         create::Options {
-            commit_ref: Some("HEAD".to_string()),
-            force: false,
+            commit_refs: vec!["HEAD".to_string()],
+            ..Default::default()
         },
         git_repo(&local_repo),
     )
@@ -175,13 +175,13 @@ fn update_commit_from_remote_with_local_changes() {
 
     local_repo.assert_note(
         "HEAD",
-        &CommitMetadata {
-            remote_branch_name: std::borrow::Cow::Owned("pr-commit".to_string()),
-            remote_commit: local_repo
+        CommitMetadata::new(
+            "pr-commit",
+            local_repo
                 .rev_parse("origin/pr-commit")
                 .parse()
                 .expect("Not a valid object id"),
-        },
+        ),
     );
 }
 
@@ -201,8 +201,8 @@ fn sync_multiple_commits() {
     //second pr
     create::execute(
         create::Options {
-            commit_ref: Some("HEAD".to_string()),
-            force: false,
+            commit_refs: vec!["HEAD".to_string()],
+            ..Default::default()
         },
         git_repo(&local_repo),
     )
@@ -211,8 +211,8 @@ fn sync_multiple_commits() {
     //first pr
     create::execute(
         create::Options {
-            commit_ref: Some("HEAD^".to_string()),
-            force: false,
+            commit_refs: vec!["HEAD^".to_string()],
+            ..Default::default()
         },
         git_repo(&local_repo),
     )
@@ -225,7 +225,7 @@ fn sync_multiple_commits() {
         .append_file("File1", "Remote fixes")
         .commit_all("Fixup")
         .push();
-    let _another_local_clone = another_local_clone
+    another_local_clone
         .checkout("second-pr")
         .append_file("File2", "Remote fixes")
         .commit_all("Fixup")
@@ -271,3 +271,341 @@ fn sync_multiple_commits() {
 
 #[test]
 fn test_update_after_rebase_of_main() {}
+
+#[test]
+fn no_fetch_uses_last_fetched_remote_state() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Some more changes")
+        .commit_all("pr commit");
+
+    create::execute(create::Options::default(), git_repo(&local_repo))
+        .expect("Unable to create initial PR");
+    let pushed_commit = local_repo.rev_parse("origin/pr-commit");
+
+    remote_repo
+        .clone_repo()
+        .checkout("pr-commit")
+        .append_file("File1", "Remote fixes")
+        .commit_all("Fixup")
+        .push();
+
+    // The push is based on the stale remote branch, so the lease rejects it
+    let err = sync::execute(
+        sync::Options {
+            no_fetch: true,
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    )
+    .expect_err("Pushing over the unfetched remote changes should fail");
+    assert!(format!("{:?}", err).contains("stale info"), "{:?}", err);
+
+    assert_eq!(local_repo.rev_parse("origin/pr-commit"), pushed_commit);
+    local_repo.assert_note(
+        "HEAD",
+        CommitMetadata::new(
+            "pr-commit",
+            pushed_commit.parse().expect("Not a valid object id"),
+        ),
+    );
+}
+
+#[test]
+fn refuse_to_sync_with_uncommitted_changes() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Some more changes")
+        .commit_all("pr commit");
+    create::execute(create::Options::default(), git_repo(&local_repo))
+        .expect("Unable to create initial PR");
+
+    let local_repo = local_repo.append_file("File1", "Not committed yet");
+    let err = sync::execute(sync::Options::default(), git_repo(&local_repo)).unwrap_err();
+
+    assert_eq!(
+        err.to_string(),
+        "working tree has uncommitted changes, commit them or run with '--force'"
+    );
+    assert_eq!(
+        std::fs::read_to_string(local_repo.path().join("File1")).unwrap(),
+        "Hello, World!\nSome more changes\nNot committed yet\n"
+    );
+}
+
+#[test]
+fn force_sync_keeps_uncommitted_changes() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "1\n2\n3\n4\n5")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello, World!")
+        .commit_all("pr commit");
+    create::execute(create::Options::default(), git_repo(&local_repo))
+        .expect("Unable to create initial PR");
+
+    remote_repo
+        .clone_repo()
+        .checkout("pr-commit")
+        .create_file("File1", "Remote fix\n2\n3\n4\n5")
+        .commit_all("Fixup")
+        .push();
+    let local_repo = local_repo.append_file("File1", "Not committed yet");
+
+    sync::execute(
+        sync::Options {
+            force: true,
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    assert_eq!(
+        std::fs::read_to_string(local_repo.path().join("File1")).unwrap(),
+        "Remote fix\n2\n3\n4\n5\nNot committed yet\n"
+    );
+    assert_eq!(
+        local_repo.rev_parse("HEAD:File1"),
+        local_repo.rev_parse("origin/pr-commit:File1")
+    );
+    let stash = local_repo
+        .run_command()
+        .stdout(Stdio::piped())
+        .args(["stash", "list"])
+        .output()
+        .unwrap();
+    assert!(stash.stdout.is_empty());
+}
+
+#[test]
+fn refuse_to_sync_rewritten_remote_branch() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Some more changes")
+        .commit_all("pr commit");
+    create::execute(create::Options::default(), git_repo(&local_repo))
+        .expect("Unable to create initial PR");
+
+    remote_repo
+        .clone_repo()
+        .checkout("pr-commit")
+        .append_file("File1", "Rewritten")
+        .commit_all_amend()
+        .force_push();
+
+    let err = sync::execute(sync::Options::default(), git_repo(&local_repo)).unwrap_err();
+
+    assert!(
+        err.to_string()
+            .starts_with("remote branch was rewritten; re-create or reset 'pr-commit'"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn notes_follow_rebased_tracked_commits() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Some more changes")
+        .commit_all("first pr")
+        .create_file("File2", "Unrelated feature")
+        .commit_all("second pr");
+
+    create::execute(
+        create::Options {
+            commit_refs: vec!["HEAD^".to_string(), "HEAD".to_string()],
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    )
+    .unwrap();
+    let second_pr_commit = local_repo.rev_parse("HEAD");
+
+    remote_repo
+        .clone_repo()
+        .checkout("first-pr")
+        .append_file("File1", "Remote fixes")
+        .commit_all("Fixup")
+        .push();
+
+    sync::execute(sync::Options::default(), git_repo(&local_repo)).unwrap();
+
+    // Only the first PR changed on the remote, but the second one has been rebased on top
+    // of it and must still be tracked.
+    assert_ne!(local_repo.rev_parse("HEAD"), second_pr_commit);
+    local_repo.assert_note(
+        "HEAD^",
+        CommitMetadata::new(
+            "first-pr",
+            local_repo.rev_parse("origin/first-pr").parse().unwrap(),
+        ),
+    );
+    local_repo.assert_note(
+        "HEAD",
+        CommitMetadata::new(
+            "second-pr",
+            local_repo.rev_parse("origin/second-pr").parse().unwrap(),
+        ),
+    );
+}
+
+#[test]
+fn update_a_single_branch() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "First PR")
+        .commit_all("commit2")
+        .create_file("File3", "Second PR")
+        .commit_all("commit3");
+    create::execute(
+        create::Options {
+            commit_refs: vec!["HEAD^".to_string(), "HEAD".to_string()],
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    )
+    .unwrap();
+    let commit3_head = local_repo.rev_parse("origin/commit3");
+
+    remote_repo
+        .clone_repo()
+        .checkout("commit2")
+        .append_file("File2", "Fixes to first PR")
+        .commit_all("Fixup 1")
+        .push()
+        .checkout("commit3")
+        .append_file("File3", "Fixes to second PR")
+        .commit_all("Fixup 2")
+        .push();
+
+    let err = sync::execute(
+        sync::Options {
+            branch: Some("no-such-branch".to_string()),
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "No tracked commit has the remote branch 'no-such-branch'"
+    );
+
+    sync::execute(
+        sync::Options {
+            branch: Some("commit2".to_string()),
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    local_repo.assert_workdir_is_clean();
+    assert_eq!(
+        std::fs::read_to_string(local_repo.path().join("File2")).unwrap(),
+        "First PR\nFixes to first PR\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(local_repo.path().join("File3")).unwrap(),
+        "Second PR\n"
+    );
+    // Only the selected branch was fetched
+    assert_eq!(local_repo.rev_parse("origin/commit3"), commit3_head);
+}
+
+#[test]
+fn failing_to_move_the_branch_leaves_it_unchanged() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Some more changes")
+        .commit_all("pr commit");
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+
+    remote_repo
+        .clone_repo()
+        .checkout("pr-commit")
+        .append_file("File1", "Remote fixes")
+        .commit_all("Fixup")
+        .push();
+
+    let old_head = local_repo.head();
+    // A stale lock makes moving 'master' fail after the new commit has been checked out
+    let lock = local_repo.path().join(".git/refs/heads/master.lock");
+    std::fs::write(&lock, "").unwrap();
+
+    let result = sync::execute(sync::Options::default(), git_repo(&local_repo));
+    std::fs::remove_file(&lock).unwrap();
+    assert!(result.is_err(), "sync should fail when 'master' is locked");
+
+    assert_eq!(local_repo.head_branch(), "master");
+    assert_eq!(local_repo.head(), old_head);
+    local_repo.assert_workdir_is_clean();
+}
+
+#[test]
+fn deep_stack_is_synced_and_created_with_a_warning() {
+    let remote_repo = RemoteRepo::new();
+    let mut local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "PR")
+        .commit_all("pr commit");
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    for i in 0..11 {
+        local_repo = local_repo
+            .create_file(format!("Stacked{}", i), "Hello")
+            .commit_all(&format!("commit {}", i));
+    }
+    let git = git_repo(&local_repo);
+    assert_eq!(git.current_stack_depth().unwrap(), 12);
+
+    let result = sync::plan(sync::Options::default(), git, &ubr::progress::NoProgress).unwrap();
+    assert_eq!(result.deep_stack, Some(12));
+    assert_eq!(result.pushed.len(), 1);
+    assert_eq!(
+        result.head.map(|oid| oid.to_string()),
+        Some(local_repo.rev_parse("HEAD"))
+    );
+
+    let result = create::plan(
+        create::Options {
+            commit_refs: vec!["HEAD~2..HEAD".to_string()],
+            ..Default::default()
+        },
+        &git_repo(&local_repo),
+        &ubr::progress::NoProgress,
+    )
+    .unwrap();
+    assert_eq!(result.deep_stack, Some(12));
+    assert_eq!(result.branches.len(), 2);
+}
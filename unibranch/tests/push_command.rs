@@ -0,0 +1,61 @@
+use std::process::Command;
+
+use pretty_assertions::assert_eq;
+use test_repo::RemoteRepo;
+
+fn ubr(dir: &std::path::Path, args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_ubr"))
+        .current_dir(dir)
+        .env("USER", "me")
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn remote_heads(remote: &RemoteRepo) -> String {
+    let output = Command::new("git")
+        .arg("ls-remote")
+        .arg("--heads")
+        .arg(remote.path())
+        .output()
+        .unwrap();
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn push_uses_the_global_remote_and_dry_run() {
+    let origin = RemoteRepo::new();
+    let upstream = RemoteRepo::new();
+    let repo = origin
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push();
+    assert!(repo
+        .run_command()
+        .args(["remote", "add", "upstream"])
+        .arg(upstream.path())
+        .status()
+        .unwrap()
+        .success());
+    let head = repo.rev_parse("HEAD");
+
+    let output = ubr(repo.path(), &["--dry-run", "--remote", "upstream", "push"]);
+    assert_eq!(
+        output,
+        format!(
+            "git push --no-verify --force-with-lease -- upstream {}:refs/heads/me/master\n",
+            head
+        )
+    );
+    assert_eq!(remote_heads(&upstream), "");
+
+    ubr(repo.path(), &["--remote", "upstream", "push"]);
+    assert_eq!(
+        remote_heads(&upstream),
+        format!("{}\trefs/heads/me/master\n", head)
+    );
+    assert!(repo.ls_remote_heads("me/master").stdout.is_empty());
+}
@@ -0,0 +1,131 @@
+use pretty_assertions::assert_eq;
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, rebase, ExitStatus},
+    git::{local_commit::RebaseConflict, GitRepo},
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+/// A stack of a tracked commit changing File1 and an untracked commit on top, with
+/// `upstream_file` written and pushed to master by someone else.
+fn setup_stack<'a>(
+    remote: &'a RemoteRepo,
+    upstream_file: &str,
+    upstream_content: &str,
+) -> TestRepoWithRemote<'a> {
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Local changes")
+        .commit_all("commit2");
+    create::execute(create::Options::default(), git_repo(&repo)).unwrap();
+    let repo = repo.create_file("File3", "Not a PR").commit_all("commit3");
+
+    remote
+        .clone_repo()
+        .create_file(upstream_file, upstream_content)
+        .commit_all("upstream")
+        .push();
+    repo
+}
+
+#[test]
+fn rebase_stack_onto_advanced_base() {
+    let remote = RemoteRepo::new();
+    let repo = setup_stack(&remote, "File2", "Upstream changes");
+
+    let result = rebase::plan(rebase::Options::default(), git_repo(&repo)).unwrap();
+
+    assert_eq!(
+        result.head.map(|id| id.to_string()),
+        Some(repo.rev_parse("HEAD"))
+    );
+    assert_eq!(repo.rev_parse("HEAD~2"), repo.rev_parse("origin/master"));
+    repo.assert_log(vec!["commit3\n", "commit2\n", "upstream\n", "commit1\n"]);
+    assert!(repo
+        .find_note("HEAD~1")
+        .starts_with("remote-branch: commit2\n"));
+    assert_eq!(result.needs_sync.len(), 1);
+    assert_eq!(result.needs_sync[0].remote_branch_name, "commit2");
+    assert_eq!(
+        result.needs_sync[0].commit.to_string(),
+        repo.rev_parse("HEAD~1")
+    );
+    repo.assert_workdir_is_clean();
+}
+
+#[test]
+fn continue_rebase_after_resolving_conflicts() {
+    let remote = RemoteRepo::new();
+    let repo = setup_stack(&remote, "File1", "Hello world!\nUpstream changes\n");
+    let commit2 = repo.rev_parse("HEAD~1");
+
+    let err = rebase::plan(rebase::Options::default(), git_repo(&repo)).unwrap_err();
+    assert_eq!(
+        err.downcast_ref::<RebaseConflict>()
+            .map(|c| c.commit.to_string()),
+        Some(commit2)
+    );
+    assert_eq!(ExitStatus::from(&err), ExitStatus::Conflict);
+    assert!(repo.path().join(".ubr/REBASE").exists());
+
+    let err = rebase::plan(
+        rebase::Options {
+            cont: true,
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Resolve all the conflicts and 'git add' the files first"
+    );
+
+    let repo = repo
+        .create_file("File1", "Hello world!\nUpstream changes\nLocal changes\n")
+        .add_all();
+    rebase::plan(
+        rebase::Options {
+            cont: true,
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+
+    assert!(!repo.path().join(".ubr/REBASE").exists());
+    assert_eq!(repo.head_branch(), "master");
+    assert_eq!(repo.rev_parse("HEAD~2"), repo.rev_parse("origin/master"));
+    repo.assert_log(vec!["commit3\n", "commit2\n", "upstream\n", "commit1\n"]);
+    assert!(repo
+        .find_note("HEAD~1")
+        .starts_with("remote-branch: commit2\n"));
+    repo.assert_workdir_is_clean();
+}
+
+#[test]
+fn abort_rebase() {
+    let remote = RemoteRepo::new();
+    let repo = setup_stack(&remote, "File1", "Hello world!\nUpstream changes\n");
+    let head = repo.rev_parse("HEAD");
+
+    assert!(rebase::plan(rebase::Options::default(), git_repo(&repo)).is_err());
+    rebase::plan(
+        rebase::Options {
+            abort: true,
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+
+    assert_eq!(repo.rev_parse("HEAD"), head);
+    assert_eq!(repo.head_branch(), "master");
+    repo.assert_workdir_is_clean();
+}
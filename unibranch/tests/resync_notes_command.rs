@@ -0,0 +1,123 @@
+use pretty_assertions::assert_eq;
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, resync_notes, sync},
+    config::Config,
+    git::{trailers, CommandOption, GitRepo},
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+fn init_repo(remote: &RemoteRepo) -> TestRepoWithRemote<'_> {
+    let local_repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2");
+
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+
+    assert!(local_repo
+        .run_command()
+        .arg("push")
+        .arg("origin")
+        .arg("--delete")
+        .arg("commit2")
+        .status()
+        .unwrap()
+        .success());
+    local_repo
+}
+
+#[test]
+fn report_dangling_note() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = init_repo(&remote_repo);
+
+    resync_notes::execute(resync_notes::Options::default(), git_repo(&local_repo)).unwrap();
+
+    assert!(!local_repo.find_note("HEAD").is_empty());
+}
+
+#[test]
+fn prune_dangling_note() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = init_repo(&remote_repo);
+
+    resync_notes::execute(resync_notes::Options { prune: true }, git_repo(&local_repo)).unwrap();
+
+    assert_eq!(local_repo.find_note("HEAD"), "");
+}
+
+#[test]
+fn keep_note_of_existing_branch() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2");
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+    let local_repo = local_repo.fetch();
+
+    resync_notes::execute(resync_notes::Options { prune: true }, git_repo(&local_repo)).unwrap();
+
+    assert!(!local_repo.find_note("HEAD").is_empty());
+}
+
+#[test]
+fn restore_note_from_trailers() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2");
+    let with_trailers = || {
+        let config = Config {
+            trailers: true,
+            ..Default::default()
+        };
+        GitRepo::open_with_config(local_repo.path(), CommandOption::Default, config).unwrap()
+    };
+    create::execute(create::Options::default(), with_trailers()).unwrap();
+
+    let remote_commit = local_repo.find_commit_by_reference("refs/remotes/origin/commit2");
+    assert_eq!(
+        remote_commit.message().unwrap(),
+        format!(
+            "commit2\n\nUbr-Source: {}\nUbr-Branch: commit2\n",
+            local_repo.head()
+        )
+    );
+    let (source, meta_data) = trailers::parse(&remote_commit).unwrap();
+    assert_eq!(source, local_repo.head());
+    assert_eq!(meta_data.remote_branch_name, "commit2");
+    assert_eq!(meta_data.remote_commit, remote_commit.id());
+
+    // The trailers don't count as a change of the message
+    sync::execute(sync::Options::default(), with_trailers()).unwrap();
+    assert_eq!(
+        local_repo.rev_parse("origin/commit2").trim(),
+        remote_commit.id().to_string()
+    );
+
+    let note = local_repo.find_note("HEAD");
+    assert!(local_repo
+        .run_command()
+        .args(["notes", "remove", "HEAD"])
+        .status()
+        .unwrap()
+        .success());
+    resync_notes::execute(resync_notes::Options::default(), with_trailers()).unwrap();
+
+    assert_eq!(local_repo.find_note("HEAD"), note);
+}
@@ -0,0 +1,112 @@
+use pretty_assertions::assert_eq;
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, set_base},
+    git::GitRepo,
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+fn repo_diff(repo: &TestRepoWithRemote, rev1: &str, rev2: &str) -> String {
+    String::from_utf8(repo.diff(rev1, rev2).stdout).unwrap()
+}
+
+fn set_base(repo: &TestRepoWithRemote, commit_ref: &str, new_base_ref: &str) -> anyhow::Result<()> {
+    set_base::plan(
+        set_base::Options {
+            commit_ref: commit_ref.to_string(),
+            new_base_ref: new_base_ref.to_string(),
+        },
+        &git_repo(repo),
+    )
+    .map(|_| ())
+}
+
+#[test]
+fn stack_pr_on_another_and_back() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2")
+        .create_file("File3", "Hello again")
+        .commit_all("commit3");
+    create::execute(
+        create::Options {
+            commit_refs: vec!["HEAD~1".to_string(), "HEAD".to_string()],
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+    assert_eq!(
+        repo.rev_parse("origin/commit3^"),
+        repo.rev_parse("origin/master")
+    );
+
+    set_base(&repo, "HEAD", "HEAD~1").unwrap();
+    assert_eq!(
+        repo.rev_parse("origin/commit3^"),
+        repo.rev_parse("origin/commit2")
+    );
+    repo.assert_diff(
+        "origin/commit2",
+        "origin/commit3",
+        &repo_diff(&repo, "HEAD^", "HEAD"),
+    );
+    assert_eq!(
+        repo.find_note("HEAD"),
+        format!(
+            "remote-branch: commit3\nremote-commit: {}\nbase-branch: commit2\n",
+            repo.rev_parse("origin/commit3")
+        )
+    );
+
+    set_base(&repo, "HEAD", "master").unwrap();
+    assert_eq!(
+        repo.rev_parse("origin/commit3^"),
+        repo.rev_parse("origin/master")
+    );
+    assert_eq!(
+        repo.find_note("HEAD"),
+        format!(
+            "remote-branch: commit3\nremote-commit: {}\n",
+            repo.rev_parse("origin/commit3")
+        )
+    );
+}
+
+#[test]
+fn new_base_must_be_below_in_the_stack() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2")
+        .create_file("File3", "Hello again")
+        .commit_all("commit3");
+    create::execute(
+        create::Options {
+            commit_refs: vec!["HEAD~1".to_string(), "HEAD".to_string()],
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+    let remote_head = repo.rev_parse("origin/commit2");
+
+    let err = set_base(&repo, "HEAD~1", "HEAD").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "'HEAD' is neither a tracked commit below 'HEAD~1' nor the base branch 'master'"
+    );
+    assert_eq!(repo.rev_parse("origin/commit2"), remote_head);
+}
@@ -0,0 +1,88 @@
+use pretty_assertions::assert_eq;
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, squash, sync},
+    git::GitRepo,
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+fn create_pr(repo: &TestRepoWithRemote, rev: &str) {
+    create::execute(
+        create::Options {
+            commit_refs: vec![rev.to_string()],
+            ..Default::default()
+        },
+        git_repo(repo),
+    )
+    .unwrap();
+}
+
+#[test]
+fn squash_tracked_commit_into_parent() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2")
+        .create_file("File3", "Hello again")
+        .commit_all("commit3")
+        .create_file("File4", "Not tracked")
+        .commit_all("commit4");
+    create_pr(&repo, "HEAD~2");
+    create_pr(&repo, "HEAD~1");
+    let original_head_tree = repo.rev_parse("HEAD^{tree}");
+
+    let result = squash::plan(
+        squash::Options {
+            commit_ref: "HEAD~1".to_string(),
+        },
+        &git_repo(&repo),
+    )
+    .unwrap();
+
+    assert_eq!(result.head, repo.head());
+    assert_eq!(
+        result.deleted.map(|m| m.remote_branch_name.into_owned()),
+        Some("commit3".to_string())
+    );
+    repo.assert_log(vec!["commit4\n", "commit2\n", "commit1\n"]);
+    assert_eq!(repo.rev_parse("HEAD^{tree}"), original_head_tree);
+    assert!(repo.ls_remote_heads("commit3").stdout.is_empty());
+    assert!(repo
+        .find_note("HEAD~1")
+        .starts_with("remote-branch: commit2\n"));
+
+    sync::execute(sync::Options::default(), git_repo(&repo)).unwrap();
+    repo.assert_diff("origin/commit2", "HEAD~1", "");
+}
+
+#[test]
+fn cannot_squash_first_commit_in_stack() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2");
+
+    let err = squash::execute(
+        squash::Options {
+            commit_ref: "HEAD".to_string(),
+        },
+        git_repo(&repo),
+    )
+    .unwrap_err();
+
+    assert_eq!(
+        err.to_string(),
+        "'HEAD' has no parent in the stack to squash into"
+    );
+}
@@ -0,0 +1,207 @@
+use pretty_assertions::assert_eq;
+use test_repo::{RemoteRepo, TestRepoWithRemote};
+use ubr::{
+    commands::{create, status, sync},
+    git::{
+        local_commit::{MainCommit, PrState},
+        GitRepo,
+    },
+};
+
+fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
+    GitRepo::open(value.path()).unwrap()
+}
+
+#[test]
+fn merged_pr_is_shown_and_not_synced() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2");
+    create::execute(create::Options::default(), git_repo(&repo)).unwrap();
+    let repo = repo
+        .append_file("File2", "Some more changes")
+        .commit_all_amend()
+        .create_file("File3", "Hello again")
+        .commit_all("commit3");
+    let remote_head = repo.rev_parse("origin/commit2");
+
+    let git_repo = git_repo(&repo);
+    let MainCommit::Tracked(tracked) = git_repo.find_unpushed_commit("HEAD^").unwrap() else {
+        panic!("HEAD^ should be tracked");
+    };
+    assert_eq!(tracked.meta_data().pr_state, PrState::Unknown);
+    let mut meta_data = tracked.meta_data().clone();
+    meta_data.pr_state = PrState::Merged;
+    git_repo
+        .save_meta_data(tracked.as_commit(), &meta_data)
+        .unwrap();
+    drop(tracked);
+
+    let commits = status::status(&git_repo).unwrap();
+    assert_eq!(
+        commits
+            .iter()
+            .map(|c| (
+                c.summary.as_str(),
+                c.remote_branch_name.as_deref(),
+                c.pr_state
+            ))
+            .collect::<Vec<_>>(),
+        vec![
+            ("commit2", Some("commit2"), PrState::Merged),
+            ("commit3", None, PrState::Unknown),
+        ]
+    );
+
+    let result = sync::plan(
+        sync::Options::default(),
+        git_repo,
+        &ubr::progress::NoProgress,
+    )
+    .unwrap();
+    assert!(result.pushed.is_empty());
+    assert_eq!(result.merged.len(), 1);
+    assert_eq!(result.merged[0].remote_branch_name, "commit2");
+    assert_eq!(repo.rev_parse("origin/commit2"), remote_head);
+}
+
+#[test]
+fn remote_branch_ahead_of_the_local_branch_head() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2");
+    create::execute(create::Options::default(), git_repo(&repo)).unwrap();
+    let commits = status::status(&git_repo(&repo)).unwrap();
+    assert_eq!(commits[0].ahead_behind, Some((0, 0)));
+
+    remote
+        .clone_repo()
+        .checkout("commit2")
+        .append_file("File2", "Remote fix")
+        .commit_all("fix1")
+        .append_file("File2", "Another remote fix")
+        .commit_all("fix2")
+        .push();
+    assert!(repo
+        .run_command()
+        .args(["fetch", "origin"])
+        .status()
+        .unwrap()
+        .success());
+
+    let commits = status::status(&git_repo(&repo)).unwrap();
+    assert_eq!(commits[0].ahead_behind, Some((0, 2)));
+
+    // A branch that is gone from the remote
+    assert!(repo
+        .run_command()
+        .args(["branch", "-d", "-r", "origin/commit2"])
+        .status()
+        .unwrap()
+        .success());
+    let commits = status::status(&git_repo(&repo)).unwrap();
+    assert_eq!(commits[0].remote_branch_name.as_deref(), Some("commit2"));
+    assert_eq!(commits[0].ahead_behind, None);
+}
+
+#[test]
+fn count_tracked_and_untracked_commits() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2")
+        .create_file("File3", "Hello again")
+        .commit_all("commit3");
+    create::execute(
+        create::Options {
+            commit_refs: vec!["HEAD^".to_string(), "HEAD".to_string()],
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+    let repo = repo
+        .append_file("File3", "Not pushed yet")
+        .commit_all_amend()
+        .create_file("File4", "Untracked")
+        .commit_all("commit4");
+
+    let counts = status::counts(&git_repo(&repo), false).unwrap();
+    assert_eq!(
+        counts,
+        status::StatusCounts {
+            total: 3,
+            tracked: 2,
+            untracked: 1,
+            in_sync: 1,
+            needs_sync: 1,
+        }
+    );
+    assert_eq!(
+        serde_json::to_string(&counts).unwrap(),
+        r#"{"total":3,"tracked":2,"untracked":1,"in-sync":1,"needs-sync":1}"#
+    );
+}
+
+#[test]
+fn status_colors_the_sync_state_but_not_formatted_output() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2");
+    create::execute(create::Options::default(), git_repo(&repo)).unwrap();
+    let repo = repo
+        .create_file("File3", "Hello again")
+        .commit_all("commit3");
+    create::execute(create::Options::default(), git_repo(&repo)).unwrap();
+    let repo = repo
+        .append_file("File3", "Some more changes")
+        .commit_all_amend()
+        .create_file("File4", "Not tracked")
+        .commit_all("commit4");
+    let ubr = |args: &[&str]| {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_ubr"))
+            .current_dir(repo.path())
+            .args(["--color", "always", "status"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    let plain = ubr(&[]);
+    assert!(plain.contains("(\x1b[32mcommit2\x1b[0m"), "{}", plain);
+    assert!(plain.contains("(\x1b[33mcommit3\x1b[0m"), "{}", plain);
+    assert!(
+        plain.contains("commit4 (\x1b[31muntracked\x1b[0m)"),
+        "{}",
+        plain
+    );
+
+    let formatted = ubr(&["--format", "%s %b %t"]);
+    assert_eq!(
+        formatted,
+        "commit2 commit2 unknown\ncommit3 commit3 unknown\ncommit4  unknown\n"
+    );
+    let json = ubr(&["--count", "--json"]);
+    assert!(!json.contains('\x1b'), "{}", json);
+}
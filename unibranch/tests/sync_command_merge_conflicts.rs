@@ -1,9 +1,13 @@
+use std::{cell::RefCell, path::PathBuf};
+
 use indoc::{formatdoc, indoc};
 use pretty_assertions::assert_eq;
 use test_repo::{RemoteRepo, TestRepoWithRemote};
 use ubr::{
-    commands::{create, sync},
-    git::{GitRepo, SyncState},
+    commands::{create, sync, ExitStatus},
+    config::{Config, StrategyOption},
+    git::{local_commit::MergeConflict, CommandOption, GitRepo, SyncState},
+    progress::ProgressReporter,
 };
 
 fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
@@ -24,8 +28,8 @@ fn test_merge_conflict_from_remote() {
     //Create a PR from local repo
     create::execute(
         create::Options {
-            commit_ref: Some("HEAD".to_string()),
-            force: false,
+            commit_refs: vec!["HEAD".to_string()],
+            ..Default::default()
         },
         git_repo(&local_repo),
     )
@@ -92,7 +96,14 @@ fn test_merge_conflict_from_remote() {
         );
     }
 
-    sync::execute(sync::Options { cont: true }, git_repo(&local_repo)).expect("Should succeed");
+    sync::execute(
+        sync::Options {
+            cont: true,
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    )
+    .expect("Should succeed");
 
     local_repo.assert_diff(
         "master^",
@@ -131,8 +142,8 @@ fn test_merge_conflict_in_the_middle_of_sync() {
     //Create a PR from local repo
     create::execute(
         create::Options {
-            commit_ref: Some("HEAD".to_string()),
-            force: false,
+            commit_refs: vec!["HEAD".to_string()],
+            ..Default::default()
         },
         git_repo(&local_repo),
     )
@@ -202,7 +213,14 @@ fn test_merge_conflict_in_the_middle_of_sync() {
         );
     }
 
-    sync::execute(sync::Options { cont: true }, git_repo(&local_repo)).expect("Should succeed");
+    sync::execute(
+        sync::Options {
+            cont: true,
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    )
+    .expect("Should succeed");
 
     local_repo.assert_diff(
         "master^",
@@ -236,8 +254,8 @@ fn test_merge_conflict_in_the_middle_of_sync_2() {
     //Create a PR from local repo
     create::execute(
         create::Options {
-            commit_ref: Some("HEAD".to_string()),
-            force: false,
+            commit_refs: vec!["HEAD".to_string()],
+            ..Default::default()
         },
         git_repo(&local_repo),
     )
@@ -311,7 +329,14 @@ fn test_merge_conflict_in_the_middle_of_sync_2() {
         );
     }
 
-    sync::execute(sync::Options { cont: true }, git_repo(&local_repo)).expect("Should succeed");
+    sync::execute(
+        sync::Options {
+            cont: true,
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    )
+    .expect("Should succeed");
 
     local_repo.assert_diff(
         "master^^",
@@ -342,3 +367,513 @@ fn test_merge_conflict_in_the_middle_of_sync_2() {
         "},
     );
 }
+
+#[derive(Default)]
+struct RecordingProgress {
+    conflicts: RefCell<Vec<PathBuf>>,
+}
+
+impl ProgressReporter for RecordingProgress {
+    fn on_conflict(&self, paths: &[PathBuf]) {
+        self.conflicts.borrow_mut().extend_from_slice(paths);
+    }
+}
+
+#[test]
+fn test_merge_conflict_is_reported_to_progress() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(
+        create::Options {
+            commit_refs: vec!["HEAD".to_string()],
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    )
+    .expect("Unable to create initial PR");
+
+    {
+        remote_repo
+            .clone_repo()
+            .checkout("feature-1")
+            .append_file("File1", "Some remote fixes")
+            .commit_all("Fixup")
+            .push();
+    }
+
+    let local_repo = local_repo
+        .append_file("File1", "Some local fixes")
+        .commit_all_amend();
+
+    let progress = RecordingProgress::default();
+    let result =
+        sync::execute_with_progress(sync::Options::default(), git_repo(&local_repo), &progress);
+    assert!(result.is_err());
+
+    assert_eq!(
+        progress.conflicts.into_inner(),
+        vec![PathBuf::from("File1")]
+    );
+}
+
+fn setup_conflict(remote_repo: &RemoteRepo) -> TestRepoWithRemote<'_> {
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+
+    create::execute(
+        create::Options {
+            commit_refs: vec!["HEAD".to_string()],
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    )
+    .expect("Unable to create initial PR");
+
+    {
+        remote_repo
+            .clone_repo()
+            .checkout("feature-1")
+            .append_file("File1", "Some remote fixes")
+            .commit_all("Fixup")
+            .push();
+    }
+
+    local_repo
+        .append_file("File1", "Some local fixes")
+        .commit_all_amend()
+}
+
+#[test]
+fn test_continue_and_abort_are_mutually_exclusive() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_conflict(&remote_repo);
+
+    assert!(sync::execute(sync::Options::default(), git_repo(&local_repo)).is_err());
+
+    let result = sync::execute(
+        sync::Options {
+            cont: true,
+            abort: true,
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    );
+    assert_eq!(
+        format!("{}", result.unwrap_err()),
+        "'--continue' and '--abort' cannot be used together"
+    );
+    assert!(local_repo.path().join(".ubr/SYNC_MERGE_HEAD").exists());
+}
+
+#[test]
+fn test_continue_without_ongoing_sync() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_conflict(&remote_repo);
+
+    let result = sync::execute(
+        sync::Options {
+            cont: true,
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    );
+    assert_eq!(format!("{}", result.unwrap_err()), "No sync in progress");
+}
+
+#[test]
+fn test_abort_sync() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_conflict(&remote_repo);
+    let original_head = local_repo.head();
+
+    assert!(sync::execute(sync::Options::default(), git_repo(&local_repo)).is_err());
+
+    sync::execute(
+        sync::Options {
+            abort: true,
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    )
+    .expect("Abort should succeed");
+
+    assert_eq!(local_repo.head_branch(), "master");
+    assert_eq!(local_repo.head(), original_head);
+    assert!(!local_repo.path().join(".ubr/SYNC_MERGE_HEAD").exists());
+    local_repo.assert_workdir_is_clean();
+}
+
+#[test]
+fn test_merge_conflict_exit_status() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_conflict(&remote_repo);
+
+    let err = sync::execute(sync::Options::default(), git_repo(&local_repo)).unwrap_err();
+
+    assert_eq!(
+        err.downcast_ref::<MergeConflict>(),
+        Some(&MergeConflict {
+            local: local_repo.head(),
+            remote: local_repo.rev_parse("origin/feature-1").parse().unwrap(),
+        })
+    );
+    assert_eq!(ExitStatus::from(&err), ExitStatus::Conflict);
+}
+
+#[test]
+fn test_continue_when_commit_is_no_longer_tracked() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_conflict(&remote_repo);
+    let main_commit = local_repo.head();
+
+    assert!(sync::execute(sync::Options::default(), git_repo(&local_repo)).is_err());
+    assert!(local_repo
+        .run_command()
+        .args(["notes", "remove", &main_commit.to_string()])
+        .status()
+        .unwrap()
+        .success());
+
+    let err = sync::execute(
+        sync::Options {
+            cont: true,
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    )
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "Commit {} is no longer tracked. Run 'ubr sync --abort' and then 'ubr create' to track it again",
+            main_commit
+        )
+    );
+
+    sync::execute(
+        sync::Options {
+            abort: true,
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    )
+    .expect("Abort should still be possible");
+    assert_eq!(local_repo.head(), main_commit);
+}
+
+#[test]
+fn test_keep_going_past_merge_conflict() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1")
+        .create_file("File2", "Another feature")
+        .commit_all("feature 2");
+
+    create::execute(
+        create::Options {
+            commit_refs: vec!["HEAD^".to_string(), "HEAD".to_string()],
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    let another_local_clone = remote_repo
+        .clone_repo()
+        .checkout("feature-1")
+        .append_file("File1", "Some remote fixes")
+        .commit_all("Fixup")
+        .push();
+    let feature_1_remote = another_local_clone.rev_parse("origin/feature-1");
+    another_local_clone
+        .checkout("feature-2")
+        .append_file("File2", "Some remote fixes")
+        .commit_all("Fixup")
+        .push();
+
+    let feature_1 = local_repo.find_commit(1).id();
+    let feature_2 = local_repo.head();
+    let local_repo = local_repo
+        .append_file("File1", "Some local fixes")
+        .commit_all_fixup(feature_1);
+
+    let result = sync::plan(
+        sync::Options {
+            keep_going: true,
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+        &ubr::progress::NoProgress,
+    )
+    .unwrap();
+
+    assert_eq!(result.skipped.len(), 1);
+    assert_eq!(result.skipped[0].summary, "feature 1");
+    assert_eq!(result.skipped[0].remote_branch_name, "feature-1");
+    assert_eq!(result.skipped[0].commit, local_repo.find_commit(1).id());
+    assert_eq!(
+        result
+            .pushed
+            .iter()
+            .map(|m| m.remote_branch_name.as_ref())
+            .collect::<Vec<_>>(),
+        vec!["feature-2"]
+    );
+
+    assert!(!local_repo.path().join(".ubr/SYNC_MERGE_HEAD").exists());
+    assert_eq!(local_repo.head_branch(), "master");
+    local_repo.assert_workdir_is_clean();
+    assert_ne!(local_repo.head(), feature_2);
+    assert_eq!(local_repo.rev_parse("origin/feature-1"), feature_1_remote);
+    assert!(local_repo
+        .find_note("HEAD^")
+        .starts_with("remote-branch: feature-1\n"));
+    local_repo.assert_diff(
+        "master^",
+        "master",
+        indoc! {"
+            diff --git a/File2 b/File2
+            new file mode 100644
+            index 0000000..e415184
+            --- /dev/null
+            +++ b/File2
+            @@ -0,0 +1,2 @@
+            +Another feature
+            +Some remote fixes
+        "},
+    );
+}
+
+#[test]
+fn test_merge_conflict_markers_are_written_to_the_working_tree() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_conflict(&remote_repo);
+
+    assert!(sync::execute(sync::Options::default(), git_repo(&local_repo)).is_err());
+
+    let content = std::fs::read_to_string(local_repo.path().join("File1")).unwrap();
+    assert_eq!(
+        content,
+        indoc! {"
+            Hello, World!
+            Starting on a new feature
+            <<<<<<< local
+            Some local fixes
+            =======
+            Some remote fixes
+            >>>>>>> origin/feature-1
+        "}
+    );
+}
+
+#[test]
+fn test_strategy_option_theirs_resolves_conflicts() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_conflict(&remote_repo);
+
+    let config = Config {
+        strategy_options: vec![StrategyOption::Theirs],
+        ..Default::default()
+    };
+    sync::execute(
+        sync::Options::default(),
+        GitRepo::open_with_config(local_repo.path(), CommandOption::Default, config).unwrap(),
+    )
+    .unwrap();
+
+    assert!(!local_repo.path().join(".ubr/SYNC_MERGE_HEAD").exists());
+    local_repo.assert_diff(
+        "origin/feature-1^",
+        "origin/feature-1",
+        indoc! {"
+            diff --git a/File1 b/File1
+            index 1fa81e5..0062f65 100644
+            --- a/File1
+            +++ b/File1
+            @@ -1,3 +1,3 @@
+             Hello, World!
+             Starting on a new feature
+            -Some local fixes
+            +Some remote fixes
+        "},
+    );
+}
+
+#[test]
+fn test_mergetool_is_run_on_the_conflicting_files() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = setup_conflict(&remote_repo);
+    let log_dir = tempfile::tempdir().unwrap();
+    let log = log_dir.path().join("mergetool.log");
+    for (key, value) in [
+        ("merge.tool", "stub".to_string()),
+        (
+            "mergetool.stub.cmd",
+            format!(
+                "echo \"$MERGED\" >> '{}' && cp \"$REMOTE\" \"$MERGED\"",
+                log.display()
+            ),
+        ),
+        ("mergetool.stub.trustExitCode", "true".to_string()),
+        ("mergetool.prompt", "false".to_string()),
+        ("mergetool.keepBackup", "false".to_string()),
+    ] {
+        assert!(local_repo
+            .run_command()
+            .args(["config", key, &value])
+            .status()
+            .unwrap()
+            .success());
+    }
+
+    let err = sync::execute(
+        sync::Options {
+            mergetool: true,
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    )
+    .unwrap_err();
+
+    assert!(err.downcast_ref::<MergeConflict>().is_some());
+    assert_eq!(std::fs::read_to_string(&log).unwrap(), "File1\n");
+    // The tool resolved the conflict, so the sync can be continued
+    sync::execute(
+        sync::Options {
+            cont: true,
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    )
+    .unwrap();
+    local_repo.assert_workdir_is_clean();
+}
+
+#[test]
+fn continue_with_unresolved_conflicts() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1");
+    create::execute(create::Options::default(), git_repo(&local_repo)).unwrap();
+
+    remote_repo
+        .clone_repo()
+        .checkout("feature-1")
+        .append_file("File1", "Some remote fixes")
+        .commit_all("Fixup")
+        .push();
+    let local_repo = local_repo
+        .append_file("File1", "Some local fixes")
+        .commit_all_amend();
+    assert!(sync::execute(sync::Options::default(), git_repo(&local_repo)).is_err());
+    let head = local_repo.head();
+
+    let cont = |repo: &TestRepoWithRemote| {
+        sync::execute(
+            sync::Options {
+                cont: true,
+                ..Default::default()
+            },
+            git_repo(repo),
+        )
+    };
+    let err = cont(&local_repo).expect_err("The conflicts haven't been resolved");
+    assert_eq!(
+        err.to_string(),
+        "unresolved conflicts remain in File1. Resolve them, 'git add' the files and run \
+         'ubr sync --continue' again"
+    );
+    assert!(local_repo.path().join(".ubr/SYNC_MERGE_HEAD").exists());
+    assert_eq!(local_repo.head(), head);
+
+    let local_repo = local_repo
+        .create_file("File1", "Hello, World!\nStarting on a new feature")
+        .add_all();
+    cont(&local_repo).expect("The conflicts have been resolved");
+    assert!(!local_repo.path().join(".ubr/SYNC_MERGE_HEAD").exists());
+}
+
+#[test]
+fn continue_merge_conflict_in_stacked_pr() {
+    let remote_repo = RemoteRepo::new();
+    let local_repo = remote_repo
+        .clone_repo()
+        .create_file("File1", "Hello, World!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Starting on a new feature")
+        .commit_all("feature 1")
+        .create_file("File2", "Starting on another feature")
+        .commit_all("feature 2");
+    create::execute(
+        create::Options {
+            commit_refs: vec!["origin/master..HEAD".to_string()],
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    )
+    .unwrap();
+    remote_repo
+        .clone_repo()
+        .checkout("feature-2")
+        .append_file("File2", "Some remote fixes")
+        .commit_all("Fixup")
+        .push();
+    let local_repo = local_repo
+        .append_file("File2", "Some local fixes")
+        .commit_all_amend();
+
+    assert!(sync::execute(sync::Options::default(), git_repo(&local_repo)).is_err());
+    let local_repo = local_repo
+        .create_file(
+            "File2",
+            "Starting on another feature\nSome local/remote fixes",
+        )
+        .add_all();
+    sync::execute(
+        sync::Options {
+            cont: true,
+            ..Default::default()
+        },
+        git_repo(&local_repo),
+    )
+    .unwrap();
+
+    // The local commit still only has the changes of its own PR
+    local_repo.assert_diff(
+        "master^",
+        "master",
+        indoc! {"
+        diff --git a/File2 b/File2
+        new file mode 100644
+        index 0000000..4e3b6cd
+        --- /dev/null
+        +++ b/File2
+        @@ -0,0 +1,2 @@
+        +Starting on another feature
+        +Some local/remote fixes
+        "},
+    );
+}
@@ -3,7 +3,8 @@ use indoc::indoc;
 use pretty_assertions::assert_eq;
 use ubr::{
     commands::{create, sync},
-    git::GitRepo,
+    config::Config,
+    git::{CommandOption, GitRepo},
 };
 
 use test_repo::{RemoteRepo, TestRepoWithRemote};
@@ -14,8 +15,8 @@ fn git_repo(value: &TestRepoWithRemote) -> GitRepo {
 
 fn push_options(commit_ref: Option<Oid>) -> create::Options {
     create::Options {
-        commit_ref: commit_ref.map(|id| format!("{}", id)),
-        force: false,
+        commit_refs: commit_ref.map(|id| format!("{}", id)).into_iter().collect(),
+        ..Default::default()
     }
 }
 
@@ -56,7 +57,14 @@ fn test_update_a_diff() {
         .append_file("File1", "Some PR review fixes")
         .commit_all_amend();
 
-    sync::execute(sync::Options::default(), git_repo(&repo)).unwrap();
+    let result = sync::plan(
+        sync::Options::default(),
+        git_repo(&repo),
+        &ubr::progress::NoProgress,
+    )
+    .unwrap();
+    assert_eq!(result.head, Some(repo.head()));
+    let remote_commit = result.pushed[0].remote_commit;
 
     //Verify the diff now.
     let actual_diff = String::from_utf8(repo.diff("origin/commit2", "origin/master").stdout)
@@ -88,13 +96,14 @@ fn test_update_a_diff() {
     assert_eq!(actual_diff, expected_diff);
 
     assert_eq!(
-        repo.find_note("head"),
+        repo.find_note("HEAD"),
         indoc! {"
             remote-branch: commit2
             remote-commit: {}
         "}
-        .replace("{}", &repo.rev_parse("origin/commit2"))
+        .replace("{}", &remote_commit.to_string())
     );
+    assert_eq!(repo.rev_parse("origin/commit2"), remote_commit.to_string());
 }
 
 #[test]
@@ -200,7 +209,7 @@ fn test_update_a_commit_and_modify_the_commit_message() {
     create::execute(push_options(Some(head)), git_repo(&repo)).unwrap();
 
     assert_eq!(
-        repo.find_note("head"),
+        repo.find_note("HEAD"),
         indoc! {"
             remote-branch: commit2
             remote-commit: {}
@@ -213,7 +222,7 @@ fn test_update_a_commit_and_modify_the_commit_message() {
         .commit_all_amend_with_message("a new message");
 
     assert_eq!(
-        repo.find_note("head"),
+        repo.find_note("HEAD"),
         indoc! {"
             remote-branch: commit2
             remote-commit: {}
@@ -225,7 +234,7 @@ fn test_update_a_commit_and_modify_the_commit_message() {
 
     //Note is still the same
     assert_eq!(
-        repo.find_note("head"),
+        repo.find_note("HEAD"),
         indoc! {"
             remote-branch: commit2
             remote-commit: {}
@@ -247,3 +256,564 @@ fn test_update_a_commit_and_modify_the_commit_message() {
     "};
     assert_eq!(actual_diff, expected_diff);
 }
+
+#[test]
+fn test_update_only_the_commit_message() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+
+    create::execute(push_options(None), git_repo(&repo)).unwrap();
+
+    let repo = repo.commit_all_amend_with_message("a new message");
+
+    sync::execute(sync::Options::default(), git_repo(&repo)).unwrap();
+
+    assert_eq!(
+        repo.find_commit_by_reference("refs/remotes/origin/commit2")
+            .message(),
+        Some("a new message\n")
+    );
+    assert_eq!(
+        repo.rev_parse("origin/commit2^"),
+        repo.rev_parse("origin/master")
+    );
+    repo.assert_diff("origin/commit2", "master", "");
+}
+
+#[test]
+fn test_update_message_below_fixup_commits() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+
+    create::execute(push_options(None), git_repo(&repo)).unwrap();
+
+    let repo = repo
+        .append_file("File1", "Some PR review fixes")
+        .commit_all_amend();
+    sync::execute(sync::Options::default(), git_repo(&repo)).unwrap();
+
+    let repo = repo.commit_all_amend_with_message("a new message");
+    sync::execute(sync::Options::default(), git_repo(&repo)).unwrap();
+
+    let fixup = repo.find_commit_by_reference("refs/remotes/origin/commit2");
+    assert_eq!(fixup.message(), Some("fixup! a new message"));
+    assert_eq!(fixup.parent(0).unwrap().message(), Some("a new message\n"));
+    repo.assert_diff("origin/commit2", "master", "");
+}
+
+#[test]
+fn test_plan_returns_pushed_branches() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+    create::execute(push_options(None), git_repo(&repo)).unwrap();
+
+    let repo = repo
+        .append_file("File1", "Some PR review fixes")
+        .commit_all_amend();
+    let result = sync::plan(
+        sync::Options::default(),
+        git_repo(&repo),
+        &ubr::progress::NoProgress,
+    )
+    .unwrap();
+
+    assert_eq!(result.pushed.len(), 1);
+    assert_eq!(result.pushed[0].remote_branch_name, "commit2");
+    assert_eq!(
+        result.pushed[0].remote_commit.to_string(),
+        repo.rev_parse("origin/commit2")
+    );
+    assert_eq!(
+        result.head.map(|oid| oid.to_string()),
+        Some(repo.rev_parse("HEAD"))
+    );
+}
+
+#[test]
+fn test_fixup_commits_are_autosquashed() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+    create::execute(push_options(None), git_repo(&repo)).unwrap();
+
+    let repo = repo
+        .append_file("File1", "Some PR review fixes")
+        .commit_all_amend();
+    sync::execute(sync::Options::default(), git_repo(&repo)).unwrap();
+    assert_eq!(
+        repo.find_commit_by_reference("refs/remotes/origin/commit2")
+            .message(),
+        Some("fixup! commit2")
+    );
+
+    let pr_clone = remote
+        .clone_repo()
+        .checkout("commit2")
+        .rebase_autosquash("origin/master");
+    assert_eq!(
+        pr_clone.rev_parse("HEAD^"),
+        pr_clone.rev_parse("origin/master")
+    );
+    pr_clone.assert_log(vec!["commit2\n"]);
+    pr_clone.assert_diff("HEAD", "origin/commit2", "");
+}
+
+#[test]
+fn test_keep_committer_date() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+    create::execute(push_options(None), git_repo(&repo)).unwrap();
+
+    // An untracked commit on top, which is rewritten by every sync
+    let repo = repo.create_file("File2", "Not part of any PR").add_all();
+    assert!(repo
+        .run_command()
+        .env("GIT_AUTHOR_DATE", "@1600000000 +0000")
+        .env("GIT_COMMITTER_DATE", "@1700000000 +0000")
+        .args(["commit", "-m", "commit3"])
+        .status()
+        .unwrap()
+        .success());
+
+    let config = Config {
+        keep_committer_date: true,
+        ..Default::default()
+    };
+    sync::execute(
+        sync::Options::default(),
+        GitRepo::open_with_config(repo.path(), CommandOption::Default, config).unwrap(),
+    )
+    .unwrap();
+    let head = repo.find_commit(0);
+    assert_eq!(head.author().when().seconds(), 1600000000);
+    assert_eq!(head.committer().when().seconds(), 1700000000);
+
+    // Without the option the author is used as committer
+    sync::execute(sync::Options::default(), git_repo(&repo)).unwrap();
+    let head = repo.find_commit(0);
+    assert_eq!(head.author().when().seconds(), 1600000000);
+    assert_eq!(head.committer().when().seconds(), 1600000000);
+}
+
+#[test]
+fn test_committer_date_is_author_date() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+    create::execute(push_options(None), git_repo(&repo)).unwrap();
+
+    let repo = repo
+        .append_file("File1", "Some PR review fixes")
+        .commit_all_amend()
+        .create_file("File2", "Not part of any PR")
+        .add_all();
+    assert!(repo
+        .run_command()
+        .env("GIT_AUTHOR_DATE", "@1600000000 +0000")
+        .env("GIT_COMMITTER_DATE", "@1700000000 +0000")
+        .args(["commit", "-m", "commit3"])
+        .status()
+        .unwrap()
+        .success());
+
+    // Takes precedence over keeping the committer date
+    let config = Config {
+        keep_committer_date: true,
+        committer_date_is_author_date: true,
+        ..Default::default()
+    };
+    sync::execute(
+        sync::Options::default(),
+        GitRepo::open_with_config(repo.path(), CommandOption::Default, config).unwrap(),
+    )
+    .unwrap();
+    let head = repo.find_commit(0);
+    assert_eq!(head.author().when().seconds(), 1600000000);
+    assert_eq!(head.committer().when().seconds(), 1600000000);
+
+    let fixup = repo.find_commit_by_reference("refs/remotes/origin/commit2");
+    assert_eq!(fixup.message(), Some("fixup! commit2"));
+    assert_eq!(
+        fixup.committer().when().seconds(),
+        fixup.author().when().seconds()
+    );
+}
+
+#[test]
+fn test_update_only_the_file_mode() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("script.sh", "echo Hello")
+        .commit_all("commit2");
+    create::execute(push_options(None), git_repo(&repo)).unwrap();
+
+    std::fs::set_permissions(
+        repo.path().join("script.sh"),
+        std::fs::Permissions::from_mode(0o755),
+    )
+    .unwrap();
+    let repo = repo.commit_all_amend();
+    sync::execute(sync::Options::default(), git_repo(&repo)).unwrap();
+
+    repo.assert_diff("origin/commit2", "master", "");
+    assert_eq!(
+        String::from_utf8(repo.diff("origin/commit2^", "origin/commit2").stdout).unwrap(),
+        indoc! {"
+            diff --git a/script.sh b/script.sh
+            old mode 100644
+            new mode 100755
+        "}
+    );
+}
+
+#[test]
+fn test_output_patches() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+    create::execute(push_options(None), git_repo(&repo)).unwrap();
+    let repo = repo
+        .append_file("File1", "Some PR review fixes")
+        .commit_all_amend();
+
+    let output = tempfile::tempdir().unwrap();
+    sync::execute(
+        sync::Options {
+            output: Some(output.path().to_path_buf()),
+            ..Default::default()
+        },
+        GitRepo::open_with_remote(repo.path(), CommandOption::DryRun).unwrap(),
+    )
+    .unwrap();
+
+    let patch = std::fs::read_to_string(output.path().join("commit2.patch")).unwrap();
+    assert!(patch.contains("Subject: [PATCH] commit2\n"), "{}", patch);
+    let other = remote.clone_repo();
+    assert!(other
+        .run_command()
+        .arg("am")
+        .arg(output.path().join("commit2.patch"))
+        .status()
+        .unwrap()
+        .success());
+    assert_eq!(other.rev_parse("HEAD^"), other.rev_parse("origin/master"));
+    other.assert_log(vec!["commit2\n", "commit1\n"]);
+    assert_eq!(
+        other.find_commit(0).tree_id(),
+        repo.find_commit(0).tree_id()
+    );
+}
+
+#[test]
+fn test_only_message_changes() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2")
+        .create_file("File3", "Hello again")
+        .commit_all("commit3");
+    create::execute(
+        create::Options {
+            commit_refs: vec!["HEAD^".to_string(), "HEAD".to_string()],
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+    let local_head = repo.head();
+    let trees = ["origin/commit2^{tree}", "origin/commit3^{tree}"].map(|r| repo.rev_parse(r));
+
+    // Reword both commits, keeping their notes
+    assert!(repo
+        .run_command()
+        .args(["rebase", "-i", "origin/master"])
+        .env("GIT_SEQUENCE_EDITOR", "sed -i s/^pick/reword/")
+        .env("GIT_EDITOR", "sed -i s/commit/reworded/")
+        .status()
+        .unwrap()
+        .success());
+    assert_ne!(repo.head(), local_head);
+    let local_head = repo.head();
+
+    let result = sync::plan(
+        sync::Options {
+            only_message_changes: true,
+            ..Default::default()
+        },
+        git_repo(&repo),
+        &ubr::progress::NoProgress,
+    )
+    .unwrap();
+
+    assert_eq!(
+        result
+            .pushed
+            .iter()
+            .map(|m| m.remote_branch_name.as_ref())
+            .collect::<Vec<_>>(),
+        vec!["commit2", "commit3"]
+    );
+    assert_eq!(result.head, Some(local_head));
+    assert_eq!(repo.head(), local_head);
+    for (branch, (tree, message)) in ["commit2", "commit3"]
+        .iter()
+        .zip(trees.iter().zip(["reworded2\n", "reworded3\n"]))
+    {
+        assert_eq!(
+            &repo.rev_parse(&format!("origin/{}^{{tree}}", branch)),
+            tree
+        );
+        assert_eq!(
+            repo.find_commit_by_reference(&format!("refs/remotes/origin/{}", branch))
+                .message(),
+            Some(message)
+        );
+    }
+}
+
+#[test]
+fn test_sync_only_a_pathspec() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .append_file("File1", "Hello again")
+        .commit_all("commit2");
+    create::execute(push_options(None), git_repo(&repo)).unwrap();
+    let remote_file1 = repo.rev_parse("origin/commit2:File1");
+
+    let repo = repo
+        .append_file("File1", "Not yet")
+        .append_file("File2", "Review fix")
+        .commit_all_amend();
+    let local_head = repo.head();
+    let result = sync::plan(
+        sync::Options {
+            pathspec: vec!["File2".to_string()],
+            ..Default::default()
+        },
+        git_repo(&repo),
+        &ubr::progress::NoProgress,
+    )
+    .unwrap();
+
+    assert_eq!(result.pushed.len(), 1);
+    assert_eq!(repo.head(), local_head);
+    assert_eq!(repo.rev_parse("origin/commit2:File1"), remote_file1);
+    assert_eq!(
+        repo.rev_parse("origin/commit2:File2"),
+        repo.rev_parse("HEAD:File2")
+    );
+
+    // The rest is pushed by the next sync
+    sync::execute(sync::Options::default(), git_repo(&repo)).unwrap();
+    assert_eq!(
+        repo.rev_parse("origin/commit2^{tree}"),
+        repo.rev_parse("HEAD^{tree}")
+    );
+}
+
+#[test]
+fn test_sync_with_the_same_change_on_the_remote() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2")
+        .create_file("File3", "Hello again")
+        .commit_all("commit3");
+    create::execute(
+        create::Options {
+            commit_refs: vec!["HEAD^".to_string(), "HEAD".to_string()],
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+    let commit3_head = repo.rev_parse("origin/commit3");
+
+    // Fix the same thing locally and on the remote
+    remote
+        .clone_repo()
+        .checkout("commit2")
+        .append_file("File2", "Review fix")
+        .commit_all("Fixup")
+        .push();
+    let remote_head = remote.clone_repo().checkout("commit2").head();
+    let repo = repo
+        .checkout("HEAD^")
+        .append_file("File2", "Review fix")
+        .commit_all_amend();
+    let fixed_commit2 = repo.head();
+    let repo = repo.checkout("master");
+    assert!(repo
+        .run_command()
+        .args(["rebase", "--onto", &fixed_commit2.to_string(), "HEAD^"])
+        .status()
+        .unwrap()
+        .success());
+
+    let result = sync::plan(
+        sync::Options::default(),
+        git_repo(&repo),
+        &ubr::progress::NoProgress,
+    )
+    .unwrap();
+
+    assert_eq!(result.pushed.len(), 2);
+    assert_eq!(repo.rev_parse("origin/commit2"), remote_head.to_string());
+    assert_eq!(repo.rev_parse("origin/commit3"), commit3_head);
+    assert_eq!(
+        repo.rev_parse("HEAD^^{tree}"),
+        repo.rev_parse("origin/commit2^{tree}")
+    );
+}
+
+#[test]
+fn test_sync_signs_the_merge_commit() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .append_file("File1", "Another Hello, World!")
+        .commit_all("commit2");
+    create::execute(push_options(None), git_repo(&repo)).unwrap();
+    let repo = repo.create_file("File2", "Local fix").commit_all_amend();
+    remote
+        .clone_repo()
+        .checkout("commit2")
+        .create_file("File3", "Remote fix")
+        .commit_all("Remote fix")
+        .push();
+
+    let key_dir = tempfile::tempdir().unwrap();
+    let key = key_dir.path().join("id_ed25519");
+    assert!(std::process::Command::new("ssh-keygen")
+        .args(["-q", "-t", "ed25519", "-N", "", "-f"])
+        .arg(&key)
+        .status()
+        .unwrap()
+        .success());
+    for (name, value) in [
+        ("gpg.format", "ssh"),
+        ("user.signingkey", key.to_str().unwrap()),
+    ] {
+        assert!(repo
+            .run_command()
+            .args(["config", name, value])
+            .status()
+            .unwrap()
+            .success());
+    }
+    let config = Config {
+        sign: true,
+        ..Default::default()
+    };
+    sync::execute(
+        sync::Options::default(),
+        GitRepo::open_with_config(repo.path(), CommandOption::Default, config).unwrap(),
+    )
+    .unwrap();
+
+    let merge = repo.find_commit_by_reference("refs/remotes/origin/commit2");
+    assert_eq!(merge.message(), Some("Merge"));
+    let git2_repo = git2::Repository::open(repo.path()).unwrap();
+    let (signature, _) = git2_repo
+        .extract_signature(&merge.id(), None)
+        .expect("Merge is not signed");
+    assert!(signature
+        .as_str()
+        .unwrap()
+        .starts_with("-----BEGIN SSH SIGNATURE-----"));
+}
+
+#[test]
+fn test_sync_pathspec_keeps_the_local_commit() {
+    let remote = RemoteRepo::new();
+    let repo = remote
+        .clone_repo()
+        .create_file("File1", "Hello world!")
+        .commit_all("commit1")
+        .push()
+        .create_file("File2", "Hello")
+        .commit_all("commit2");
+    create::execute(push_options(None), git_repo(&repo)).unwrap();
+
+    // A rewrite takes the committer time from the author time, so make the amended
+    // commit's committer time differ from it
+    std::thread::sleep(std::time::Duration::from_secs(1));
+    let repo = repo.append_file("File2", "Review fix").commit_all_amend();
+    let local_head = repo.head();
+
+    sync::execute(
+        sync::Options {
+            pathspec: vec!["File2".to_string()],
+            ..Default::default()
+        },
+        git_repo(&repo),
+    )
+    .unwrap();
+
+    assert_eq!(repo.head(), local_head);
+    assert_eq!(
+        repo.rev_parse("origin/commit2:File2"),
+        repo.rev_parse("HEAD:File2")
+    );
+}